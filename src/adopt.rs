@@ -0,0 +1,92 @@
+//! `runall adopt`: folds a process that was started outside of runall into
+//! a running session. The session runs a copy of runall in its place
+//! (`runall adopt --watch`) that exits once the process is gone and passes
+//! the signals it gets on to it, so the process shows in the status table,
+//! counts for `--kill-others` and is stopped together with the session. Its
+//! output can't be captured. Unix only.
+
+#[derive(clap::Args)]
+pub struct AdoptArgs {
+    /// The process to adopt.
+    #[clap(long)]
+    pub pid: u32,
+
+    /// Its name in the session.
+    #[clap(long)]
+    pub name: String,
+
+    /// The session to add it to. Defaults to the name of the current
+    /// directory, like for the session itself.
+    #[clap(long)]
+    pub session: Option<String>,
+
+    /// Watch the process, as done for the session.
+    #[clap(long, hide = true)]
+    pub watch: bool,
+}
+
+/// Asks the session to adopt the process and prints the answer. Returns
+/// whether it did.
+pub fn adopt(args: AdoptArgs) -> bool {
+    if args.watch {
+        watch(args.pid);
+    }
+    if !cfg!(unix) {
+        panic!("adopting processes is only supported on unix");
+    }
+    let session = args.session.unwrap_or_else(crate::logs::default_session);
+    let request = format!("adopt {}={}", args.name, args.pid);
+    let answer = crate::ctl::send(&session, &request).unwrap_or_else(|err| panic!("{err}"));
+    if answer.starts_with("error: ") {
+        eprint!("{answer}");
+        false
+    } else {
+        print!("{answer}");
+        true
+    }
+}
+
+/// Whether a process with that pid exists, also if it belongs to another
+/// user.
+#[cfg(unix)]
+pub fn alive(pid: u32) -> bool {
+    use nix::{errno::Errno, sys::signal::kill, unistd::Pid};
+    !matches!(kill(Pid::from_raw(pid as i32), None), Err(Errno::ESRCH))
+}
+
+#[cfg(not(unix))]
+pub fn alive(_pid: u32) -> bool {
+    false
+}
+
+/// `--watch`: waits until the process is gone, passing on the signals that
+/// end or address it.
+#[cfg(unix)]
+fn watch(pid: u32) -> ! {
+    use nix::{
+        sys::signal::{kill, Signal},
+        unistd::Pid,
+    };
+    use signal_hook::consts::{SIGHUP, SIGINT, SIGQUIT, SIGTERM, SIGUSR1, SIGUSR2};
+
+    let mut signals =
+        signal_hook::iterator::Signals::new([SIGHUP, SIGINT, SIGQUIT, SIGTERM, SIGUSR1, SIGUSR2])
+            .expect("set signal handlers");
+    std::thread::spawn(move || {
+        for signal in signals.forever() {
+            if let Ok(signal) = Signal::try_from(signal) {
+                let _ = kill(Pid::from_raw(pid as i32), signal);
+            }
+        }
+    });
+    while alive(pid) {
+        std::thread::sleep(std::time::Duration::from_millis(250));
+    }
+    eprintln!("process {pid} exited");
+    std::process::exit(0);
+}
+
+#[cfg(not(unix))]
+fn watch(_pid: u32) -> ! {
+    panic!("adopting processes is only supported on unix");
+}
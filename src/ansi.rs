@@ -0,0 +1,61 @@
+//! Handling of terminal escape sequences in child output.
+
+/// Tracks an OSC-8 hyperlink that is still open at the end of a line, so that
+/// it can be closed before the line break (keeping the next prefix unlinked)
+/// and reopened after the prefix.
+#[derive(Default)]
+pub struct OscState {
+    open_link: Option<String>,
+}
+
+const ST: &str = "\x1b\\";
+
+/// Filters OSC sequences (`ESC ] ... BEL` or `ESC ] ... ESC \`) of one line.
+/// OSC-8 hyperlinks are kept if `keep_links` is set and reduced to their text
+/// otherwise. All other OSC sequences (window titles, clipboard access,
+/// palette changes) are removed since they would affect runall's terminal as
+/// a whole.
+pub fn filter_osc(line: &str, keep_links: bool, state: &mut OscState) -> String {
+    let mut out = String::with_capacity(line.len());
+    if keep_links {
+        if let Some(link) = &state.open_link {
+            out.push_str(link);
+        }
+    }
+
+    let mut rest = line;
+    while let Some(start) = rest.find("\x1b]") {
+        out.push_str(&rest[..start]);
+        let body_start = &rest[start + 2..];
+        let (body, after) = match (body_start.find('\x07'), body_start.find(ST)) {
+            (Some(bel), Some(st)) if bel < st => (&body_start[..bel], &body_start[bel + 1..]),
+            (_, Some(st)) => (&body_start[..st], &body_start[st + 2..]),
+            (Some(bel), None) => (&body_start[..bel], &body_start[bel + 1..]),
+            // unterminated, drop the rest of the sequence
+            (None, None) => (body_start, ""),
+        };
+        if let Some(link) = body.strip_prefix("8;") {
+            let uri = link.split_once(';').map_or("", |(_, uri)| uri);
+            let sequence = format!("\x1b]{body}{ST}");
+            if keep_links {
+                out.push_str(&sequence);
+            }
+            state.open_link = (!uri.is_empty()).then_some(sequence);
+        }
+        rest = after;
+    }
+    out.push_str(rest);
+
+    if keep_links && state.open_link.is_some() {
+        let newline = out.ends_with('\n');
+        if newline {
+            out.pop();
+        }
+        out.push_str("\x1b]8;;");
+        out.push_str(ST);
+        if newline {
+            out.push('\n');
+        }
+    }
+    out
+}
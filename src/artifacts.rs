@@ -0,0 +1,53 @@
+//! `--artifacts`: files a command leaves behind, like test reports or
+//! coverage, are copied into the run directory under the command's name
+//! once it finished. Commands running in parallel can write the same paths
+//! without overwriting what the other collected.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// `<run>/artifacts/<name>`.
+pub fn dir(run: &Path, name: &str) -> PathBuf {
+    let name = crate::logs::file_name(name);
+    run.join("artifacts").join(name.trim_end_matches(".log"))
+}
+
+/// Copies the files and directories matching `patterns` below `cwd` to
+/// `dest`, keeping their paths relative to `cwd`. Returns how many files
+/// were copied.
+pub fn collect(patterns: &[String], cwd: &Path, dest: &Path) -> Result<usize, String> {
+    let mut copied = 0;
+    for pattern in patterns {
+        let full = cwd.join(pattern.trim_end_matches('/'));
+        let paths = glob::glob(&full.to_string_lossy())
+            .map_err(|err| format!("invalid pattern {pattern:?}: {err}"))?;
+        for path in paths.flatten() {
+            // paths outside of the directory of the command keep their name
+            let relative = match path.strip_prefix(cwd) {
+                Ok(relative) if !relative.as_os_str().is_empty() => relative.to_path_buf(),
+                _ => PathBuf::from(path.file_name().unwrap_or(path.as_os_str())),
+            };
+            copied += copy(&path, &dest.join(relative))
+                .map_err(|err| format!("cannot copy {}: {err}", path.display()))?;
+        }
+    }
+    Ok(copied)
+}
+
+fn copy(from: &Path, to: &Path) -> std::io::Result<usize> {
+    if from.is_dir() {
+        let mut copied = 0;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copied += copy(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        return Ok(copied);
+    }
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::copy(from, to)?;
+    Ok(1)
+}
@@ -0,0 +1,285 @@
+//! `--events`: publishes lifecycle events to a NATS or MQTT broker. Both
+//! protocols are simple enough to be spoken directly over TCP. Only plain
+//! connections and fire-and-forget delivery (MQTT QoS 0) are supported;
+//! events that happen while the broker is unreachable are dropped.
+
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+/// Idle connections are pinged this often.
+const KEEPALIVE: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, Debug)]
+enum Protocol {
+    Nats,
+    Mqtt,
+}
+
+/// Where to publish, parsed from `nats://[user:pass@]host[:port][/subject]`
+/// or `mqtt://[user:pass@]host[:port][/topic]`.
+#[derive(Clone, Debug)]
+pub struct Broker {
+    protocol: Protocol,
+    addr: String,
+    credentials: Option<(String, String)>,
+    subject: String,
+}
+
+pub fn parse(url: &str) -> Result<Broker, String> {
+    let (scheme, rest) = url
+        .split_once("://")
+        .ok_or_else(|| format!("expected nats://... or mqtt://..., got {url:?}"))?;
+    let (protocol, port, default_subject) = match scheme {
+        "nats" => (Protocol::Nats, 4222, "runall.events"),
+        "mqtt" => (Protocol::Mqtt, 1883, "runall/events"),
+        _ => {
+            return Err(format!(
+                "unsupported scheme {scheme:?}, expected nats or mqtt"
+            ))
+        }
+    };
+    let (authority, subject) = match rest.split_once('/') {
+        Some((authority, subject)) if !subject.is_empty() => (authority, subject),
+        Some((authority, _)) => (authority, default_subject),
+        None => (rest, default_subject),
+    };
+    let (credentials, host) = match authority.rsplit_once('@') {
+        Some((user, host)) => {
+            let (user, pass) = user.split_once(':').unwrap_or((user, ""));
+            (Some((user.to_string(), pass.to_string())), host)
+        }
+        None => (None, authority),
+    };
+    if host.is_empty() {
+        return Err(format!("missing host in {url:?}"));
+    }
+    let has_port = host
+        .rsplit_once(':')
+        .is_some_and(|(_, p)| !p.ends_with(']'));
+    Ok(Broker {
+        protocol,
+        addr: if has_port {
+            host.to_string()
+        } else {
+            format!("{host}:{port}")
+        },
+        credentials,
+        subject: subject.to_string(),
+    })
+}
+
+/// MQTT's variable length encoding of the remaining packet length.
+fn mqtt_length(mut len: usize, out: &mut Vec<u8>) {
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        out.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+}
+
+fn mqtt_string(s: &str, out: &mut Vec<u8>) {
+    out.extend((s.len() as u16).to_be_bytes());
+    out.extend(s.as_bytes());
+}
+
+fn mqtt_packet(kind: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![kind];
+    mqtt_length(body.len(), &mut packet);
+    packet.extend(body);
+    packet
+}
+
+struct Connection {
+    protocol: Protocol,
+    /// Shared with the reader thread, which answers NATS pings.
+    writer: Arc<Mutex<TcpStream>>,
+}
+
+impl Connection {
+    fn open(broker: &Broker) -> io::Result<Self> {
+        let addr = broker
+            .addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address"))?;
+        let stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?;
+        stream.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+
+        match broker.protocol {
+            Protocol::Nats => {
+                let mut info = String::new();
+                reader.read_line(&mut info)?;
+                if !info.starts_with("INFO") {
+                    return Err(io::Error::other(format!("unexpected greeting {info:?}")));
+                }
+                let mut options = serde_json::json!({
+                    "verbose": false,
+                    "pedantic": false,
+                    "name": "runall",
+                    "lang": "rust",
+                    "version": env!("CARGO_PKG_VERSION"),
+                });
+                if let Some((user, pass)) = &broker.credentials {
+                    options["user"] = user.as_str().into();
+                    options["pass"] = pass.as_str().into();
+                }
+                write!(writer, "CONNECT {options}\r\n")?;
+            }
+            Protocol::Mqtt => {
+                let mut body = Vec::new();
+                mqtt_string("MQTT", &mut body);
+                // protocol level 3.1.1, clean session, keepalive in seconds
+                let mut flags = 0x02;
+                if broker.credentials.is_some() {
+                    flags |= 0xc0;
+                }
+                body.extend([4, flags]);
+                body.extend((KEEPALIVE.as_secs() as u16 * 2).to_be_bytes());
+                mqtt_string(&format!("runall-{}", std::process::id()), &mut body);
+                if let Some((user, pass)) = &broker.credentials {
+                    mqtt_string(user, &mut body);
+                    mqtt_string(pass, &mut body);
+                }
+                writer.write_all(&mqtt_packet(0x10, &body))?;
+                let mut connack = [0; 4];
+                reader.read_exact(&mut connack)?;
+                if connack[0] != 0x20 || connack[3] != 0 {
+                    return Err(io::Error::other(format!(
+                        "connection refused (code {})",
+                        connack[3]
+                    )));
+                }
+            }
+        }
+
+        let writer = Arc::new(Mutex::new(writer));
+        let protocol = broker.protocol;
+        let pong = writer.clone();
+        reader.get_ref().set_read_timeout(None)?;
+        thread::spawn(move || match protocol {
+            Protocol::Nats => {
+                for line in reader.lines().map_while(Result::ok) {
+                    if line.starts_with("PING") {
+                        let _ = pong.lock().expect("lock").write_all(b"PONG\r\n");
+                    } else if let Some(err) = line.strip_prefix("-ERR") {
+                        eprintln!("events: nats error:{err}");
+                    }
+                }
+            }
+            // nothing but ping responses arrive for QoS 0
+            Protocol::Mqtt => {
+                let _ = io::copy(&mut reader, &mut io::sink());
+            }
+        });
+
+        Ok(Self { protocol, writer })
+    }
+
+    fn publish(&self, subject: &str, payload: &str) -> io::Result<()> {
+        let mut writer = self.writer.lock().expect("lock");
+        match self.protocol {
+            Protocol::Nats => write!(writer, "PUB {subject} {}\r\n{payload}\r\n", payload.len()),
+            Protocol::Mqtt => {
+                let mut body = Vec::new();
+                mqtt_string(subject, &mut body);
+                body.extend(payload.as_bytes());
+                writer.write_all(&mqtt_packet(0x30, &body))
+            }
+        }
+    }
+
+    fn ping(&self) -> io::Result<()> {
+        let mut writer = self.writer.lock().expect("lock");
+        match self.protocol {
+            Protocol::Nats => writer.write_all(b"PING\r\n"),
+            Protocol::Mqtt => writer.write_all(&[0xc0, 0]),
+        }
+    }
+}
+
+fn publish_loop(broker: Broker, events: flume::Receiver<String>) {
+    let mut connection = None::<Connection>;
+    let mut retry_at = Instant::now();
+    let mut warned = false;
+    loop {
+        let event = match events.recv_timeout(KEEPALIVE) {
+            Ok(event) => Some(event),
+            Err(flume::RecvTimeoutError::Timeout) => None,
+            Err(flume::RecvTimeoutError::Disconnected) => break,
+        };
+        if connection.is_none() && Instant::now() >= retry_at {
+            match Connection::open(&broker) {
+                Ok(c) => {
+                    connection = Some(c);
+                    warned = false;
+                }
+                Err(err) => {
+                    if !warned {
+                        eprintln!("events: cannot connect to {}: {err}", broker.addr);
+                        warned = true;
+                    }
+                    retry_at = Instant::now() + RETRY_INTERVAL;
+                }
+            }
+        }
+        let Some(c) = &connection else {
+            continue;
+        };
+        let result = match &event {
+            Some(event) => c.publish(&broker.subject, event),
+            None => c.ping(),
+        };
+        if let Err(err) = result {
+            eprintln!("events: lost connection to {}: {err}", broker.addr);
+            connection = None;
+        }
+    }
+}
+
+/// Publishes from a background thread so that a slow broker never blocks
+/// the runner. Dropping it waits for the queued events to be sent.
+pub struct Publisher {
+    events: Option<flume::Sender<String>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Publisher {
+    pub fn start(broker: Broker) -> Self {
+        let (events, rx) = flume::unbounded();
+        let thread = thread::spawn(move || publish_loop(broker, rx));
+        Self {
+            events: Some(events),
+            thread: Some(thread),
+        }
+    }
+
+    pub fn publish(&self, event: &serde_json::Value) {
+        if let Some(events) = &self.events {
+            let _ = events.send(event.to_string());
+        }
+    }
+}
+
+impl Drop for Publisher {
+    fn drop(&mut self) {
+        self.events.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,59 @@
+//! `--open`: opens a URL in the default browser once a command is ready.
+
+use std::{
+    process,
+    time::{Duration, Instant},
+};
+
+use crate::ports;
+
+/// How long to wait for a port to show up for `{port}`.
+const PORT_TIMEOUT: Duration = Duration::from_secs(30);
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Opens `url` in the background. `{port}` is replaced by the first TCP port
+/// the process tree of `pid` listens on.
+pub fn open_when_listening(prefix: String, url: String, pid: u32) {
+    std::thread::spawn(move || {
+        let url = if url.contains("{port}") {
+            let started = Instant::now();
+            let port = loop {
+                if let Some(&port) = ports::listening(pid).first() {
+                    break port;
+                }
+                if started.elapsed() > PORT_TIMEOUT {
+                    notice!("{prefix} not opening {url}, no listening port found");
+                    return;
+                }
+                std::thread::sleep(POLL_INTERVAL);
+            };
+            url.replace("{port}", &port.to_string())
+        } else {
+            url
+        };
+        notice!("{prefix} opening {url}");
+        if let Err(err) = open(&url) {
+            notice!("{prefix} cannot open browser: {err}");
+        }
+    });
+}
+
+/// Opens `url`, or a path in the file manager.
+pub fn open(url: &str) -> std::io::Result<()> {
+    #[cfg(target_os = "macos")]
+    let mut command = process::Command::new("open");
+    #[cfg(windows)]
+    let mut command = {
+        let mut command = process::Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    };
+    #[cfg(all(unix, not(target_os = "macos")))]
+    let mut command = process::Command::new("xdg-open");
+    command
+        .arg(url)
+        .stdin(process::Stdio::null())
+        .stdout(process::Stdio::null())
+        .stderr(process::Stdio::null());
+    crate::reaper::status(&mut command).map(|_| ())
+}
@@ -0,0 +1,64 @@
+//! `--session-mem-limit` and `--session-cpu-limit`: a budget for all
+//! processes of the session together. The usage of every command and its
+//! descendants is polled from /proc (see [`crate::usage`]), so this is only
+//! supported on Linux.
+//!
+//! When the session uses too much memory, the command with the lowest
+//! `--priority` (the biggest one among equals) is restarted, which is the
+//! only way to get the memory back. When it uses too much CPU, that command
+//! is paused instead and continued once there is room for it again.
+
+use std::time::{Duration, Instant};
+
+use crate::usage::Meter;
+
+/// How long to wait after restarting a command before acting again, so
+/// that its memory is returned and its successor has started.
+pub const SETTLE: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Default)]
+pub struct Limits {
+    /// Bytes of resident memory.
+    pub mem: Option<u64>,
+    /// Percent of one CPU core, e.g. 600 for six cores.
+    pub cpu: Option<f64>,
+}
+
+/// The state of the budget between polls.
+pub struct Budget {
+    pub limits: Limits,
+    /// When the usage is polled next.
+    pub next: Instant,
+    /// Don't act before that, see [`SETTLE`].
+    pub settled: Instant,
+    pub meter: Meter,
+    /// Jobs paused for the CPU budget and the CPU they used before.
+    pub paused: Vec<(usize, f64)>,
+}
+
+impl Budget {
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            next: Instant::now(),
+            settled: Instant::now(),
+            meter: Meter::default(),
+            paused: Vec::new(),
+        }
+    }
+
+    /// Forgets job `idx`, which stopped running.
+    pub fn forget(&mut self, idx: usize) {
+        self.meter.forget(idx);
+        self.paused.retain(|(paused, _)| *paused != idx);
+    }
+}
+
+/// `--session-cpu-limit`: a percentage of one core like `600%`.
+pub fn parse_cpu(s: &str) -> Result<f64, String> {
+    let percent = s.trim().trim_end_matches('%').trim();
+    match percent.parse::<f64>() {
+        Ok(percent) if percent > 0.0 => Ok(percent),
+        _ => Err(format!("invalid CPU limit {s:?}, expected e.g. 600%")),
+    }
+}
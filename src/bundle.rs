@@ -0,0 +1,191 @@
+//! `--capture-bundle`: when the session fails, everything needed to report
+//! the problem in one `runall-<time>.tar.gz`: the command line and the
+//! commands it resolved to, the environment with secrets masked, the logs
+//! of the processes, their timings and some facts about the system.
+
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::Job;
+
+/// Environment variables whose names contain one of these have their value
+/// replaced.
+const SECRET_NAMES: [&str; 7] = [
+    "SECRET",
+    "TOKEN",
+    "PASSWORD",
+    "PASSWD",
+    "KEY",
+    "AUTH",
+    "CREDENTIAL",
+];
+
+pub struct Bundle<'a> {
+    pub jobs: &'a [Job],
+    pub statuses: &'a [Option<std::process::ExitStatus>],
+    pub log_dir: Option<&'a Path>,
+    /// `--stats-export` CSV with the timings.
+    pub timings: Option<&'a Path>,
+}
+
+/// Writes the bundle into `dir` and returns its path.
+pub fn write(dir: &Path, bundle: &Bundle) -> std::io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let stamp = chrono::Local::now().format("%Y-%m-%dT%H-%M-%S");
+    let path = dir.join(format!("runall-{stamp}.tar.gz"));
+    let file = fs::File::create(&path)?;
+    let mut tar = Tar::new(flate2::write::GzEncoder::new(
+        file,
+        flate2::Compression::default(),
+    ));
+    tar.add("argv.txt", argv().as_bytes())?;
+    tar.add("commands.txt", commands(bundle).as_bytes())?;
+    tar.add("env.txt", environment().as_bytes())?;
+    tar.add("system.txt", system().as_bytes())?;
+    if let Some(timings) = bundle.timings {
+        tar.add("timings.csv", &fs::read(timings)?)?;
+    }
+    if let Some(log_dir) = bundle.log_dir {
+        let mut logs = fs::read_dir(log_dir)?
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            // not the pid file of the run in progress
+            .filter(|path| path.is_file() && path.extension().is_none_or(|ext| ext != "pid"))
+            .collect::<Vec<_>>();
+        logs.sort();
+        for log in logs {
+            let name = log.file_name().expect("file name").to_string_lossy();
+            tar.add(&format!("logs/{name}"), &fs::read(&log)?)?;
+        }
+    }
+    tar.finish()?.finish()?;
+    Ok(path)
+}
+
+fn argv() -> String {
+    let argv = std::env::args_os()
+        .map(|arg| arg.to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    format!("{}\n", argv.join(" "))
+}
+
+fn commands(bundle: &Bundle) -> String {
+    let mut out = String::new();
+    for (idx, job) in bundle.jobs.iter().enumerate() {
+        let status = match bundle.statuses.get(idx).copied().flatten() {
+            Some(status) => status.to_string(),
+            None => "not started".to_string(),
+        };
+        out.push_str(&format!(
+            "[{}]\ncmd = {}\nresult = {status}\n",
+            job.name,
+            crate::redact::apply(job.cmd.as_str().into())
+        ));
+        if let Some(cwd) = &job.cwd {
+            out.push_str(&format!("cwd = {}\n", cwd.display()));
+        }
+        for (key, value) in &job.env {
+            out.push_str(&format!("env {key} = {}\n", redact(key, value)));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn redact(key: &str, value: &str) -> String {
+    let key = key.to_ascii_uppercase();
+    if SECRET_NAMES.iter().any(|secret| key.contains(secret)) {
+        "<redacted>".to_string()
+    } else {
+        crate::redact::apply(value.into()).into_owned()
+    }
+}
+
+fn environment() -> String {
+    let mut vars = std::env::vars_os()
+        .map(|(key, value)| {
+            let key = key.to_string_lossy().to_string();
+            let value = redact(&key, &value.to_string_lossy());
+            format!("{key}={value}\n")
+        })
+        .collect::<Vec<_>>();
+    vars.sort();
+    vars.concat()
+}
+
+fn system() -> String {
+    let cwd = std::env::current_dir().map_or("-".to_string(), |dir| dir.display().to_string());
+    let cpus = std::thread::available_parallelism().map_or(0, |n| n.get());
+    format!(
+        "runall {}\nos {}\narch {}\ncpus {cpus}\ncwd {cwd}\ntime {}\n{}",
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        chrono::Local::now().to_rfc3339(),
+        uname(),
+    )
+}
+
+#[cfg(unix)]
+fn uname() -> String {
+    match crate::reaper::output(std::process::Command::new("uname").arg("-a")) {
+        Ok(uname) => format!("uname {}", String::from_utf8_lossy(&uname.stdout)),
+        Err(_) => String::new(),
+    }
+}
+
+#[cfg(not(unix))]
+fn uname() -> String {
+    String::new()
+}
+
+/// A minimal writer of ustar archives.
+struct Tar<W: Write> {
+    out: W,
+}
+
+impl<W: Write> Tar<W> {
+    fn new(out: W) -> Self {
+        Self { out }
+    }
+
+    fn add(&mut self, path: &str, data: &[u8]) -> std::io::Result<()> {
+        let mtime = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let path = format!("runall-bundle/{path}");
+        let mut header = [0u8; 512];
+        let name = &path.as_bytes()[..path.len().min(100)];
+        header[..name.len()].copy_from_slice(name);
+        octal(&mut header[100..108], 0o644);
+        octal(&mut header[108..116], 0);
+        octal(&mut header[116..124], 0);
+        octal(&mut header[124..136], data.len() as u64);
+        octal(&mut header[136..148], mtime);
+        header[156] = b'0';
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        // the checksum is computed with its own field set to spaces
+        header[148..156].fill(b' ');
+        let sum = header.iter().map(|&b| u64::from(b)).sum::<u64>();
+        octal(&mut header[148..155], sum);
+        self.out.write_all(&header)?;
+        self.out.write_all(data)?;
+        let padding = (512 - data.len() % 512) % 512;
+        self.out.write_all(&vec![0; padding])
+    }
+
+    fn finish(mut self) -> std::io::Result<W> {
+        self.out.write_all(&[0; 1024])?;
+        Ok(self.out)
+    }
+}
+
+/// Writes `value` as zero padded octal digits and a NUL into `field`.
+fn octal(field: &mut [u8], value: u64) {
+    let digits = format!("{value:0width$o}\0", width = field.len() - 1);
+    field.copy_from_slice(digits.as_bytes());
+}
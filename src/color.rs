@@ -0,0 +1,52 @@
+//! ANSI colors for runall's own status output.
+
+use std::{
+    io::IsTerminal,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// `--no-color`
+pub fn disable() {
+    DISABLED.store(true, Ordering::Relaxed);
+}
+
+fn allowed() -> bool {
+    !DISABLED.load(Ordering::Relaxed) && std::env::var_os("NO_COLOR").is_none()
+}
+
+/// Colors are used when stderr is a terminal and neither `NO_COLOR` nor
+/// `--no-color` is set.
+pub fn enabled() -> bool {
+    allowed() && std::io::stderr().is_terminal()
+}
+
+/// The same for the prefixes of child output, which goes to stdout.
+pub fn output_enabled() -> bool {
+    allowed() && std::io::stdout().is_terminal()
+}
+
+/// Prefix colors, red and green are left for exit banners.
+const PALETTE: [&str; 8] = ["36", "33", "35", "34", "96", "93", "95", "94"];
+
+/// The prefix color of the job with index `i`.
+pub fn palette(i: usize) -> &'static str {
+    PALETTE[i % PALETTE.len()]
+}
+
+pub fn paint(text: &str, code: &str) -> String {
+    if enabled() {
+        format!("\x1b[{code}m{text}\x1b[0m")
+    } else {
+        text.to_string()
+    }
+}
+
+pub fn green(text: &str) -> String {
+    paint(text, "32")
+}
+
+pub fn red(text: &str) -> String {
+    paint(text, "31")
+}
@@ -0,0 +1,366 @@
+//! `--success-when`: a condition that decides whether a run of a command
+//! succeeded, for tools whose exit code doesn't tell, like
+//! `exit_code == 0 || output_contains('0 failures')`. The run then counts
+//! as a success or failure everywhere, in the summary, for retries and for
+//! `--kill-others`.
+//!
+//! A condition compares `exit_code` to a number (`==`, `!=`, `<`, `<=`,
+//! `>`, `>=`), looks at the lines of both streams with
+//! `output_contains('text')` and `output_matches('regex')`, or is `true` or
+//! `false`, combined with `&&`, `||`, `!` and parentheses. Strings are in
+//! single or double quotes, a backslash escapes the quote.
+
+use std::process::ExitStatus;
+
+use crate::probe::LogProbe;
+
+#[derive(Clone, Debug)]
+pub enum Condition {
+    Const(bool),
+    ExitCode(Cmp, i32),
+    /// Set by the output forwarding once a line matched.
+    Output(LogProbe),
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Cmp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Cmp {
+    fn test(self, left: i32, right: i32) -> bool {
+        match self {
+            Cmp::Eq => left == right,
+            Cmp::Ne => left != right,
+            Cmp::Lt => left < right,
+            Cmp::Le => left <= right,
+            Cmp::Gt => left > right,
+            Cmp::Ge => left >= right,
+        }
+    }
+}
+
+impl Condition {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parser = Parser { text, pos: 0 };
+        let condition = parser.or()?;
+        parser.skip_space();
+        if !parser.rest().is_empty() {
+            return Err(parser.expected("&& or ||"));
+        }
+        Ok(condition)
+    }
+
+    /// The condition for a new run, before any output matched.
+    pub fn fresh(&self) -> Self {
+        let fresh = |condition: &Condition| Box::new(condition.fresh());
+        match self {
+            Condition::Output(probe) => Condition::Output(LogProbe::new(probe.regex.clone())),
+            Condition::Not(condition) => Condition::Not(fresh(condition)),
+            Condition::And(left, right) => Condition::And(fresh(left), fresh(right)),
+            Condition::Or(left, right) => Condition::Or(fresh(left), fresh(right)),
+            condition => condition.clone(),
+        }
+    }
+
+    /// The probes that need to see the lines of the process.
+    pub fn probes(&self) -> Vec<LogProbe> {
+        match self {
+            Condition::Const(_) | Condition::ExitCode(..) => Vec::new(),
+            Condition::Output(probe) => vec![probe.clone()],
+            Condition::Not(condition) => condition.probes(),
+            Condition::And(left, right) | Condition::Or(left, right) => {
+                let mut probes = left.probes();
+                probes.extend(right.probes());
+                probes
+            }
+        }
+    }
+
+    /// Killed by a signal there is no exit code, and comparisons with it
+    /// don't hold.
+    fn holds(&self, code: Option<i32>) -> bool {
+        match self {
+            Condition::Const(value) => *value,
+            Condition::ExitCode(cmp, value) => code.is_some_and(|code| cmp.test(code, *value)),
+            Condition::Output(probe) => probe.matched(),
+            Condition::Not(condition) => !condition.holds(code),
+            Condition::And(left, right) => left.holds(code) && right.holds(code),
+            Condition::Or(left, right) => left.holds(code) || right.holds(code),
+        }
+    }
+
+    /// What the run counts as: a success if the condition holds, otherwise
+    /// the failure it was, or exit code 1 if it exited with 0.
+    pub fn classify(&self, status: ExitStatus) -> ExitStatus {
+        match (self.holds(status.code()), status.success()) {
+            (true, false) => exited(0),
+            (false, true) => exited(1),
+            _ => status,
+        }
+    }
+}
+
+#[cfg(unix)]
+fn exited(code: i32) -> ExitStatus {
+    std::os::unix::process::ExitStatusExt::from_raw(code << 8)
+}
+
+#[cfg(not(unix))]
+fn exited(code: i32) -> ExitStatus {
+    std::os::windows::process::ExitStatusExt::from_raw(code as u32)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn skip_space(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Consumes `token` if it comes next.
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_space();
+        let found = self.rest().starts_with(token);
+        if found {
+            self.pos += token.len();
+        }
+        found
+    }
+
+    fn expected(&self, what: &str) -> String {
+        match self.rest() {
+            "" => format!("expected {what} at the end of {:?}", self.text),
+            rest => format!("expected {what} at {rest:?} in {:?}", self.text),
+        }
+    }
+
+    fn or(&mut self) -> Result<Condition, String> {
+        let mut condition = self.and()?;
+        while self.eat("||") {
+            condition = Condition::Or(Box::new(condition), Box::new(self.and()?));
+        }
+        Ok(condition)
+    }
+
+    fn and(&mut self) -> Result<Condition, String> {
+        let mut condition = self.unary()?;
+        while self.eat("&&") {
+            condition = Condition::And(Box::new(condition), Box::new(self.unary()?));
+        }
+        Ok(condition)
+    }
+
+    fn unary(&mut self) -> Result<Condition, String> {
+        if self.eat("!") {
+            return Ok(Condition::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("(") {
+            let condition = self.or()?;
+            if !self.eat(")") {
+                return Err(self.expected("')'"));
+            }
+            return Ok(condition);
+        }
+        let start = self.pos;
+        match self.word() {
+            "true" => Ok(Condition::Const(true)),
+            "false" => Ok(Condition::Const(false)),
+            "exit_code" => {
+                let cmps = [
+                    ("==", Cmp::Eq),
+                    ("!=", Cmp::Ne),
+                    ("<=", Cmp::Le),
+                    (">=", Cmp::Ge),
+                    ("<", Cmp::Lt),
+                    (">", Cmp::Gt),
+                ];
+                let Some((_, cmp)) = cmps.into_iter().find(|(token, _)| self.eat(token)) else {
+                    return Err(self.expected("a comparison"));
+                };
+                self.skip_space();
+                let rest = self.rest();
+                let len = rest
+                    .char_indices()
+                    .find(|&(i, c)| !(c.is_ascii_digit() || (i == 0 && c == '-')))
+                    .map_or(rest.len(), |(i, _)| i);
+                let Ok(value) = rest[..len].parse() else {
+                    return Err(self.expected("an exit code"));
+                };
+                self.pos += len;
+                Ok(Condition::ExitCode(cmp, value))
+            }
+            function @ ("output_contains" | "output_matches") => {
+                if !self.eat("(") {
+                    return Err(self.expected("'('"));
+                }
+                let text = self.string()?;
+                if !self.eat(")") {
+                    return Err(self.expected("')'"));
+                }
+                let pattern = match function {
+                    "output_contains" => regex::escape(&text),
+                    _ => text,
+                };
+                let regex = regex::Regex::new(&pattern)
+                    .map_err(|err| format!("invalid regex in {:?}: {err}", self.text))?;
+                Ok(Condition::Output(LogProbe::new(regex)))
+            }
+            _ => {
+                self.pos = start;
+                Err(self.expected("a condition"))
+            }
+        }
+    }
+
+    fn word(&mut self) -> &'a str {
+        self.skip_space();
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        self.pos += len;
+        &rest[..len]
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.skip_space();
+        let mut chars = self.rest().char_indices();
+        let Some((_, quote @ ('\'' | '"'))) = chars.next() else {
+            return Err(self.expected("a quoted string"));
+        };
+        let mut value = String::new();
+        let mut escaped = false;
+        for (i, c) in chars {
+            if escaped {
+                if c != quote {
+                    value.push('\\');
+                }
+                value.push(c);
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == quote {
+                self.pos += i + 1;
+                return Ok(value);
+            } else {
+                value.push(c);
+            }
+        }
+        Err(format!("unterminated string in {:?}", self.text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn holds(text: &str, code: i32) -> bool {
+        let condition = Condition::parse(text).expect("valid condition");
+        condition.classify(exited(code)).success()
+    }
+
+    #[test]
+    fn compares_exit_codes() {
+        assert!(holds("exit_code == 0", 0));
+        assert!(!holds("exit_code == 0", 1));
+        assert!(holds("exit_code != 0", 2));
+        assert!(holds("exit_code<=1", 1));
+        assert!(holds("exit_code >= 2", 3));
+        assert!(holds("exit_code < 0 || exit_code > 1", 2));
+        assert!(holds("exit_code > -1", 0));
+        assert!(holds("true", 1));
+        assert!(!holds("false", 0));
+    }
+
+    #[test]
+    fn combines_conditions() {
+        assert!(holds("!(exit_code == 1)", 0));
+        assert!(!holds("exit_code == 0 && false", 0));
+        assert!(holds("false || exit_code == 0 && true", 0));
+        // && binds tighter than ||
+        assert!(holds("true || false && false", 1));
+        assert!(!holds("(true || false) && false", 1));
+    }
+
+    #[test]
+    fn matches_output() {
+        let text = r#"exit_code == 0 || output_contains('0 failures') && !output_matches("^E\d+")"#;
+        let condition = Condition::parse(text).expect("valid condition");
+        assert!(!condition.classify(exited(1)).success());
+        let probes = condition.probes();
+        assert_eq!(probes.len(), 2);
+        for probe in &probes {
+            probe.check("3 tests, 0 failures");
+        }
+        assert!(condition.classify(exited(1)).success());
+        for probe in &probes {
+            probe.check("E42 flaky");
+        }
+        assert!(!condition.classify(exited(1)).success());
+        // a new run starts without matches
+        assert!(!condition.fresh().classify(exited(1)).success());
+    }
+
+    #[test]
+    fn parses_strings() {
+        let condition = Condition::parse(r"output_contains('it\'s (1+1)')").expect("valid");
+        let probes = condition.probes();
+        assert_eq!(probes[0].regex.as_str(), r"it's \(1\+1\)");
+        let condition = Condition::parse(r#"output_matches("\d \"x\"")"#).expect("valid");
+        assert_eq!(condition.probes()[0].regex.as_str(), r#"\d "x""#);
+    }
+
+    #[test]
+    fn classifies_runs() {
+        let condition = Condition::parse("exit_code == 3").expect("valid condition");
+        assert_eq!(condition.classify(exited(3)).code(), Some(0));
+        assert_eq!(condition.classify(exited(0)).code(), Some(1));
+        assert_eq!(condition.classify(exited(4)).code(), Some(4));
+    }
+
+    #[test]
+    fn rejects_invalid_conditions() {
+        let error = |text| Condition::parse(text).expect_err(text);
+        assert_eq!(
+            error("exit_code"),
+            r#"expected a comparison at the end of "exit_code""#
+        );
+        assert_eq!(
+            error("exit_code == zero"),
+            r#"expected an exit code at "zero" in "exit_code == zero""#
+        );
+        assert_eq!(
+            error("exit_code == 0 &"),
+            r#"expected && or || at "&" in "exit_code == 0 &""#
+        );
+        assert_eq!(error("(true"), r#"expected ')' at the end of "(true""#);
+        assert_eq!(
+            error("exit == 0"),
+            r#"expected a condition at "exit == 0" in "exit == 0""#
+        );
+        assert_eq!(
+            error("output_contains(x)"),
+            r#"expected a quoted string at "x)" in "output_contains(x)""#
+        );
+        assert!(error("output_contains('x").starts_with("unterminated string"));
+        assert!(error("output_matches('(')").starts_with("invalid regex"));
+        assert!(Condition::parse("").is_err());
+    }
+}
@@ -0,0 +1,640 @@
+//! `--config` (a runall.toml) and `--procfile`: named commands defined in a
+//! file instead of on the command line.
+//!
+//! ```toml
+//! [settings]
+//! fail_fast = true
+//!
+//! [commands.db]
+//! cmd = "postgres -D data"
+//! kind = "service"
+//! ready_check = "pg_isready"
+//!
+//! [commands.web]
+//! cmd = "npm run dev"
+//! cwd = "frontend"
+//! env = { NODE_ENV = "development" }
+//! needs = ["db"]
+//!
+//! [commands.migrate]
+//! cmd = "npm run migrate"
+//! lock = "db"
+//! on = { success = "./seed.sh" }
+//! ```
+//!
+//! The entries are turned into the equivalent command line options, so
+//! options given on the command line add to or override them. Several
+//! config files are merged, a `local.toml` with
+//!
+//! ```toml
+//! [commands.web]
+//! env = { API_URL = "http://localhost:8080" }
+//! ```
+//!
+//! after the one above adds to the environment of `web`.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Args, Success};
+
+pub const DEFAULT_FILE: &str = "runall.toml";
+
+#[derive(Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct File {
+    #[serde(default, skip_serializing_if = "Settings::is_empty")]
+    settings: Settings,
+    /// Variables of all commands, see `--global-env`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    env: BTreeMap<String, String>,
+    #[serde(default)]
+    commands: toml::Table,
+}
+
+/// The `[settings]` of a config file, options of the session as a whole.
+#[derive(Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    pub fail_fast: Option<bool>,
+    pub kill_others: Option<bool>,
+    pub continue_on_error: Option<bool>,
+    pub success: Option<String>,
+    pub jobs: Option<usize>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub redact: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub redact_env: Vec<String>,
+    pub redact_tokens: Option<bool>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub highlight: Vec<String>,
+    /// `--lock-limit`: units per lock.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub locks: BTreeMap<String, u32>,
+    /// The top-level `[env]` of the file.
+    #[serde(skip)]
+    pub env: BTreeMap<String, String>,
+}
+
+impl Settings {
+    fn is_empty(&self) -> bool {
+        self.fail_fast.is_none()
+            && self.kill_others.is_none()
+            && self.continue_on_error.is_none()
+            && self.success.is_none()
+            && self.jobs.is_none()
+            && self.redact.is_empty()
+            && self.redact_env.is_empty()
+            && self.redact_tokens.is_none()
+            && self.highlight.is_empty()
+            && self.locks.is_empty()
+    }
+}
+
+/// One command of a config file. Apart from `cmd`, `env` and the lists the
+/// fields take the same values as the command line option of the same name.
+#[derive(Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Entry {
+    pub cmd: String,
+    /// Relative to the directory of the config file.
+    pub cwd: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    pub kind: Option<String>,
+    pub oneshot: Option<bool>,
+    pub priority: Option<String>,
+    pub group: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub needs: Vec<String>,
+    pub after_output: Option<AfterOutput>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub after: Vec<String>,
+    pub restart: Option<String>,
+    pub restart_delay: Option<String>,
+    pub max_restarts: Option<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub retry_on: Vec<i32>,
+    pub retries: Option<u32>,
+    pub retry_backoff: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub watch: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub artifacts: Vec<String>,
+    pub ready_check: Option<String>,
+    pub ready_interval: Option<String>,
+    pub ready_retries: Option<u32>,
+    pub wait_file: Option<String>,
+    pub wait_file_gone: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub wait_for: Vec<String>,
+    pub timeout: Option<String>,
+    pub start_timeout: Option<String>,
+    pub timeout_warning: Option<String>,
+    pub timeout_grace: Option<String>,
+    pub expected_duration: Option<String>,
+    pub success_when: Option<String>,
+    pub port: Option<u16>,
+    pub interrupt_signal: Option<String>,
+    pub forward_interrupt: Option<bool>,
+    pub forward_signals: Option<bool>,
+    pub propagate_restart: Option<bool>,
+    pub autostart: Option<bool>,
+    /// Commands run on events of this one, e.g. `on = { ready = "..." }`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub on: BTreeMap<String, String>,
+    pub start_banner: Option<bool>,
+    pub open: Option<String>,
+    pub stdin_policy: Option<String>,
+    pub pipe: Option<String>,
+    pub stdin_file: Option<String>,
+    pub encoding: Option<String>,
+    pub schedule: Option<String>,
+    pub every: Option<String>,
+    pub repeat: Option<u32>,
+    pub rate_limit: Option<String>,
+    pub min_level: Option<String>,
+    pub filter: Option<String>,
+    pub exclude: Option<String>,
+    pub lock: Option<String>,
+    /// Units of `lock` held, 1 by default.
+    pub weight: Option<u32>,
+}
+
+/// `after_output = { name = "seed", pattern = "seeding done" }`.
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct AfterOutput {
+    pub name: String,
+    pub pattern: String,
+}
+
+/// Reads the config files and merges them from left to right: tables
+/// (`[settings]`, `[env]`, the commands and their `env`) are merged, other
+/// values of a later file replace those of earlier ones.
+pub fn load(paths: &[PathBuf]) -> (Settings, Vec<(String, Entry)>) {
+    let mut merged = toml::Table::new();
+    for path in paths {
+        merge(&mut merged, read(path));
+    }
+    let what = paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let file: File = merged
+        .try_into()
+        .unwrap_or_else(|err| panic!("{what}: {err}"));
+    let entries = file
+        .commands
+        .into_iter()
+        .map(|(name, entry)| {
+            let entry: Entry = entry
+                .try_into()
+                .unwrap_or_else(|err| panic!("{what}: command {name}: {err}"));
+            (name, entry)
+        })
+        .collect();
+    let settings = Settings {
+        env: file.env,
+        ..file.settings
+    };
+    (settings, entries)
+}
+
+/// One config file, with the `cwd` of its commands relative to where it is.
+fn read(path: &Path) -> toml::Table {
+    let content = std::fs::read_to_string(path)
+        .unwrap_or_else(|err| panic!("cannot read {}: {err}", path.display()));
+    let mut table: toml::Table =
+        toml::from_str(&content).unwrap_or_else(|err| panic!("{}: {err}", path.display()));
+    // the commands may be incomplete, they are checked once merged
+    if let Err(err) = table.clone().try_into::<File>() {
+        panic!("{}: {err}", path.display());
+    }
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let commands = table.get_mut("commands").and_then(|c| c.as_table_mut());
+    for (_, entry) in commands.into_iter().flat_map(|c| c.iter_mut()) {
+        if let Some(cwd) = entry.get_mut("cwd") {
+            if let Some(relative) = cwd.as_str() {
+                *cwd = dir.join(relative).display().to_string().into();
+            }
+        }
+    }
+    table
+}
+
+fn merge(into: &mut toml::Table, from: toml::Table) {
+    for (key, value) in from {
+        match (into.get_mut(&key), value) {
+            (Some(toml::Value::Table(into)), toml::Value::Table(from)) => merge(into, from),
+            (_, value) => {
+                into.insert(key, value);
+            }
+        }
+    }
+}
+
+/// The content of a config file with the entries, after a `header` comment.
+pub fn render(header: &str, mut settings: Settings, entries: Vec<(String, Entry)>) -> String {
+    let commands = entries
+        .into_iter()
+        .map(|(name, entry)| {
+            let entry = toml::Value::try_from(entry).expect("serializable entry");
+            (name, entry)
+        })
+        .collect();
+    let file = File {
+        env: std::mem::take(&mut settings.env),
+        settings,
+        commands,
+    };
+    let body = toml::to_string(&file).expect("serializable config");
+    let header = header
+        .lines()
+        .map(|line| format!("# {line}\n"))
+        .collect::<String>();
+    format!("{header}\n{body}")
+}
+
+/// Reads a Procfile: `name: command` lines, `#` starts a comment.
+pub fn procfile(path: &Path) -> Vec<(String, Entry)> {
+    let content = std::fs::read_to_string(path)
+        .unwrap_or_else(|err| panic!("cannot read {}: {err}", path.display()));
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(n, line)| {
+            let Some((name, cmd)) = line.split_once(':') else {
+                panic!("{}:{}: expected \"name: command\"", path.display(), n + 1);
+            };
+            let entry = Entry {
+                cmd: cmd.trim().to_string(),
+                ..Default::default()
+            };
+            (name.trim().to_string(), entry)
+        })
+        .collect()
+}
+
+/// Replaces the commands of `args` with the entries, or with the entries
+/// named by the commands and their dependencies.
+pub fn apply(args: &mut Args, settings: Settings, entries: Vec<(String, Entry)>) {
+    args.fail_fast |= settings.fail_fast.unwrap_or(false);
+    args.kill_others |= settings.kill_others.unwrap_or(false);
+    args.continue_on_error |= settings.continue_on_error.unwrap_or(false);
+    args.jobs = args.jobs.or(settings.jobs);
+    args.redact.extend(settings.redact);
+    args.redact_env.extend(settings.redact_env);
+    args.redact_tokens |= settings.redact_tokens.unwrap_or(false);
+    args.highlight.extend(settings.highlight);
+    let limits = settings.locks.iter().map(|(lock, n)| format!("{lock}={n}"));
+    args.lock_limit.splice(0..0, limits);
+    // the command line comes last so that it wins
+    let env = settings
+        .env
+        .iter()
+        .map(|(key, value)| format!("{key}={value}"));
+    args.global_env.splice(0..0, env);
+    if let (Some(success), Success::All) = (&settings.success, &args.success) {
+        args.success = crate::parse_success(success).unwrap_or_else(|err| panic!("success: {err}"));
+    }
+
+    let selected = select(&entries, &args.commands);
+    let entries = entries
+        .into_iter()
+        .zip(selected)
+        .filter_map(|(entry, selected)| selected.then_some(entry))
+        .collect::<Vec<_>>();
+
+    args.names = Some(entries.iter().map(|(name, _)| name.clone()).collect());
+    args.commands = entries.iter().map(|(_, entry)| entry.cmd.clone()).collect();
+    // the command line comes last so that it wins
+    let options = entries
+        .iter()
+        .flat_map(|(name, entry)| entry.options(name))
+        .collect::<Vec<_>>();
+    for (option, value) in options.into_iter().rev() {
+        values(args, option)
+            .unwrap_or_else(|| unreachable!("unknown option {option}"))
+            .insert(0, value);
+    }
+}
+
+/// The per command options that only take the name of the command.
+const NAME_OPTIONS: &[&str] = &[
+    "oneshot",
+    "propagate-restart",
+    "no-forward-interrupt",
+    "no-start-banner",
+    "no-autostart",
+    "forward-signals",
+];
+
+/// Turns the `-c OPTIONS COMMAND` pairs into the commands and their per
+/// command options.
+pub fn inline(args: &mut Args) {
+    let pairs = std::mem::take(&mut args.cmd);
+    let mut names = Vec::new();
+    let mut options = Vec::new();
+    for (i, pair) in pairs.chunks(2).enumerate() {
+        let [spec, cmd] = pair else {
+            unreachable!("-c takes two values");
+        };
+        // a part without = continues the value before it, as in needs=a,b
+        let mut pairs = Vec::<(String, Option<String>)>::new();
+        for part in spec.split(',').filter(|part| !part.is_empty()) {
+            match part.split_once('=') {
+                Some((key, value)) => pairs.push((key.replace('_', "-"), Some(value.to_string()))),
+                None if NAME_OPTIONS.contains(&part.replace('_', "-").as_str()) => {
+                    pairs.push((part.replace('_', "-"), None))
+                }
+                None => match pairs.last_mut() {
+                    Some((_, Some(value))) => {
+                        value.push(',');
+                        value.push_str(part);
+                    }
+                    _ => panic!("-c {spec:?}: expected KEY=VALUE, got {part:?}"),
+                },
+            }
+        }
+        let name = pairs
+            .iter()
+            .find(|(key, _)| key == "name")
+            .and_then(|(_, value)| value.clone())
+            .unwrap_or_else(|| format!("cmd-{}", i + 1));
+        for (key, value) in pairs.into_iter().filter(|(key, _)| key != "name") {
+            let value = match value {
+                _ if !NAME_OPTIONS.contains(&key.as_str()) => {
+                    format!("{name}={}", value.unwrap_or_default())
+                }
+                None => name.clone(),
+                Some(value) => match value.parse::<bool>() {
+                    Ok(true) => name.clone(),
+                    Ok(false) => continue,
+                    Err(_) => panic!("-c {spec:?}: {key} is true or false"),
+                },
+            };
+            options.push((key, value));
+        }
+        args.commands.push(cmd.clone());
+        names.push(name);
+    }
+    args.names = Some(names);
+    // the command line comes last so that it wins
+    for (option, value) in options.into_iter().rev() {
+        let Some(values) = values(args, &option) else {
+            panic!("-c: unknown option {option:?}");
+        };
+        values.insert(0, value);
+    }
+}
+
+impl Entry {
+    /// The settings as per command options, e.g. `("needs", "web=db")`.
+    fn options(&self, name: &str) -> Vec<(&'static str, String)> {
+        let mut options = Vec::new();
+        let mut add = |option, value: Option<String>| {
+            if let Some(value) = value {
+                options.push((option, format!("{name}={value}")));
+            }
+        };
+        add(
+            "cwd",
+            self.cwd.as_ref().map(|cwd| cwd.display().to_string()),
+        );
+        for (key, value) in &self.env {
+            add("env", Some(format!("{key}={value}")));
+        }
+        add("kind", self.kind.clone());
+        add("priority", self.priority.clone());
+        add("group", self.group.clone());
+        add(
+            "needs",
+            (!self.needs.is_empty()).then(|| self.needs.join(",")),
+        );
+        add(
+            "after",
+            (!self.after.is_empty()).then(|| self.after.join(",")),
+        );
+        add("restart", self.restart.clone());
+        add("restart-delay", self.restart_delay.clone());
+        add("max-restarts", self.max_restarts.map(|n| n.to_string()));
+        add(
+            "retry-on",
+            (!self.retry_on.is_empty()).then(|| {
+                let codes = self.retry_on.iter().map(i32::to_string);
+                codes.collect::<Vec<_>>().join(",")
+            }),
+        );
+        add(
+            "watch",
+            (!self.watch.is_empty()).then(|| self.watch.join(",")),
+        );
+        add(
+            "artifacts",
+            (!self.artifacts.is_empty()).then(|| self.artifacts.join(",")),
+        );
+        add("retries", self.retries.map(|n| n.to_string()));
+        add("retry-backoff", self.retry_backoff.clone());
+        add("ready-check", self.ready_check.clone());
+        add("ready-interval", self.ready_interval.clone());
+        add("ready-retries", self.ready_retries.map(|n| n.to_string()));
+        add("wait-file", self.wait_file.clone());
+        add("wait-file-gone", self.wait_file_gone.clone());
+        add(
+            "after-output",
+            self.after_output
+                .as_ref()
+                .map(|after| format!("{}:{}", after.name, after.pattern)),
+        );
+        for condition in &self.wait_for {
+            add("wait-for", Some(condition.clone()));
+        }
+        add("timeout", self.timeout.clone());
+        add("start-timeout", self.start_timeout.clone());
+        add("timeout-warning", self.timeout_warning.clone());
+        add("timeout-grace", self.timeout_grace.clone());
+        add("expected-duration", self.expected_duration.clone());
+        add("success-when", self.success_when.clone());
+        add("port", self.port.map(|port| port.to_string()));
+        add("interrupt-signal", self.interrupt_signal.clone());
+        add("open", self.open.clone());
+        add("stdin-policy", self.stdin_policy.clone());
+        add("stdin-file", self.stdin_file.clone());
+        add("pipe", self.pipe.clone());
+        add("encoding", self.encoding.clone());
+        add("schedule", self.schedule.clone());
+        add("every", self.every.clone());
+        add("repeat", self.repeat.map(|n| n.to_string()));
+        add("rate-limit", self.rate_limit.clone());
+        add("min-level", self.min_level.clone());
+        add("filter", self.filter.clone());
+        add("exclude", self.exclude.clone());
+        if let Some(lock) = &self.lock {
+            let weight = self.weight.map_or(String::new(), |w| format!(":{w}"));
+            add("lock", Some(format!("{lock}{weight}")));
+        } else if self.weight.is_some() {
+            panic!("command {name}: weight without a lock");
+        }
+        if self.forward_interrupt == Some(false) {
+            options.push(("no-forward-interrupt", name.to_string()));
+        }
+        for (event, cmd) in &self.on {
+            options.push(("on", format!("{name}:{event}={cmd}")));
+        }
+        if self.oneshot == Some(true) {
+            options.push(("oneshot", name.to_string()));
+        }
+        if self.propagate_restart == Some(true) {
+            options.push(("propagate-restart", name.to_string()));
+        }
+        if self.forward_signals == Some(true) {
+            options.push(("forward-signals", name.to_string()));
+        }
+        if self.autostart == Some(false) {
+            options.push(("no-autostart", name.to_string()));
+        }
+        if self.start_banner == Some(false) {
+            options.push(("no-start-banner", name.to_string()));
+        }
+        options
+    }
+}
+
+fn values<'a>(args: &'a mut Args, option: &str) -> Option<&'a mut Vec<String>> {
+    Some(match option {
+        "cwd" => &mut args.cwd,
+        "env" => &mut args.env,
+        "kind" => &mut args.kind,
+        "oneshot" => &mut args.oneshot,
+        "propagate-restart" => &mut args.propagate_restart,
+        "priority" => &mut args.priority,
+        "group" => &mut args.group,
+        "needs" => &mut args.needs,
+        "after" => &mut args.after,
+        "restart" => &mut args.restart,
+        "restart-delay" => &mut args.restart_delay,
+        "max-restarts" => &mut args.max_restarts,
+        "retry-on" => &mut args.retry_on,
+        "retries" => &mut args.retries,
+        "retry-backoff" => &mut args.retry_backoff,
+        "watch" => &mut args.watch,
+        "artifacts" => &mut args.artifacts,
+        "ready-check" => &mut args.ready_check,
+        "ready-interval" => &mut args.ready_interval,
+        "ready-retries" => &mut args.ready_retries,
+        "wait-file" => &mut args.wait_file,
+        "wait-file-gone" => &mut args.wait_file_gone,
+        "wait-for" => &mut args.wait_for,
+        "after-output" => &mut args.after_output,
+        "timeout" => &mut args.timeout,
+        "start-timeout" => &mut args.start_timeout,
+        "timeout-warning" => &mut args.timeout_warning,
+        "timeout-grace" => &mut args.timeout_grace,
+        "expected-duration" => &mut args.expected_duration,
+        "success-when" => &mut args.success_when,
+        "port" => &mut args.port,
+        "interrupt-signal" => &mut args.interrupt_signal,
+        "no-forward-interrupt" => &mut args.no_forward_interrupt,
+        "no-start-banner" => &mut args.no_start_banner,
+        "no-autostart" => &mut args.no_autostart,
+        "forward-signals" => &mut args.forward_signals,
+        "on" => &mut args.on,
+        "open" => &mut args.open,
+        "stdin-policy" => &mut args.stdin_policy,
+        "stdin-file" => &mut args.stdin_file,
+        "pipe" => &mut args.pipe,
+        "encoding" => &mut args.encoding,
+        "schedule" => &mut args.schedule,
+        "every" => &mut args.every,
+        "repeat" => &mut args.repeat,
+        "rate-limit" => &mut args.rate_limit,
+        "min-level" => &mut args.min_level,
+        "filter" => &mut args.filter,
+        "exclude" => &mut args.exclude,
+        "lock" => &mut args.lock,
+        _ => return None,
+    })
+}
+
+/// Which entries run: all of them if no names are given, otherwise the
+/// named ones and the entries they depend on.
+fn select(entries: &[(String, Entry)], names: &[String]) -> Vec<bool> {
+    if names.is_empty() {
+        return vec![true; entries.len()];
+    }
+    let mut selected = vec![false; entries.len()];
+    let mut pending = names.to_vec();
+    while let Some(name) = pending.pop() {
+        let Some(idx) = entries.iter().position(|(n, _)| *n == name) else {
+            panic!("no command named {name:?} in the config");
+        };
+        if !std::mem::replace(&mut selected[idx], true) {
+            let entry = &entries[idx].1;
+            let handoff = entry.after_output.iter().map(|after| &after.name);
+            pending.extend(
+                entry
+                    .needs
+                    .iter()
+                    .chain(&entry.after)
+                    .chain(handoff)
+                    .cloned(),
+            );
+        }
+    }
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{CommandFactory, Parser};
+
+    use super::*;
+
+    #[test]
+    fn knows_every_per_command_option() {
+        let mut args = Args::parse_from(["runall", "true"]);
+        for arg in Args::command().get_arguments() {
+            let per_command = arg
+                .get_value_names()
+                .and_then(|names| names.first())
+                .is_some_and(|name| name.starts_with("NAME=") || name.starts_with("NAME:"));
+            // --exec defines a command rather than an option of one
+            let Some(long) = arg.get_long().filter(|&long| long != "exec") else {
+                continue;
+            };
+            if per_command || NAME_OPTIONS.contains(&long) {
+                assert!(values(&mut args, long).is_some(), "--{long}");
+            }
+        }
+    }
+
+    #[test]
+    fn inlines_options() {
+        let mut args = Args::parse_from([
+            "runall",
+            "-c",
+            "name=web,needs=db,api,ready-interval=1s,oneshot",
+            "npm run dev",
+            "-c",
+            "forward-signals=false",
+            "cargo run",
+        ]);
+        inline(&mut args);
+        assert_eq!(args.commands, ["npm run dev", "cargo run"]);
+        assert_eq!(
+            args.names,
+            Some(vec!["web".to_string(), "cmd-2".to_string()])
+        );
+        assert_eq!(args.needs, ["web=db,api"]);
+        assert_eq!(args.ready_interval, ["web=1s"]);
+        assert_eq!(args.oneshot, ["web"]);
+        assert!(args.forward_signals.is_empty());
+    }
+}
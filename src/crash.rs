@@ -0,0 +1,110 @@
+//! Bugs in runall itself: once the session runs, a panic stops the
+//! processes of the commands so that none of them is left behind, writes a
+//! crash report with the backtrace into the run directory and ends runall
+//! with a short message pointing to the report instead of a raw backtrace.
+
+use std::{
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// The processes of the commands that are running.
+static RUNNING: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+/// How long the processes get to stop before they are killed.
+const GRACE: Duration = Duration::from_secs(2);
+
+pub fn started(pid: u32) {
+    RUNNING.lock().expect("lock").push(pid);
+}
+
+pub fn exited(pid: u32) {
+    RUNNING
+        .lock()
+        .expect("lock")
+        .retain(|&running| running != pid);
+}
+
+fn running() -> Vec<u32> {
+    // the panic may have happened while the lock was held
+    match RUNNING.lock() {
+        Ok(running) => running.clone(),
+        Err(poisoned) => poisoned.into_inner().clone(),
+    }
+}
+
+/// Handles panics from now on. The report goes into `dir`.
+pub fn install(dir: PathBuf) {
+    std::panic::set_hook(Box::new(move |info| {
+        let message = match info.payload().downcast_ref::<&str>() {
+            Some(message) => message.to_string(),
+            None => info
+                .payload()
+                .downcast_ref::<String>()
+                .cloned()
+                .unwrap_or_else(|| "unknown panic".to_string()),
+        };
+        let location = info
+            .location()
+            .map_or_else(String::new, |location| location.to_string());
+        let backtrace = std::backtrace::Backtrace::force_capture();
+        let pids = running();
+        stop(&pids);
+        let argv = std::env::args_os()
+            .map(|arg| arg.to_string_lossy().to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let thread = std::thread::current();
+        let report = format!(
+            "runall {} crashed at {}\n\
+             os: {} {}\n\
+             command line: {}\n\
+             thread: {}\n\
+             panic: {message}\n\
+             location: {location}\n\
+             processes stopped: {pids:?}\n\n\
+             {backtrace}\n",
+            env!("CARGO_PKG_VERSION"),
+            chrono::Local::now().to_rfc3339(),
+            std::env::consts::OS,
+            std::env::consts::ARCH,
+            crate::redact::apply(argv.into()),
+            thread.name().unwrap_or("unnamed"),
+        );
+        let name = format!("crash-{}.txt", chrono::Local::now().format("%Y%m%d-%H%M%S"));
+        let path = dir.join(name);
+        let written = std::fs::create_dir_all(&dir).and_then(|()| std::fs::write(&path, report));
+        eprintln!("runall crashed: {message} ({location})");
+        if !pids.is_empty() {
+            eprintln!(
+                "stopped {} processes so that none is left running",
+                pids.len()
+            );
+        }
+        match written {
+            Ok(()) => eprintln!(
+                "the crash report is in {}, please attach it to an issue at {}/issues",
+                path.display(),
+                env!("CARGO_PKG_REPOSITORY")
+            ),
+            Err(err) => eprintln!("cannot write the crash report {}: {err}", path.display()),
+        }
+        std::process::exit(101);
+    }));
+}
+
+/// Asks the processes to stop and kills the ones still running after
+/// [`GRACE`].
+fn stop(pids: &[u32]) {
+    for &pid in pids {
+        crate::runner::signal_tree(pid, "SIGTERM");
+    }
+    let deadline = Instant::now() + GRACE;
+    while !running().is_empty() && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    for pid in running() {
+        crate::runner::signal_tree(pid, "SIGKILL");
+    }
+}
@@ -0,0 +1,194 @@
+//! Cron expressions (`minute hour day-of-month month day-of-week`) for
+//! `--schedule`.
+
+use chrono::{DateTime, Datelike, Duration, Local, Timelike};
+
+#[derive(Clone, Debug)]
+pub struct Schedule {
+    minutes: Vec<bool>,
+    hours: Vec<bool>,
+    days: Vec<bool>,
+    months: Vec<bool>,
+    weekdays: Vec<bool>,
+    /// Like cron, if both day fields are restricted a day matches if either
+    /// of them matches.
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+/// Parses one field like `*`, `*/5`, `1-5`, `0-30/10` or `1,15` into a
+/// lookup table indexed by value.
+fn field(spec: &str, min: u32, max: u32) -> Result<Vec<bool>, String> {
+    let mut table = vec![false; max as usize + 1];
+    for part in spec.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (
+                range,
+                step.parse::<u32>()
+                    .ok()
+                    .filter(|s| *s > 0)
+                    .ok_or_else(|| format!("invalid step in {spec:?}"))?,
+            ),
+            None => (part, 1),
+        };
+        let (from, to) = if range == "*" {
+            (min, max)
+        } else if let Some((from, to)) = range.split_once('-') {
+            (number(from, spec)?, number(to, spec)?)
+        } else {
+            let n = number(range, spec)?;
+            (n, if step > 1 { max } else { n })
+        };
+        if from < min || to > max || from > to {
+            return Err(format!("{spec:?} is out of range {min}-{max}"));
+        }
+        for value in (from..=to).step_by(step as usize) {
+            table[value as usize] = true;
+        }
+    }
+    Ok(table)
+}
+
+fn number(s: &str, spec: &str) -> Result<u32, String> {
+    s.parse().map_err(|_| format!("invalid number in {spec:?}"))
+}
+
+impl Schedule {
+    pub fn parse(expr: &str) -> Result<Self, String> {
+        let fields = expr.split_whitespace().collect::<Vec<_>>();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!("expected 5 fields in cron expression {expr:?}"));
+        };
+        let mut weekdays = field(weekday, 0, 7)?;
+        // both 0 and 7 are sunday
+        weekdays[0] |= weekdays[7];
+        let schedule = Self {
+            minutes: field(minute, 0, 59)?,
+            hours: field(hour, 0, 23)?,
+            days: field(day, 1, 31)?,
+            months: field(month, 1, 12)?,
+            weekdays,
+            days_restricted: day != "*",
+            weekdays_restricted: weekday != "*",
+        };
+        // with the day of the week restricted as well any week has a match
+        let only_days = schedule.days_restricted && !schedule.weekdays_restricted;
+        if only_days && !schedule.possible_day() {
+            return Err(format!("{expr:?} never matches, no month has that day"));
+        }
+        Ok(schedule)
+    }
+
+    /// Whether one of the days exists in one of the months, February 29 in
+    /// leap years.
+    fn possible_day(&self) -> bool {
+        const LENGTHS: [usize; 12] = [31, 29, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+        (1..=12)
+            .filter(|&month| self.months[month])
+            .any(|month| (1..=LENGTHS[month - 1]).any(|day| self.days[day]))
+    }
+
+    fn matches(&self, t: &DateTime<Local>) -> bool {
+        let day = self.days[t.day() as usize];
+        let weekday = self.weekdays[t.weekday().num_days_from_sunday() as usize];
+        let day_matches = match (self.days_restricted, self.weekdays_restricted) {
+            (true, true) => day || weekday,
+            _ => day && weekday,
+        };
+        self.minutes[t.minute() as usize]
+            && self.hours[t.hour() as usize]
+            && self.months[t.month() as usize]
+            && day_matches
+    }
+
+    /// The first matching minute after `t`, if there is one within the next
+    /// few years.
+    pub fn next_after(&self, t: DateTime<Local>) -> Option<DateTime<Local>> {
+        let mut next = t.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let limit = t + Duration::days(5 * 366);
+        while next < limit {
+            if self.matches(&next) {
+                return Some(next);
+            }
+            next += Duration::minutes(1);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn at(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Local
+            .with_ymd_and_hms(year, month, day, hour, minute, 0)
+            .single()
+            .expect("unambiguous local time")
+    }
+
+    fn next(expr: &str, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        Schedule::parse(expr).expect("valid").next_after(after)
+    }
+
+    #[test]
+    fn parses_fields() {
+        assert!(Schedule::parse("* * * * *").is_ok());
+        assert!(Schedule::parse("*/5 0-12/3 1,15 1-6 1-5").is_ok());
+        assert!(Schedule::parse("0 0 * * 7").is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_fields() {
+        assert!(Schedule::parse("* * * *").is_err());
+        assert!(Schedule::parse("* * * * * *").is_err());
+        assert!(Schedule::parse("60 * * * *").is_err());
+        assert!(Schedule::parse("* 24 * * *").is_err());
+        assert!(Schedule::parse("* * 0 * *").is_err());
+        assert!(Schedule::parse("* * * 13 *").is_err());
+        assert!(Schedule::parse("* * * * 8").is_err());
+        assert!(Schedule::parse("*/0 * * * *").is_err());
+        assert!(Schedule::parse("5-1 * * * *").is_err());
+        assert!(Schedule::parse("a * * * *").is_err());
+        // no names for weekdays or months
+        assert!(Schedule::parse("* * * * mon").is_err());
+    }
+
+    #[test]
+    fn rejects_impossible_dates() {
+        assert!(Schedule::parse("0 0 30 2 *").is_err());
+        assert!(Schedule::parse("0 0 31 4,6,9,11 *").is_err());
+        assert!(Schedule::parse("0 0 31 4,5 *").is_ok());
+        assert!(Schedule::parse("0 0 29 2 *").is_ok());
+        // the day of the week still matches every week
+        assert!(Schedule::parse("0 0 30 2 1").is_ok());
+    }
+
+    #[test]
+    fn next_minute() {
+        let t = at(2024, 3, 5, 10, 30);
+        assert_eq!(next("* * * * *", t), Some(at(2024, 3, 5, 10, 31)));
+        assert_eq!(next("*/15 * * * *", t), Some(at(2024, 3, 5, 10, 45)));
+        assert_eq!(next("0 9 * * *", t), Some(at(2024, 3, 6, 9, 0)));
+        assert_eq!(next("0 0 1 * *", t), Some(at(2024, 4, 1, 0, 0)));
+    }
+
+    #[test]
+    fn next_leap_day() {
+        let t = at(2025, 3, 1, 0, 0);
+        assert_eq!(next("0 0 29 2 *", t), Some(at(2028, 2, 29, 0, 0)));
+    }
+
+    #[test]
+    fn day_of_week_and_month() {
+        // 2024-03-05 is a tuesday
+        let t = at(2024, 3, 5, 12, 0);
+        assert_eq!(next("0 0 * * 5", t), Some(at(2024, 3, 8, 0, 0)));
+        assert_eq!(next("0 0 * * 0", t), next("0 0 * * 7", t));
+        // both restricted: either one matches, like cron
+        assert_eq!(next("0 0 7 * 5", t), Some(at(2024, 3, 7, 0, 0)));
+        assert_eq!(next("0 0 20 * 3", t), Some(at(2024, 3, 6, 0, 0)));
+    }
+}
@@ -0,0 +1,246 @@
+//! `runall ctl`: managing a running session from another terminal. Every
+//! session listens on a unix socket named after it (a port of localhost on
+//! windows), a request is one line
+//! (`status`, `ready`, `start NAME`, `stop NAME`, `restart NAME`, `show
+//! NAME`, `hide NAME`, `add NAME=COMMAND`, `set KEY=VALUE`, `adopt NAME=PID`
+//! or `quit`) and the answer is text up to the end of the connection. Failed requests are answered
+//! with a line starting with `error: `.
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::runner::Event;
+
+#[derive(clap::Args)]
+pub struct CtlArgs {
+    /// What to do: show the status table, tell whether all commands are
+    /// ready (ready, or waiting for NAMES), start, stop or restart NAME,
+    /// print the output of NAME on the console or only log it (show, hide,
+    /// like --show), run another command in the session (add NAME=COMMAND),
+    /// change an option of the session (set KEY=VALUE), watch a process
+    /// started elsewhere (adopt NAME=PID, see runall adopt), or end the
+    /// session (quit).
+    #[clap(value_enum)]
+    pub action: Action,
+
+    /// The command to start, stop, restart, show or hide. For add its name
+    /// and the command, like "web=npm run dev". For set the option and its
+    /// value: kill-others, fail-fast or
+    /// continue-on-error=true|false, or min-level=LEVEL|off for all commands.
+    #[clap(required_if_eq_any = [
+        ("action", "start"),
+        ("action", "stop"),
+        ("action", "restart"),
+        ("action", "show"),
+        ("action", "hide"),
+        ("action", "add"),
+        ("action", "set"),
+        ("action", "adopt"),
+    ])]
+    pub name: Option<String>,
+
+    /// The session to manage. Defaults to the name of the current
+    /// directory, like for the session itself.
+    #[clap(long)]
+    pub session: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum Action {
+    Status,
+    Ready,
+    Start,
+    Stop,
+    Restart,
+    Show,
+    Hide,
+    Add,
+    Set,
+    Adopt,
+    Quit,
+}
+
+/// A request to the runner, answered through the channel.
+pub struct Request {
+    pub action: Action,
+    pub name: String,
+    pub reply: flume::Sender<String>,
+}
+
+/// How long the runner gets to answer.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The socket of `session`, next to the logs.
+pub fn socket(session: &str) -> PathBuf {
+    let root = crate::logs::root();
+    let dir = root.parent().unwrap_or(&root);
+    dir.join("ctl")
+        .join(format!("{}.sock", crate::logs::file_name(session)))
+}
+
+/// Removes the socket when the session ends.
+pub struct Server(PathBuf);
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Listens on `path` and turns the requests into events.
+#[cfg(unix)]
+pub fn serve(path: &Path, events: flume::Sender<Event>) -> Option<Server> {
+    use std::os::unix::net::UnixListener;
+
+    if connect(path).is_ok() {
+        in_use(path);
+        return None;
+    }
+    // left behind by a session that was killed
+    let _ = std::fs::remove_file(path);
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    let listener = match UnixListener::bind(path) {
+        Ok(listener) => listener,
+        Err(err) => {
+            notice!("cannot listen on {}: {err}", path.display());
+            return None;
+        }
+    };
+    std::thread::spawn(move || {
+        for stream in listener.incoming().map_while(Result::ok) {
+            let events = events.clone();
+            let _ = stream.set_read_timeout(Some(TIMEOUT));
+            std::thread::spawn(move || respond(stream, &events));
+        }
+    });
+    Some(Server(path.to_path_buf()))
+}
+
+/// Without unix sockets the session listens on a port of localhost, which
+/// is written to `path`.
+#[cfg(not(unix))]
+pub fn serve(path: &Path, events: flume::Sender<Event>) -> Option<Server> {
+    use std::net::{Ipv4Addr, TcpListener};
+
+    if connect(path).is_ok() {
+        in_use(path);
+        return None;
+    }
+    let listener = match TcpListener::bind((Ipv4Addr::LOCALHOST, 0)) {
+        Ok(listener) => listener,
+        Err(err) => {
+            notice!("cannot listen for runall ctl: {err}");
+            return None;
+        }
+    };
+    let port = listener.local_addr().expect("local address").port();
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    if let Err(err) = std::fs::write(path, port.to_string()) {
+        notice!("cannot write {}: {err}", path.display());
+        return None;
+    }
+    std::thread::spawn(move || {
+        for stream in listener.incoming().map_while(Result::ok) {
+            let events = events.clone();
+            let _ = stream.set_read_timeout(Some(TIMEOUT));
+            std::thread::spawn(move || respond(stream, &events));
+        }
+    });
+    Some(Server(path.to_path_buf()))
+}
+
+#[cfg(unix)]
+fn connect(path: &Path) -> std::io::Result<std::os::unix::net::UnixStream> {
+    std::os::unix::net::UnixStream::connect(path)
+}
+
+#[cfg(not(unix))]
+fn connect(path: &Path) -> std::io::Result<std::net::TcpStream> {
+    let port = std::fs::read_to_string(path)?;
+    let port = port
+        .trim()
+        .parse::<u16>()
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+    std::net::TcpStream::connect((std::net::Ipv4Addr::LOCALHOST, port))
+}
+
+fn in_use(path: &Path) {
+    notice!(
+        "another session listens on {}, runall ctl reaches that one",
+        path.display()
+    );
+}
+
+fn respond<S>(stream: S, events: &flume::Sender<Event>)
+where
+    for<'a> &'a S: Read + Write,
+{
+    let answer = answer(&mut BufReader::new(&stream), events);
+    let _ = (&stream).write_all(answer.as_bytes());
+}
+
+fn answer(reader: &mut impl BufRead, events: &flume::Sender<Event>) -> String {
+    let mut line = String::new();
+    if let Err(err) = reader.read_line(&mut line) {
+        return format!("error: {err}\n");
+    }
+    // the rest of the line, the command of add contains spaces
+    let line = line.trim();
+    let (action, name) = line.split_once(' ').unwrap_or((line, ""));
+    let Ok(action) = <Action as clap::ValueEnum>::from_str(action, false) else {
+        return format!("error: unknown request {line:?}\n");
+    };
+    let (reply, answer) = flume::bounded(1);
+    let request = Request {
+        action,
+        name: name.trim().to_string(),
+        reply,
+    };
+    if events.send(Event::Control(request)).is_err() {
+        return "error: the session is ending\n".to_string();
+    }
+    answer
+        .recv_timeout(TIMEOUT)
+        .unwrap_or_else(|_| "error: no answer from the session\n".to_string())
+}
+
+/// Sends `request` to `session` and returns the answer, or why there is
+/// none.
+pub fn send(session: &str, request: &str) -> Result<String, String> {
+    let mut stream = connect(&socket(session))
+        .map_err(|err| format!("no session {session:?} is running ({err})"))?;
+    stream
+        .write_all(format!("{request}\n").as_bytes())
+        .map_err(|err| format!("cannot send the request: {err}"))?;
+    let mut answer = String::new();
+    stream
+        .read_to_string(&mut answer)
+        .map_err(|err| format!("cannot read the answer: {err}"))?;
+    Ok(answer)
+}
+
+/// Sends the request of `runall ctl` and prints the answer. Returns whether
+/// the request succeeded.
+pub fn ctl(args: CtlArgs) -> bool {
+    let session = args
+        .session
+        .clone()
+        .unwrap_or_else(crate::logs::default_session);
+    let action = format!("{:?}", args.action).to_lowercase();
+    let request = format!("{action} {}", args.name.unwrap_or_default());
+    let answer = send(&session, request.trim_end()).unwrap_or_else(|err| panic!("{err}"));
+    if answer.starts_with("error: ") {
+        eprint!("{answer}");
+        false
+    } else {
+        print!("{answer}");
+        true
+    }
+}
@@ -0,0 +1,213 @@
+//! `--daemon`: the session keeps running in the background, detached from
+//! the terminal. runall starts itself again with the same arguments, writes
+//! the pid of that copy to a pidfile and its output to a log file next to
+//! the `runall ctl` sockets. `runall status` and `runall stop` manage it
+//! like any other session, through its socket. With `--wait-ready` runall
+//! returns only once the commands in the background are ready, for scripts
+//! like `runall --wait-ready ... && ./run-tests`.
+
+use std::{
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use crate::{ctl, logs};
+
+/// Set for the copy of runall that runs in the background.
+pub const DETACHED: &str = "RUNALL_DETACHED";
+
+#[derive(clap::Args)]
+pub struct SessionArgs {
+    /// The session. Defaults to the name of the current directory, like
+    /// for the session itself.
+    #[clap(long)]
+    pub session: Option<String>,
+}
+
+fn path(session: &str, extension: &str) -> PathBuf {
+    let root = logs::root();
+    let dir = root.parent().unwrap_or(&root).join("daemon");
+    let name = logs::file_name(session);
+    dir.join(format!("{}.{extension}", name.trim_end_matches(".log")))
+}
+
+pub fn log_file(session: &str) -> PathBuf {
+    path(session, "log")
+}
+
+fn pidfile(session: &str) -> PathBuf {
+    path(session, "pid")
+}
+
+/// Whether this is the copy of runall in the background. The variable is
+/// removed so that the commands don't see it.
+pub fn detached() -> bool {
+    let detached = std::env::var_os(DETACHED).is_some();
+    std::env::remove_var(DETACHED);
+    detached
+}
+
+/// Starts runall with the same arguments in the background.
+pub fn detach(session: &str) -> Child {
+    if ctl::send(session, "status").is_ok() {
+        panic!("session {session:?} is already running, see runall status");
+    }
+    let log = log_file(session);
+    if let Some(dir) = log.parent() {
+        std::fs::create_dir_all(dir)
+            .unwrap_or_else(|err| panic!("cannot create {}: {err}", dir.display()));
+    }
+    let file = std::fs::File::create(&log)
+        .unwrap_or_else(|err| panic!("cannot create {}: {err}", log.display()));
+    let mut command = Command::new(std::env::current_exe().expect("path of runall"));
+    command
+        .args(std::env::args_os().skip(1))
+        .env(DETACHED, "1")
+        .stdin(Stdio::null())
+        .stdout(file.try_clone().expect("log file"))
+        .stderr(file);
+    // a session of its own, which the terminal closing doesn't end
+    #[cfg(unix)]
+    // SAFETY: setsid is async-signal-safe
+    unsafe {
+        std::os::unix::process::CommandExt::pre_exec(&mut command, || {
+            if libc::setsid() == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    #[cfg(windows)]
+    std::os::windows::process::CommandExt::creation_flags(
+        &mut command,
+        windows_sys::Win32::System::Threading::DETACHED_PROCESS
+            | windows_sys::Win32::System::Threading::CREATE_NEW_PROCESS_GROUP,
+    );
+    let child = command.spawn().expect("start runall in the background");
+    let _ = std::fs::write(pidfile(session), format!("{}\n", child.id()));
+    child
+}
+
+/// Leaves the session running in the background. Returns the exit code of
+/// runall.
+pub fn leave(session: &str, child: Child) -> i32 {
+    eprintln!(
+        "session {session} runs in the background (pid {}), its output goes to {}",
+        child.id(),
+        log_file(session).display()
+    );
+    0
+}
+
+/// `--wait-ready`: waits until the commands of the session in the
+/// background are ready. Stops the session if they aren't within `timeout`
+/// or one of them fails. Returns the exit code of runall, 0 if they got
+/// ready or all finished.
+pub fn wait_ready(session: &str, mut child: Child, timeout: Duration) -> i32 {
+    let started = Instant::now();
+    loop {
+        if let Ok(Some(status)) = child.try_wait() {
+            if status.success() {
+                eprintln!(
+                    "session {session} already finished, its output is in {}",
+                    log_file(session).display()
+                );
+                return 0;
+            }
+            eprintln!(
+                "session {session} ended ({status}) before it was ready, see {}",
+                log_file(session).display()
+            );
+            return 1;
+        }
+        // the socket doesn't exist until the session runs
+        if let Ok(answer) = ctl::send(session, "ready") {
+            if answer == "ready\n" {
+                return leave(session, child);
+            }
+            if let Some(err) = answer.strip_prefix("error: ") {
+                eprint!("session {session} is not ready, {err}");
+                break;
+            }
+        }
+        if started.elapsed() > timeout {
+            eprintln!(
+                "session {session} is not ready after {}",
+                crate::duration::format(timeout)
+            );
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    eprintln!(
+        "stopping it, its output is in {}",
+        log_file(session).display()
+    );
+    let _ = ctl::send(session, "quit");
+    let _ = child.wait();
+    1
+}
+
+/// Removes the pidfile when the session in the background ends.
+pub struct Pidfile(PathBuf);
+
+impl Pidfile {
+    pub fn new(session: &str) -> Self {
+        Self(pidfile(session))
+    }
+}
+
+impl Drop for Pidfile {
+    fn drop(&mut self) {
+        let ours = std::fs::read_to_string(&self.0)
+            .is_ok_and(|pid| pid.trim() == std::process::id().to_string());
+        if ours {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+}
+
+/// `runall status`: prints the status table of the session. Returns whether
+/// it is running.
+pub fn status(args: SessionArgs) -> bool {
+    let session = args.session.unwrap_or_else(logs::default_session);
+    match ctl::send(&session, "status") {
+        Ok(table) => {
+            if let Ok(pid) = std::fs::read_to_string(pidfile(&session)) {
+                println!(
+                    "session {session} runs in the background (pid {}), its output goes to {}",
+                    pid.trim(),
+                    log_file(&session).display()
+                );
+            }
+            print!("{table}");
+            true
+        }
+        Err(err) => {
+            eprintln!("{err}");
+            false
+        }
+    }
+}
+
+/// `runall stop`: ends the session and waits until it is over. Returns
+/// whether it was running.
+pub fn stop(args: SessionArgs) -> bool {
+    let session = args.session.unwrap_or_else(logs::default_session);
+    if let Err(err) = ctl::send(&session, "quit") {
+        eprintln!("{err}");
+        return false;
+    }
+    eprintln!("stopping session {session}");
+    // the socket goes away with the session
+    let started = Instant::now();
+    while ctl::send(&session, "status").is_ok() {
+        if started.elapsed() > Duration::from_secs(60) {
+            eprintln!("session {session} is still stopping, see runall status");
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    true
+}
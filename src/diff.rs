@@ -0,0 +1,99 @@
+//! `runall diff`: runs two commands side by side and compares their output.
+
+use std::{io::IsTerminal, thread};
+
+use similar::{ChangeTag, TextDiff};
+
+#[derive(clap::Args)]
+pub struct DiffArgs {
+    /// The command whose output is the "old" side of the diff.
+    pub a: String,
+
+    /// The command whose output is the "new" side of the diff.
+    pub b: String,
+
+    /// Sort the lines before comparing, for output in nondeterministic order.
+    #[clap(long)]
+    pub sort: bool,
+
+    /// Ignore color codes, trailing whitespace and differences in the amount
+    /// of whitespace.
+    #[clap(long)]
+    pub normalize: bool,
+
+    /// Number of unchanged lines shown around each change.
+    #[clap(short = 'U', long, default_value_t = 3)]
+    pub context: usize,
+}
+
+/// Runs `cmd` and returns its stdout; stderr goes to the terminal.
+fn capture(cmd: &str) -> String {
+    let output = crate::shell::command(cmd)
+        .and_then(|mut command| crate::reaper::output(&mut command))
+        .unwrap_or_else(|err| panic!("cannot start {cmd:?}: {err}"));
+    if !output.status.success() {
+        eprintln!("{cmd:?} {}", crate::runner::describe_exit(output.status));
+    }
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn prepare(output: &str, args: &DiffArgs) -> String {
+    let escapes = regex::Regex::new("\x1b\\[[0-9;?]*[ -/]*[@-~]").expect("valid regex");
+    let mut lines = output
+        .lines()
+        .map(|line| {
+            if args.normalize {
+                let line = escapes.replace_all(line, "");
+                line.split_whitespace().collect::<Vec<_>>().join(" ")
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<_>>();
+    if args.sort {
+        lines.sort();
+    }
+    lines.into_iter().map(|line| line + "\n").collect()
+}
+
+/// Prints a unified diff of the outputs of both commands and returns whether
+/// they were the same.
+pub fn diff(args: DiffArgs) -> bool {
+    let (a, b) = thread::scope(|scope| {
+        let a = scope.spawn(|| capture(&args.a));
+        let b = scope.spawn(|| capture(&args.b));
+        (a.join().expect("join"), b.join().expect("join"))
+    });
+    let (a, b) = (prepare(&a, &args), prepare(&b, &args));
+    if a == b {
+        return true;
+    }
+
+    let colored = std::env::var_os("NO_COLOR").is_none() && std::io::stdout().is_terminal();
+    let paint = |text: String, code: &str| {
+        if colored {
+            format!("\x1b[{code}m{text}\x1b[0m")
+        } else {
+            text
+        }
+    };
+    let diff = TextDiff::from_lines(&a, &b);
+    println!("{}", paint(format!("--- {}", args.a), "1"));
+    println!("{}", paint(format!("+++ {}", args.b), "1"));
+    for hunk in diff
+        .unified_diff()
+        .context_radius(args.context)
+        .iter_hunks()
+    {
+        println!("{}", paint(hunk.header().to_string(), "36"));
+        for change in hunk.iter_changes() {
+            let line = change.value().trim_end_matches('\n');
+            match change.tag() {
+                ChangeTag::Equal => println!(" {line}"),
+                ChangeTag::Delete => println!("{}", paint(format!("-{line}"), "31")),
+                ChangeTag::Insert => println!("{}", paint(format!("+{line}"), "32")),
+            }
+        }
+    }
+    false
+}
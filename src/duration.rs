@@ -0,0 +1,111 @@
+//! Parsing and formatting of human readable durations like `500ms`, `30s`,
+//! `4m12s` or `2h`, and of times of day like `18:30`.
+
+use std::time::Duration;
+
+pub fn parse(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Err("empty duration".to_string());
+    }
+    let mut total = Duration::ZERO;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(rest.len());
+        let (number, tail) = rest.split_at(digits);
+        let unit_len = tail
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_len);
+        let number: f64 = number
+            .parse()
+            .map_err(|_| format!("invalid duration {s:?}"))?;
+        let secs = match unit {
+            "ms" => number / 1000.0,
+            "" | "s" => number,
+            "m" => number * 60.0,
+            "h" => number * 3600.0,
+            "d" => number * 86400.0,
+            _ => return Err(format!("invalid duration unit {unit:?} in {s:?}")),
+        };
+        total += Duration::from_secs_f64(secs);
+        rest = tail;
+    }
+    Ok(total)
+}
+
+/// A time of day, `HH:MM` or `HH:MM:SS`.
+pub fn parse_time_of_day(s: &str) -> Result<chrono::NaiveTime, String> {
+    chrono::NaiveTime::parse_from_str(s, "%H:%M:%S")
+        .or_else(|_| chrono::NaiveTime::parse_from_str(s, "%H:%M"))
+        .map_err(|_| format!("invalid time {s:?}, expected HH:MM or HH:MM:SS"))
+}
+
+/// How long until the next `time` of day, today or tomorrow.
+pub fn until(time: chrono::NaiveTime) -> Duration {
+    let now = chrono::Local::now().naive_local();
+    let mut at = now.date().and_time(time);
+    if at <= now {
+        at += chrono::Duration::days(1);
+    }
+    (at - now).to_std().unwrap_or_default()
+}
+
+/// Formats `d` compactly, e.g. `850ms`, `12.3s`, `4m12s` or `1h02m`.
+pub fn format(d: Duration) -> String {
+    let secs = d.as_secs();
+    if secs < 1 {
+        format!("{}ms", d.as_millis())
+    } else if secs < 60 {
+        format!("{:.1}s", d.as_secs_f64())
+    } else if secs < 3600 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h{:02}m", secs / 3600, secs % 3600 / 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse("30"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse(" 30s "), Ok(Duration::from_secs(30)));
+        assert_eq!(parse("1.5s"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse("4m12s"), Ok(Duration::from_secs(252)));
+        assert_eq!(parse("2h"), Ok(Duration::from_secs(7200)));
+        assert_eq!(parse("1d1h"), Ok(Duration::from_secs(90000)));
+    }
+
+    #[test]
+    fn rejects_invalid_durations() {
+        assert!(parse("").is_err());
+        assert!(parse("s").is_err());
+        assert!(parse("abc").is_err());
+        assert!(parse("5x").is_err());
+        assert!(parse("1..2s").is_err());
+        assert!(parse("-1s").is_err());
+    }
+
+    #[test]
+    fn parses_times_of_day() {
+        let time = |h, m, s| chrono::NaiveTime::from_hms_opt(h, m, s);
+        assert_eq!(parse_time_of_day("18:30").ok(), time(18, 30, 0));
+        assert_eq!(parse_time_of_day("07:05:09").ok(), time(7, 5, 9));
+        assert!(parse_time_of_day("24:00").is_err());
+        assert!(parse_time_of_day("6pm").is_err());
+    }
+
+    #[test]
+    fn formats_durations() {
+        assert_eq!(format(Duration::from_millis(850)), "850ms");
+        assert_eq!(format(Duration::from_millis(12_340)), "12.3s");
+        assert_eq!(format(Duration::from_secs(252)), "4m12s");
+        assert_eq!(format(Duration::from_secs(3720)), "1h02m");
+    }
+}
@@ -0,0 +1,212 @@
+//! Running a session from another program. The output of the commands is
+//! reported as [`Event`]s instead of being printed.
+//!
+//! ```no_run
+//! let handle = runall::Runner::new()
+//!     .add_command("web", "npm run dev")
+//!     .add_command("api", "cargo run")
+//!     .fail_fast(true)
+//!     .spawn();
+//! for event in handle.events().iter() {
+//!     if let runall::embed::Event::Line { name, line, .. } = event {
+//!         println!("{name}: {line}");
+//!     }
+//! }
+//! let statuses = handle.wait();
+//! ```
+
+use std::{
+    process::ExitStatus,
+    sync::atomic::{AtomicBool, Ordering},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use crate::{logs, prefix, runner, Job};
+
+static NOTICES: AtomicBool = AtomicBool::new(true);
+
+/// Whether runall prints its own messages ("starting ...", "exited with
+/// ...") to stderr.
+pub fn notices() -> bool {
+    NOTICES.load(Ordering::Relaxed)
+}
+
+#[derive(Debug)]
+pub enum Event {
+    Started {
+        name: String,
+        pid: u32,
+    },
+    /// A line of output, without the line break.
+    Line {
+        name: String,
+        stream: Stream,
+        line: String,
+    },
+    Exited {
+        name: String,
+        status: ExitStatus,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// Builds a session of shell commands, see [`Runner::spawn`].
+#[derive(Default)]
+pub struct Runner {
+    jobs: Vec<Job>,
+    fail_fast: bool,
+    kill_others: bool,
+    max_running: Option<usize>,
+    kill_timeout: Option<Duration>,
+    notices: bool,
+}
+
+impl Runner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a command, run with bash (cmd on windows).
+    pub fn add_command(mut self, name: impl ToString, cmd: impl ToString) -> Self {
+        self.jobs.push(Job::new(name, cmd));
+        self
+    }
+
+    /// Stop all commands as soon as one fails.
+    pub fn fail_fast(mut self, fail_fast: bool) -> Self {
+        self.fail_fast = fail_fast;
+        self
+    }
+
+    /// Stop all commands as soon as one exits.
+    pub fn kill_others(mut self, kill_others: bool) -> Self {
+        self.kill_others = kill_others;
+        self
+    }
+
+    /// Run at most `n` commands at the same time.
+    pub fn max_running(mut self, n: usize) -> Self {
+        self.max_running = Some(n);
+        self
+    }
+
+    /// How long stopped commands get before they are killed, 10s by
+    /// default.
+    pub fn kill_timeout(mut self, timeout: Duration) -> Self {
+        self.kill_timeout = Some(timeout);
+        self
+    }
+
+    /// Print runall's own messages to stderr, off by default.
+    pub fn notices(mut self, notices: bool) -> Self {
+        self.notices = notices;
+        self
+    }
+
+    /// Starts the commands in a background thread.
+    pub fn spawn(self) -> Handle {
+        NOTICES.store(self.notices, Ordering::Relaxed);
+        let (events_tx, events) = flume::unbounded();
+        let (stop, stop_rx) = flume::bounded(1);
+        let options = runner::Options {
+            max_running: self.max_running,
+            lock_limits: Default::default(),
+            prefix_format: prefix::DEFAULT_FORMAT.to_string(),
+            prefix_width: None,
+            still_running: None,
+            kill_timeout: self.kill_timeout.unwrap_or(Duration::from_secs(10)),
+            keep_open: false,
+            no_ctrlc_handler: false,
+            forward_ctrlc: false,
+            hyperlinks: false,
+            highlight: None,
+            log_dir: None,
+            fifo_dir: None,
+            pty: false,
+            line_buffer: false,
+            log_streams: logs::Streams::Merged,
+            log_mode: logs::Mode::Append,
+            log_max_size: None,
+            stats_export: None,
+            checksum: false,
+            interactive: false,
+            fail_fast: self.fail_fast,
+            kill_others: self.kill_others,
+            continue_on_error: false,
+            dedup_lines: false,
+            open_urls: false,
+            compact: false,
+            ordered: false,
+            failure_context: 0,
+            page_summary: false,
+            triage: false,
+            summary: false,
+            tui: false,
+            grouped: false,
+            raw: false,
+            stats: false,
+            stats_bar: false,
+            input_target: None,
+            primary: None,
+            heartbeat: None,
+            ctl: None,
+            stop_after: None,
+            idle_timeout: None,
+            budget: Default::default(),
+            exit_codes: None,
+            mux: None,
+            #[cfg(feature = "broker")]
+            events: None,
+            ship_logs: None,
+            embed: Some(runner::Embed {
+                events: events_tx,
+                stop: stop_rx,
+            }),
+        };
+        let jobs = self.jobs;
+        let thread = std::thread::spawn(move || {
+            let outcome = runner::run(&jobs, options);
+            jobs.into_iter()
+                .map(|job| job.name)
+                .zip(outcome.statuses)
+                .collect()
+        });
+        Handle {
+            events,
+            stop,
+            thread,
+        }
+    }
+}
+
+/// A running session.
+pub struct Handle {
+    events: flume::Receiver<Event>,
+    stop: flume::Sender<()>,
+    thread: JoinHandle<Vec<(String, Option<ExitStatus>)>>,
+}
+
+impl Handle {
+    /// The events of the session. The channel is closed once all commands
+    /// exited.
+    pub fn events(&self) -> &flume::Receiver<Event> {
+        &self.events
+    }
+
+    /// Stops all commands, like ctrl-c does for the command line.
+    pub fn stop(&self) {
+        let _ = self.stop.try_send(());
+    }
+
+    /// Waits until all commands exited. Returns the exit status of every
+    /// command, `None` for commands that never ran.
+    pub fn wait(self) -> Vec<(String, Option<ExitStatus>)> {
+        self.thread.join().expect("session thread")
+    }
+}
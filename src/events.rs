@@ -0,0 +1,73 @@
+//! Lifecycle events (a process started, became ready, exited, ...) as JSON
+//! objects, for consumers outside of runall's console output.
+
+use std::process::ExitStatus;
+
+use serde_json::{json, Map, Value};
+
+fn hostname() -> String {
+    #[cfg(unix)]
+    {
+        nix::unistd::gethostname()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+    #[cfg(not(unix))]
+    {
+        std::env::var("COMPUTERNAME").unwrap_or_default()
+    }
+}
+
+/// Identifies the runall session the events come from.
+pub struct Source {
+    session: String,
+    host: String,
+    pid: u32,
+}
+
+impl Source {
+    pub fn new(session: impl ToString) -> Self {
+        Self {
+            session: session.to_string(),
+            host: hostname(),
+            pid: std::process::id(),
+        }
+    }
+
+    /// [`event`] with `"session"`, `"host"` and `"runall_pid"`.
+    pub fn event(&self, kind: &str, name: &str, fields: Value) -> Value {
+        let mut event = event(kind, name, fields);
+        if let Value::Object(event) = &mut event {
+            event.insert("session".into(), self.session.clone().into());
+            event.insert("host".into(), self.host.clone().into());
+            event.insert("runall_pid".into(), self.pid.into());
+        }
+        event
+    }
+}
+
+/// `{"ts": ..., "event": kind, "name": name}` plus `fields`.
+pub fn event(kind: &str, name: &str, fields: Value) -> Value {
+    let mut event = Map::new();
+    event.insert("ts".into(), chrono::Local::now().to_rfc3339().into());
+    event.insert("event".into(), kind.into());
+    event.insert("name".into(), name.into());
+    if let Value::Object(fields) = fields {
+        event.extend(fields);
+    }
+    Value::Object(event)
+}
+
+/// The fields of an `exited` event.
+pub fn exit_fields(status: ExitStatus, duration: std::time::Duration) -> Value {
+    #[cfg(unix)]
+    let signal = std::os::unix::process::ExitStatusExt::signal(&status);
+    #[cfg(not(unix))]
+    let signal = None::<i32>;
+    json!({
+        "code": status.code(),
+        "signal": signal,
+        "success": status.success(),
+        "duration_ms": duration.as_millis() as u64,
+    })
+}
@@ -0,0 +1,154 @@
+//! `runall export`: editor tasks for the commands of a runall.toml, a task
+//! per command and one for all of them, each running runall with the
+//! config. VS Code gets a `.vscode/tasks.json`, IntelliJ IDEs a shell run
+//! configuration per task in `.run`.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde_json::json;
+
+use crate::{config, template::shell_quote};
+
+#[derive(clap::Args)]
+pub struct ExportArgs {
+    /// The editor to write the tasks for.
+    #[clap(long, value_enum)]
+    pub format: Format,
+
+    /// The config whose commands become tasks.
+    #[clap(long, value_name = "PATH", default_value = config::DEFAULT_FILE)]
+    pub config: PathBuf,
+
+    /// The project directory the tasks are written to.
+    #[clap(short, long, value_name = "DIR", default_value = ".")]
+    pub output: PathBuf,
+
+    /// Overwrite existing task files.
+    #[clap(long)]
+    pub force: bool,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum Format {
+    VscodeTasks,
+    Intellij,
+}
+
+/// A task: its label, what it runs and the arguments of runall.
+struct Task {
+    label: String,
+    detail: String,
+    args: Vec<String>,
+}
+
+pub fn export(args: ExportArgs) {
+    let (_, entries) = config::load(std::slice::from_ref(&args.config));
+    if entries.is_empty() {
+        panic!("no commands in {}", args.config.display());
+    }
+    let config = args.config.to_string_lossy().to_string();
+    let names = entries.iter().map(|(name, _)| name.clone());
+    let mut tasks = vec![Task {
+        label: "runall: all".to_string(),
+        detail: names.collect::<Vec<_>>().join(", "),
+        args: vec!["--config".to_string(), config.clone()],
+    }];
+    tasks.extend(entries.into_iter().map(|(name, entry)| Task {
+        label: format!("runall: {name}"),
+        detail: entry.cmd,
+        args: vec!["--config".to_string(), config.clone(), name],
+    }));
+    let files = match args.format {
+        Format::VscodeTasks => vec![(PathBuf::from(".vscode/tasks.json"), vscode(&tasks))],
+        Format::Intellij => tasks
+            .iter()
+            .map(|task| {
+                let file = format!("{}.run.xml", task.label.replace(": ", " "));
+                (Path::new(".run").join(file), intellij(task))
+            })
+            .collect(),
+    };
+    for (file, _) in &files {
+        let path = args.output.join(file);
+        if path.exists() && !args.force {
+            panic!(
+                "{} already exists, use --force to overwrite it",
+                path.display()
+            );
+        }
+    }
+    for (file, content) in &files {
+        let path = args.output.join(file);
+        let written = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(&path, content));
+        if let Err(err) = written {
+            panic!("cannot write {}: {err}", path.display());
+        }
+        eprintln!("wrote {}", path.display());
+    }
+}
+
+fn vscode(tasks: &[Task]) -> String {
+    let tasks = tasks
+        .iter()
+        .map(|task| {
+            json!({
+                "label": task.label,
+                "detail": task.detail,
+                "type": "process",
+                "command": "runall",
+                "args": task.args,
+                "options": { "cwd": "${workspaceFolder}" },
+                "presentation": { "panel": "dedicated" },
+                "problemMatcher": [],
+            })
+        })
+        .collect::<Vec<_>>();
+    let file = json!({ "version": "2.0.0", "tasks": tasks });
+    let json = serde_json::to_string_pretty(&file).expect("serialize tasks");
+    format!("{json}\n")
+}
+
+fn intellij(task: &Task) -> String {
+    let words = task.args.iter().map(|arg| shell_quote(arg));
+    let script = format!("runall {}", words.collect::<Vec<_>>().join(" "));
+    let options = [
+        ("SCRIPT_TEXT", script.as_str()),
+        ("INDEPENDENT_SCRIPT_PATH", "true"),
+        ("SCRIPT_PATH", ""),
+        ("SCRIPT_OPTIONS", ""),
+        ("INDEPENDENT_SCRIPT_WORKING_DIRECTORY", "true"),
+        ("SCRIPT_WORKING_DIRECTORY", "$PROJECT_DIR$"),
+        ("INDEPENDENT_INTERPRETER_PATH", "true"),
+        ("INTERPRETER_PATH", ""),
+        ("INTERPRETER_OPTIONS", ""),
+        ("EXECUTE_IN_TERMINAL", "true"),
+        ("EXECUTE_SCRIPT_FILE", "false"),
+    ];
+    let mut xml = String::from("<component name=\"ProjectRunConfigurationManager\">\n");
+    xml.push_str(&format!(
+        "  <configuration default=\"false\" name=\"{}\" type=\"ShConfigurationType\">\n",
+        escape(&task.label)
+    ));
+    for (name, value) in options {
+        xml.push_str(&format!(
+            "    <option name=\"{name}\" value=\"{}\" />\n",
+            escape(value)
+        ));
+    }
+    xml.push_str("    <envs />\n    <method v=\"2\" />\n  </configuration>\n</component>\n");
+    xml
+}
+
+/// For XML attribute values.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
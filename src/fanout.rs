@@ -0,0 +1,237 @@
+//! Generation of jobs from a command template and a list of inputs
+//! (`--each`, `--from-csv`, `--template`, `:::`).
+
+use std::{collections::HashMap, path::Path};
+
+use crate::{template, Job};
+
+/// Creates one job per command and item. Unless there is a `name_template`,
+/// jobs are named after the item, qualified with the command number when
+/// there are several commands.
+fn cross<T>(
+    commands: &[String],
+    items: &[T],
+    name_template: Option<&str>,
+    name: impl Fn(&T) -> String,
+    fill: impl Fn(&str, &T) -> String,
+) -> Vec<Job> {
+    commands
+        .iter()
+        .enumerate()
+        .flat_map(|(i, cmd)| items.iter().map(move |item| (i, cmd, item)))
+        .enumerate()
+        .map(|(n, (i, cmd, item))| {
+            let input = name(item);
+            let name = match name_template {
+                Some(template) => template::fill_name(template, n + 1, cmd, Some(&input)),
+                None if commands.len() == 1 => input,
+                None => format!("cmd-{}:{input}", i + 1),
+            };
+            Job::new(name, fill(cmd, item))
+        })
+        .collect()
+}
+
+pub fn each(pattern: &str, commands: &[String], name_template: Option<&str>) -> Vec<Job> {
+    let files = glob::glob(pattern)
+        .unwrap_or_else(|err| panic!("invalid glob {pattern:?}: {err}"))
+        .flatten()
+        .map(|file| file.to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    if files.is_empty() {
+        eprintln!("no files match {pattern:?}");
+    }
+    inputs(commands, &files, name_template)
+}
+
+/// One job per command and input, like `parallel CMD ::: INPUTS`.
+pub fn inputs(commands: &[String], inputs: &[String], name_template: Option<&str>) -> Vec<Job> {
+    cross(
+        commands,
+        inputs,
+        name_template,
+        |input| input.clone(),
+        |cmd, input| template::fill_input(cmd, input),
+    )
+}
+
+/// The inputs of `--template`: the comma separated `values` with their
+/// brace groups expanded, followed by the lines of `file`.
+pub fn input_list(values: &[String], file: Option<&Path>) -> Vec<String> {
+    let mut inputs = Vec::new();
+    for value in values {
+        for input in split_commas(value) {
+            inputs.extend(template::expand_braces(input).into_iter().map(|(i, _)| i));
+        }
+    }
+    if let Some(file) = file {
+        let content = std::fs::read_to_string(file)
+            .unwrap_or_else(|err| panic!("cannot read {}: {err}", file.display()));
+        inputs.extend(
+            content
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::to_string),
+        );
+    }
+    inputs
+}
+
+/// Splits at the commas that are not inside a brace group.
+fn split_commas(value: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    for (i, c) in value.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&value[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&value[start..]);
+    parts.into_iter().filter(|part| !part.is_empty()).collect()
+}
+
+/// One job per row of a CSV file (or TSV if the file ends in `.tsv`), with
+/// the `{column}` placeholders of the commands filled from the row.
+pub fn csv(
+    path: &Path,
+    name_column: Option<&str>,
+    commands: &[String],
+    name_template: Option<&str>,
+) -> Vec<Job> {
+    let tsv = path.extension().is_some_and(|ext| ext == "tsv");
+    let mut reader = ::csv::ReaderBuilder::new()
+        .delimiter(if tsv { b'\t' } else { b',' })
+        .from_path(path)
+        .unwrap_or_else(|err| panic!("cannot read {}: {err}", path.display()));
+    let headers = reader
+        .headers()
+        .expect("read csv header")
+        .iter()
+        .map(|h| h.trim().to_string())
+        .collect::<Vec<_>>();
+
+    let name_column = name_column.unwrap_or_else(|| headers.first().map_or("", |h| h.as_str()));
+    if !headers.iter().any(|h| h == name_column) {
+        panic!("{} has no column {name_column:?}", path.display());
+    }
+
+    let rows = reader
+        .records()
+        .map(|record| {
+            let record =
+                record.unwrap_or_else(|err| panic!("cannot read {}: {err}", path.display()));
+            headers
+                .iter()
+                .cloned()
+                .zip(record.iter().map(|v| v.to_string()))
+                .collect::<HashMap<_, _>>()
+        })
+        .collect::<Vec<_>>();
+
+    cross(
+        commands,
+        &rows,
+        name_template,
+        |row| row[name_column].clone(),
+        |cmd, row| {
+            template::fill_fields(cmd, |field| row.get(field).cloned())
+                .unwrap_or_else(|err| panic!("{}: {err}", path.display()))
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    fn named(jobs: &[Job]) -> Vec<(&str, &str)> {
+        jobs.iter()
+            .map(|job| (job.name.as_str(), job.cmd.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn names_jobs_after_their_input() {
+        let jobs = inputs(&strings(&["gzip"]), &strings(&["a.txt", "b.txt"]), None);
+        assert_eq!(
+            named(&jobs),
+            [("a.txt", "gzip a.txt"), ("b.txt", "gzip b.txt")]
+        );
+    }
+
+    #[test]
+    fn qualifies_names_with_the_command_number() {
+        let commands = strings(&["gzip", "wc -l {}"]);
+        let jobs = inputs(&commands, &strings(&["a", "b"]), None);
+        assert_eq!(
+            named(&jobs),
+            [
+                ("cmd-1:a", "gzip a"),
+                ("cmd-1:b", "gzip b"),
+                ("cmd-2:a", "wc -l a"),
+                ("cmd-2:b", "wc -l b"),
+            ]
+        );
+    }
+
+    #[test]
+    fn names_jobs_from_the_name_template() {
+        let commands = strings(&["gzip", "wc"]);
+        let jobs = inputs(&commands, &strings(&["a", "b"]), Some("{i}-{cmd}-{input}"));
+        let names = jobs.iter().map(|job| job.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["1-gzip-a", "2-gzip-b", "3-wc-a", "4-wc-b"]);
+    }
+
+    #[test]
+    fn names_csv_rows_after_the_name_column() {
+        let path = std::env::temp_dir().join(format!("runall-hosts-{}.csv", std::process::id()));
+        std::fs::write(&path, "host, port\nweb,80\ndb,5432\n").expect("write csv");
+        let commands = strings(&["nc -z {host} {port}"]);
+        let by_host = csv(&path, None, &commands, None);
+        let by_port = csv(&path, Some("port"), &commands, None);
+        std::fs::remove_file(&path).expect("remove csv");
+        assert_eq!(
+            named(&by_host),
+            [("web", "nc -z web 80"), ("db", "nc -z db 5432")]
+        );
+        let ports = by_port
+            .iter()
+            .map(|job| job.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ports, ["80", "5432"]);
+    }
+
+    #[test]
+    fn splits_commas_outside_brace_groups() {
+        assert_eq!(split_commas("a,b{1,2},,c"), ["a", "b{1,2}", "c"]);
+        assert_eq!(split_commas(""), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn lists_inputs_from_values_and_file() {
+        let path = std::env::temp_dir().join(format!("runall-inputs-{}", std::process::id()));
+        std::fs::write(
+            &path,
+            "# hosts
+web
+
+  db  
+",
+        )
+        .expect("write inputs");
+        let inputs = input_list(&strings(&["a,x{1,2}", "b"]), Some(&path));
+        std::fs::remove_file(&path).expect("remove inputs");
+        assert_eq!(inputs, ["a", "x1", "x2", "b", "web", "db"]);
+    }
+}
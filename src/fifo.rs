@@ -0,0 +1,101 @@
+//! `--fifo-dir`: a named pipe per command that carries its output without
+//! prefixes, for `cat dir/web.out` in another terminal or a dashboard.
+//!
+//! Nobody has to read the pipes: output is only written while a reader is
+//! connected, and lines a slow reader can't take are dropped instead of
+//! holding up the session.
+
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+pub struct Fifo {
+    path: PathBuf,
+    /// Open while a reader is connected.
+    file: Mutex<Option<File>>,
+}
+
+impl Fifo {
+    /// Creates `dir/<name>.out`, replacing what is left from an earlier
+    /// session.
+    pub fn create(dir: &Path, name: &str) -> Self {
+        std::fs::create_dir_all(dir)
+            .unwrap_or_else(|err| panic!("cannot create {}: {err}", dir.display()));
+        let log = crate::logs::file_name(name);
+        let path = dir.join(format!("{}.out", log.trim_end_matches(".log")));
+        let _ = std::fs::remove_file(&path);
+        make_fifo(&path).unwrap_or_else(|err| panic!("cannot create {}: {err}", path.display()));
+        Self {
+            path,
+            file: Mutex::new(None),
+        }
+    }
+
+    /// Writes a line if somebody reads the pipe.
+    pub fn write(&self, line: &str) {
+        let mut file = self.file.lock().expect("lock");
+        if file.is_none() {
+            *file = open(&self.path);
+        }
+        let Some(pipe) = file.as_mut() else {
+            return;
+        };
+        let line = line.strip_suffix('\n').unwrap_or(line);
+        match pipe.write_all(format!("{line}\n").as_bytes()) {
+            Ok(()) => {}
+            // the reader is behind, it misses the line
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {}
+            // the reader is gone, wait for the next one
+            Err(_) => *file = None,
+        }
+    }
+}
+
+impl Drop for Fifo {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(unix)]
+fn make_fifo(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
+    // SAFETY: the path is a valid C string for the duration of the call
+    if unsafe { libc::mkfifo(path.as_ptr(), 0o600) } == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(unix))]
+fn make_fifo(_: &Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "named pipes are only supported on unix",
+    ))
+}
+
+/// Opens the pipe for writing without waiting for a reader, fails if there
+/// is none.
+#[cfg(unix)]
+fn open(path: &Path) -> Option<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    std::fs::OpenOptions::new()
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)
+        .ok()
+}
+
+#[cfg(not(unix))]
+fn open(_: &Path) -> Option<File> {
+    None
+}
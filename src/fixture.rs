@@ -0,0 +1,62 @@
+//! `runall with`: the fixtures of a command, like the database and the
+//! services its tests talk to. The commands of the config are started, once
+//! the services are ready and the tasks (migrations, seeding) succeeded the
+//! given command runs in the foreground, and when it is done the services
+//! are stopped. runall exits with the exit code of the command.
+
+use std::path::PathBuf;
+
+use crate::{
+    config::{self, Entry, Settings},
+    template::shell_quote,
+};
+
+#[derive(clap::Args)]
+pub struct WithArgs {
+    /// The config with the fixtures. Given more than once the files are
+    /// merged, like for --config of the session.
+    #[clap(long, value_name = "FILE", default_value = config::DEFAULT_FILE)]
+    pub config: Vec<PathBuf>,
+
+    /// The name of the command in the output. Defaults to the program it
+    /// runs.
+    #[clap(long)]
+    pub name: Option<String>,
+
+    /// The command to run with the fixtures, after `--`.
+    #[clap(last = true, required = true, value_name = "COMMAND")]
+    pub command: Vec<String>,
+}
+
+/// The fixtures and the command as config entries, and the name of the
+/// command.
+pub fn entries(args: &WithArgs) -> (Settings, Vec<(String, Entry)>, String) {
+    let (mut settings, mut entries) = config::load(&args.config);
+    let name = args.name.clone().unwrap_or_else(|| {
+        let program = std::path::Path::new(&args.command[0]).file_stem();
+        program.map_or("command".to_string(), |p| p.to_string_lossy().to_string())
+    });
+    if entries.iter().any(|(fixture, _)| *fixture == name) {
+        panic!("a fixture is named {name:?} as well, use --name to name the command");
+    }
+    let service = |entry: &Entry| entry.kind.as_deref() == Some("service");
+    let (services, tasks) = entries
+        .iter()
+        .partition::<Vec<_>, _>(|(_, entry)| service(entry));
+    let names = |fixtures: Vec<&(String, Entry)>| {
+        fixtures
+            .into_iter()
+            .map(|(fixture, _)| fixture.clone())
+            .collect()
+    };
+    let cmd = args.command.iter().map(|word| shell_quote(word));
+    let entry = Entry {
+        cmd: cmd.collect::<Vec<_>>().join(" "),
+        needs: names(services),
+        after: names(tasks),
+        ..Default::default()
+    };
+    entries.push((name.clone(), entry));
+    settings.success = Some(name.clone());
+    (settings, entries, name)
+}
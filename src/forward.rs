@@ -0,0 +1,482 @@
+//! Forwarding of child output to runall's stdout.
+
+use std::{
+    borrow::Cow,
+    collections::VecDeque,
+    io::{Read, Write},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use sha2::Digest;
+
+use crate::{
+    ansi, embed, level, logs, mux, prefix::Prefix, probe, redact, split, throttle::Throttle,
+};
+
+/// What the output forwarding threads of a process need.
+#[derive(Clone)]
+pub struct Forward {
+    pub prefix: Prefix,
+    pub last_output: Arc<Mutex<Instant>>,
+    /// The last line that wasn't empty.
+    pub last_line: Arc<Mutex<String>>,
+    /// Lines printed by the process, on both streams.
+    pub lines: Arc<AtomicU64>,
+    /// Output encoding of the process if it is not UTF-8.
+    pub encoding: Option<&'static encoding_rs::Encoding>,
+    /// Keep OSC-8 hyperlinks instead of reducing them to their text.
+    pub hyperlinks: bool,
+    /// Whether output is printed at all, otherwise it only goes to the logs.
+    pub visible: Arc<AtomicBool>,
+    /// Log files the stream is written to.
+    pub logs: Vec<logs::LogFile>,
+    /// `--fifo-dir`, shared by both streams.
+    pub fifo: Option<Arc<crate::fifo::Fifo>>,
+    /// `--ship-logs`.
+    pub ship: Option<crate::ship::Stream>,
+    /// Hash of the raw bytes of the stream (`--checksum`).
+    pub checksum: Option<Arc<Mutex<sha2::Sha256>>>,
+    /// The last lines of both streams, see `--failure-context`.
+    pub tail: Option<Tail>,
+    pub throttle: Option<Arc<Mutex<Throttle>>>,
+    /// Lines below that level are not printed.
+    pub min_level: Arc<Mutex<Option<level::Level>>>,
+    /// Only lines matching this are printed, see `--filter`.
+    pub filter: Option<regex::Regex>,
+    /// Lines matching this are not printed, see `--exclude`.
+    pub exclude: Option<regex::Regex>,
+    /// Matches of this are shown in reverse video, see `--highlight`.
+    pub highlight: Option<regex::Regex>,
+    /// `--wait-for NAME=log:REGEX` probes that look at the lines.
+    pub log_probes: Vec<probe::LogProbe>,
+    /// Print repeated identical lines only once, followed by a count.
+    pub dedup: bool,
+    pub compact: Option<Compact>,
+    pub ordered: Option<Ordered>,
+    /// Lines go to the embedding program instead of stdout.
+    pub sink: Option<Sink>,
+    /// `--output json`: the name and stream the lines are attributed to.
+    pub json: Option<(String, embed::Stream)>,
+    /// Gets the raw bytes, see `--mux-fd`.
+    pub mux: Option<mux::Stream>,
+    /// Lines go to the pane of the process with this index (`--tui`).
+    pub tui: Option<usize>,
+    /// `--raw`: the bytes go unchanged to runall's stdout or stderr as they
+    /// are read, the lines only to the logs and probes.
+    pub raw: Option<embed::Stream>,
+    /// `--grouped`: the output of both streams, printed once the process
+    /// exited.
+    pub grouped: Option<Arc<Mutex<String>>>,
+}
+
+#[derive(Clone)]
+pub struct Sink {
+    pub events: flume::Sender<embed::Event>,
+    pub name: String,
+    pub stream: embed::Stream,
+}
+
+/// The last `max` lines of a process, shown when it fails.
+#[derive(Clone)]
+pub struct Tail {
+    lines: Arc<Mutex<VecDeque<String>>>,
+    max: usize,
+}
+
+impl Tail {
+    pub fn new(max: usize) -> Self {
+        Self {
+            lines: Default::default(),
+            max,
+        }
+    }
+
+    fn push(&self, line: &str) {
+        let mut lines = self.lines.lock().expect("lock");
+        if lines.len() == self.max {
+            lines.pop_front();
+        }
+        lines.push_back(line.trim_end_matches(['\r', '\n']).to_string());
+    }
+
+    pub fn take(&self) -> Vec<String> {
+        std::mem::take(&mut *self.lines.lock().expect("lock")).into()
+    }
+}
+
+/// `--compact`: lines are printed without prefix, below a header line that
+/// is repeated whenever the output switches to another process.
+#[derive(Clone)]
+pub struct Compact {
+    pub idx: usize,
+    /// The process that printed the last line, shared by all forwarders.
+    pub last: Arc<Mutex<Option<usize>>>,
+}
+
+/// `--ordered`: the output of a process is only printed live while all
+/// processes before it have finished, until then it is held back.
+#[derive(Clone)]
+pub struct Ordered {
+    pub idx: usize,
+    pub release: Arc<Mutex<Release>>,
+}
+
+/// The output held back by `--ordered`, shared by all forwarders.
+#[derive(Default)]
+pub struct Release {
+    /// The first process that didn't finish, its output is printed live.
+    next: usize,
+    finished: Vec<bool>,
+    held: Vec<String>,
+}
+
+impl Release {
+    /// Marks the process as finished and prints the held output of the
+    /// processes that are next in line.
+    pub fn finish(&mut self, idx: usize) {
+        if self.finished.len() <= idx {
+            self.finished.resize(idx + 1, false);
+        }
+        self.finished[idx] = true;
+        while self.finished.get(self.next) == Some(&true) {
+            self.next += 1;
+            if let Some(held) = self.held.get_mut(self.next) {
+                crate::output::out(&std::mem::take(held));
+            }
+        }
+    }
+
+    fn hold(&mut self, idx: usize, text: String) {
+        if self.held.len() <= idx {
+            self.held.resize(idx + 1, String::new());
+        }
+        self.held[idx].push_str(&text);
+    }
+}
+
+impl Forward {
+    /// Whether `line` passes `--filter` and `--exclude`. The colors of the
+    /// line don't count.
+    fn matches(&self, line: &str) -> bool {
+        if self.filter.is_none() && self.exclude.is_none() {
+            return true;
+        }
+        let plain = level::strip_colors(line);
+        self.filter
+            .as_ref()
+            .is_none_or(|filter| filter.is_match(&plain))
+            && !self
+                .exclude
+                .as_ref()
+                .is_some_and(|exclude| exclude.is_match(&plain))
+    }
+
+    /// `line` with the `--highlight` matches in reverse video, which keeps
+    /// the colors of the line.
+    fn highlight<'a>(&self, line: &'a str) -> Cow<'a, str> {
+        match &self.highlight {
+            Some(highlight) => highlight.replace_all(line, "\x1b[7m$0\x1b[27m"),
+            None => Cow::Borrowed(line),
+        }
+    }
+
+    /// Prints a line of output, or a notice about the output, attributed to
+    /// the process.
+    fn print(&self, text: &str) {
+        if self.raw.is_some() {
+            return;
+        }
+        if let Some(idx) = self.tui {
+            crate::tui::line(idx, text);
+            return;
+        }
+        if let Some(sink) = &self.sink {
+            let _ = sink.events.send(embed::Event::Line {
+                name: sink.name.clone(),
+                stream: sink.stream,
+                line: text.trim_end_matches(['\r', '\n']).to_string(),
+            });
+            return;
+        }
+        let newline = if text.ends_with('\n') { "" } else { "\n" };
+        let out = match (&self.json, &self.compact) {
+            (Some((name, stream)), _) => {
+                let stream = match stream {
+                    embed::Stream::Stdout => "stdout",
+                    embed::Stream::Stderr => "stderr",
+                };
+                let line = text.trim_end_matches(['\r', '\n']);
+                let fields = serde_json::json!({"stream": stream, "line": line});
+                format!("{}\n", crate::events::event("line", name, fields))
+            }
+            (None, Some(compact)) => {
+                let mut last = compact.last.lock().expect("lock");
+                let mut out = String::new();
+                if *last != Some(compact.idx) && !self.prefix.is_empty() {
+                    out = format!("{}\n", self.prefix.render().trim_end());
+                    *last = Some(compact.idx);
+                }
+                format!("{out}{text}{newline}")
+            }
+            (None, None) if self.prefix.is_empty() => format!("{text}{newline}"),
+            (None, None) => format!("{} {text}{newline}", self.prefix),
+        };
+        if let Some(block) = &self.grouped {
+            block.lock().expect("lock").push_str(&out);
+            return;
+        }
+        match &self.ordered {
+            Some(ordered) => {
+                let mut release = ordered.release.lock().expect("lock");
+                if ordered.idx <= release.next {
+                    crate::output::out(&out);
+                } else {
+                    release.hold(ordered.idx, out);
+                }
+            }
+            None => crate::output::out(&out),
+        }
+    }
+}
+
+/// Identical consecutive lines seen by `--dedup-lines`.
+#[derive(Default)]
+struct Repeats {
+    line: String,
+    count: u64,
+}
+
+impl Repeats {
+    /// Whether `line` repeats the previous one. Otherwise the count of the
+    /// previous line is printed.
+    fn repeated(&mut self, fwd: &Forward, line: &str) -> bool {
+        let line = line.trim_end_matches(['\n', '\r']);
+        if self.count > 0 && self.line == line {
+            self.count += 1;
+            return true;
+        }
+        self.flush(fwd);
+        self.line = line.to_string();
+        self.count = 1;
+        false
+    }
+
+    fn flush(&mut self, fwd: &Forward) {
+        if self.count > 1 {
+            fwd.print(&format!("(×{})", self.count));
+        }
+        self.count = 0;
+    }
+}
+
+fn report_dropped(fwd: &Forward, dropped: Option<u64>) {
+    if let Some(dropped) = dropped {
+        fwd.print(&format!("... {dropped} lines not shown (rate limit)"));
+    }
+}
+
+/// `--raw`: copies what is read from the process to runall's stdout or
+/// stderr right away, before it is split into lines.
+struct Tee<R> {
+    inner: R,
+    stream: embed::Stream,
+    visible: Arc<AtomicBool>,
+}
+
+impl<R: Read> Read for Tee<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n > 0 && self.visible.load(Ordering::Relaxed) {
+            match self.stream {
+                embed::Stream::Stdout => crate::output::stdout(&buf[..n]),
+                embed::Stream::Stderr => {
+                    let _ = std::io::stderr().write_all(&buf[..n]);
+                }
+            }
+        }
+        Ok(n)
+    }
+}
+
+/// Treats data as binary if it contains NUL bytes or mostly consists of
+/// control characters other than whitespace and escape sequences.
+fn looks_binary(buf: &[u8]) -> bool {
+    if buf.contains(&0) {
+        return true;
+    }
+    let control = buf
+        .iter()
+        .filter(|&&b| (b < 0x20 && !b"\t\n\r\x1b\x08\x0c".contains(&b)) || b == 0x7f)
+        .count();
+    buf.len() >= 8 && control * 10 > buf.len() * 3
+}
+
+fn hex_sample(buf: &[u8]) -> String {
+    buf.iter()
+        .take(16)
+        .map(|b| format!("{b:02x}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn fwd_stream(
+    fwd: &Forward,
+    stream: Option<impl Read + Send + 'static>,
+) -> Option<std::thread::JoinHandle<()>> {
+    let fwd = fwd.clone();
+    stream.map(|stream| {
+        std::thread::spawn(move || {
+            let mut stream: Box<dyn Read + Send> = match fwd.raw {
+                Some(raw) => Box::new(Tee {
+                    inner: stream,
+                    stream: raw,
+                    visible: fwd.visible.clone(),
+                }),
+                None => Box::new(stream),
+            };
+            // read in a thread of its own so that a partial line can be shown
+            // when the process pauses
+            let (chunks_tx, chunks) = flume::bounded::<Vec<u8>>(16);
+            std::thread::spawn(move || {
+                let mut buf = vec![0; 16 * 1024];
+                loop {
+                    match stream.read(&mut buf) {
+                        Ok(0) => break,
+                        Ok(n) => {
+                            if chunks_tx.send(buf[..n].to_vec()).is_err() {
+                                break;
+                            }
+                        }
+                        Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+                        Err(err) => {
+                            notice!("error reading output: {err}");
+                            break;
+                        }
+                    }
+                }
+            });
+
+            // Once binary data shows up, the rest of the stream is only counted.
+            let mut binary_bytes = None;
+            let mut osc = ansi::OscState::default();
+            let mut log_osc = ansi::OscState::default();
+            let mut repeats = Repeats::default();
+            let mut levels = level::Filter::default();
+            let mut forward = |buf: &[u8]| {
+                if let Some(count) = &mut binary_bytes {
+                    *count += buf.len();
+                    return;
+                }
+                if looks_binary(buf) {
+                    let notice = format!(
+                        "binary output detected, suppressing it (starts with {})",
+                        hex_sample(buf)
+                    );
+                    for log in &fwd.logs {
+                        logs::write(log, &notice);
+                    }
+                    if fwd.visible.load(Ordering::Relaxed) {
+                        fwd.print(&notice);
+                    }
+                    binary_bytes = Some(buf.len());
+                    return;
+                }
+                let line = redact::apply(match fwd.encoding {
+                    Some(encoding) => encoding.decode(buf).0,
+                    None => String::from_utf8_lossy(buf),
+                });
+                fwd.lines.fetch_add(1, Ordering::Relaxed);
+                let text = line.trim_end();
+                if !text.is_empty() {
+                    *fwd.last_line.lock().expect("lock") = text.to_string();
+                }
+                if let Some(tail) = &fwd.tail {
+                    tail.push(&line);
+                }
+                for probe in &fwd.log_probes {
+                    probe.check(&line);
+                }
+                if let Some(ship) = &fwd.ship {
+                    ship.line(&line);
+                }
+                if !fwd.logs.is_empty() || fwd.fifo.is_some() {
+                    let line = ansi::filter_osc(&line, false, &mut log_osc);
+                    for log in &fwd.logs {
+                        logs::write(log, &line);
+                    }
+                    if let Some(fifo) = &fwd.fifo {
+                        fifo.write(&line);
+                    }
+                }
+                if !fwd.visible.load(Ordering::Relaxed) {
+                    return;
+                }
+                let min_level = *fwd.min_level.lock().expect("min level");
+                if min_level.is_some_and(|min| !levels.shows(min, &line)) {
+                    return;
+                }
+                if !fwd.matches(&line) {
+                    return;
+                }
+                if fwd.dedup && repeats.repeated(&fwd, &line) {
+                    return;
+                }
+                if let Some(throttle) = &fwd.throttle {
+                    let (admitted, dropped) = throttle.lock().expect("lock").admit();
+                    report_dropped(&fwd, dropped);
+                    if !admitted {
+                        return;
+                    }
+                }
+                let line = ansi::filter_osc(&line, fwd.hyperlinks, &mut osc);
+                fwd.print(&fwd.highlight(&line));
+            };
+
+            let mut splitter = split::Splitter::default();
+            loop {
+                let chunk = if splitter.pending() {
+                    chunks.recv_timeout(split::IDLE)
+                } else {
+                    chunks
+                        .recv()
+                        .map_err(|_| flume::RecvTimeoutError::Disconnected)
+                };
+                let lines = match chunk {
+                    Ok(chunk) => {
+                        *fwd.last_output.lock().expect("lock") = Instant::now();
+                        if let Some(mux) = &fwd.mux {
+                            mux.data(&chunk);
+                        }
+                        if let Some(checksum) = &fwd.checksum {
+                            checksum.lock().expect("lock").update(&chunk);
+                        }
+                        splitter.push(&chunk)
+                    }
+                    Err(flume::RecvTimeoutError::Timeout) => splitter.idle().into_iter().collect(),
+                    Err(flume::RecvTimeoutError::Disconnected) => break,
+                };
+                for line in lines {
+                    forward(&line);
+                }
+            }
+            if let Some(line) = splitter.finish() {
+                forward(&line);
+            }
+
+            repeats.flush(&fwd);
+            if let Some(mux) = &fwd.mux {
+                mux.eof();
+            }
+            if let Some(throttle) = &fwd.throttle {
+                report_dropped(&fwd, throttle.lock().expect("lock").take_dropped());
+            }
+            if let Some(count) = binary_bytes.filter(|_| fwd.visible.load(Ordering::Relaxed)) {
+                fwd.print(&format!("{count} binary bytes suppressed"));
+            }
+        })
+    })
+}
@@ -0,0 +1,32 @@
+//! Git helpers for `--changed-since`.
+
+use std::{
+    path::{Path, PathBuf},
+    process,
+};
+
+fn git(cwd: &Path, args: &[&str]) -> String {
+    let output = crate::reaper::output(process::Command::new("git").args(args).current_dir(cwd))
+        .expect("run git");
+    if !output.status.success() {
+        panic!("git {} failed", args.join(" "));
+    }
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+/// Returns the absolute paths of all files that differ between the merge-base
+/// of `rev` and `HEAD` and the working tree, including untracked files.
+pub fn changed_files(cwd: &Path, rev: &str) -> Vec<PathBuf> {
+    let toplevel = PathBuf::from(git(cwd, &["rev-parse", "--show-toplevel"]).trim());
+    let base = git(cwd, &["merge-base", rev, "HEAD"]);
+    let diff = git(cwd, &["diff", "--name-only", base.trim()]);
+    let untracked = git(
+        cwd,
+        &["ls-files", "--others", "--exclude-standard", "--full-name"],
+    );
+    diff.lines()
+        .chain(untracked.lines())
+        .filter(|line| !line.is_empty())
+        .map(|line| toplevel.join(line))
+        .collect()
+}
@@ -0,0 +1,323 @@
+//! `runall import`: converts a concurrently invocation or a foreman
+//! Procfile into a runall.toml, mapping their options to the runall
+//! equivalents.
+
+use std::{fs, path::PathBuf};
+
+use crate::config::{self, Entry, Settings};
+
+#[derive(clap::Args)]
+pub struct ImportArgs {
+    /// A concurrently command line, e.g. 'concurrently -k -n api,web "npm:api"
+    /// "npm:web"'.
+    #[clap(
+        long,
+        value_name = "CMDLINE",
+        required_unless_present = "from_procfile",
+        conflicts_with = "from_procfile"
+    )]
+    pub from_concurrently: Option<String>,
+
+    /// A Procfile as used by foreman, honcho and others.
+    #[clap(long, value_name = "PATH")]
+    pub from_procfile: Option<PathBuf>,
+
+    /// Where to write the config, "-" for stdout.
+    #[clap(short, long, value_name = "PATH", default_value = config::DEFAULT_FILE)]
+    pub output: PathBuf,
+
+    /// Overwrite an existing output file.
+    #[clap(long)]
+    pub force: bool,
+}
+
+pub fn import(args: ImportArgs) {
+    let (source, settings, entries) = if let Some(cmdline) = &args.from_concurrently {
+        let (settings, entries) = concurrently(cmdline);
+        ("concurrently", settings, entries)
+    } else {
+        let path = args.from_procfile.as_ref().expect("--from-procfile");
+        // foreman stops all processes as soon as one of them exits
+        let settings = Settings {
+            kill_others: Some(true),
+            ..Default::default()
+        };
+        ("the Procfile", settings, config::procfile(path))
+    };
+    if entries.is_empty() {
+        panic!("no commands found in {source}");
+    }
+
+    let stdout = args.output.as_os_str() == "-";
+    let file = if stdout {
+        config::DEFAULT_FILE.to_string()
+    } else {
+        args.output.display().to_string()
+    };
+    let header = format!(
+        "Imported from {source} by `runall import`. Run all commands with\n\
+         `runall --config {file}`, or some of them with\n\
+         `runall --config {file} NAME...`."
+    );
+    let names = entries
+        .iter()
+        .map(|(name, _)| name.clone())
+        .collect::<Vec<_>>();
+    let content = config::render(&header, settings, entries);
+    if stdout {
+        print!("{content}");
+        return;
+    }
+    if args.output.exists() && !args.force {
+        panic!(
+            "{} already exists, use --force to overwrite it",
+            args.output.display()
+        );
+    }
+    fs::write(&args.output, content)
+        .unwrap_or_else(|err| panic!("cannot write {}: {err}", args.output.display()));
+    eprintln!("wrote {} with {}", args.output.display(), names.join(", "));
+}
+
+/// concurrently options without a runall equivalent in the config. They are
+/// ignored.
+const IGNORED: [&str; 11] = [
+    "-r",
+    "--raw",
+    "--no-color",
+    "-g",
+    "--group",
+    "--timings",
+    "-i",
+    "--handle-input",
+    "-P",
+    "--passthrough-arguments",
+    "--pad-prefix",
+];
+
+/// Like [`IGNORED`], for the options that take a value.
+const IGNORED_WITH_VALUE: [&str; 12] = [
+    "-c",
+    "--prefix-colors",
+    "-p",
+    "--prefix",
+    "-l",
+    "--prefix-length",
+    "-t",
+    "--timestamp-format",
+    "--hide",
+    "--default-input-target",
+    "--teardown",
+    "--padding",
+];
+
+/// Parses the options and commands of a concurrently command line. Anything
+/// before the `concurrently` program (npx, pnpm exec, ...) is skipped.
+fn concurrently(cmdline: &str) -> (Settings, Vec<(String, Entry)>) {
+    let mut words = split_words(cmdline);
+    if let Some(pos) = words
+        .iter()
+        .position(|w| w == "concurrently" || w.ends_with("/concurrently"))
+    {
+        words.drain(..=pos);
+    }
+
+    let mut settings = Settings::default();
+    let mut names = None;
+    let mut separator = ",".to_string();
+    let mut cwd = None;
+    let mut restart_tries = None;
+    let mut restart_after = None;
+    let mut success = None;
+    let mut commands = Vec::new();
+    let mut words = words.into_iter();
+    while let Some(word) = words.next() {
+        if !word.starts_with('-') || word == "-" {
+            commands.push(word);
+            continue;
+        }
+        if word == "--" {
+            commands.extend(words.by_ref());
+            break;
+        }
+        let (flag, inline) = match word.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => {
+                (flag.to_string(), Some(value.to_string()))
+            }
+            _ => (word, None),
+        };
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| words.next())
+                .unwrap_or_else(|| panic!("concurrently option {flag} needs a value"))
+        };
+        match flag.as_str() {
+            "-n" | "--names" => names = Some(value()),
+            "--name-separator" => separator = value(),
+            "-k" | "--kill-others" => settings.kill_others = Some(true),
+            "--kill-others-on-fail" => settings.fail_fast = Some(true),
+            "-s" | "--success" => success = Some(value()),
+            "-m" | "--max-processes" => {
+                let max = value();
+                match max.parse() {
+                    Ok(max) => settings.jobs = Some(max),
+                    Err(_) => eprintln!("ignoring --max-processes {max}, expected a number"),
+                }
+            }
+            "--restart-tries" => {
+                let tries = value();
+                let tries = tries
+                    .parse::<i64>()
+                    .unwrap_or_else(|_| panic!("invalid --restart-tries {tries:?}"));
+                restart_tries = Some(tries);
+            }
+            "--restart-after" => {
+                let after = value();
+                match after.parse::<u64>() {
+                    Ok(ms) => restart_after = Some(format!("{ms}ms")),
+                    Err(_) => eprintln!("ignoring --restart-after {after}, expected milliseconds"),
+                }
+            }
+            "--cwd" => cwd = Some(PathBuf::from(value())),
+            flag if IGNORED.contains(&flag) => eprintln!("ignoring concurrently option {flag}"),
+            flag if IGNORED_WITH_VALUE.contains(&flag) => {
+                let _ = value();
+                eprintln!("ignoring concurrently option {flag}");
+            }
+            flag => eprintln!("ignoring unknown concurrently option {flag}"),
+        }
+    }
+
+    let names = names
+        .map(|names| {
+            names
+                .split(separator.as_str())
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    let mut entries: Vec<(String, Entry)> = Vec::new();
+    for (i, command) in commands.iter().enumerate() {
+        let (default_name, cmd) = expand_shortcut(command);
+        let name = match names.get(i).filter(|n| !n.is_empty()) {
+            Some(name) => name.clone(),
+            None => default_name,
+        };
+        let name = unique(&entries, name);
+        let mut entry = Entry {
+            cmd,
+            cwd: cwd.clone(),
+            ..Default::default()
+        };
+        // -1 retries forever
+        if let Some(tries) = restart_tries.filter(|t| *t != 0) {
+            entry.restart = Some("on-failure".to_string());
+            entry.max_restarts = u32::try_from(tries).ok();
+            entry.restart_delay = restart_after.clone();
+        }
+        entries.push((name, entry));
+    }
+
+    if let Some(success) = success {
+        settings.success = success_policy(&success, &entries);
+    }
+    (settings, entries)
+}
+
+/// concurrently's `--success` values: all, first, last, command-NAME or
+/// command-INDEX. The negated `!command-...` forms have no equivalent.
+fn success_policy(policy: &str, entries: &[(String, Entry)]) -> Option<String> {
+    match policy {
+        "all" | "first" | "last" => Some(policy.to_string()),
+        _ => {
+            let command = policy.strip_prefix("command-");
+            let name = command.and_then(|command| match command.parse::<usize>() {
+                Ok(idx) => entries.get(idx).map(|(name, _)| name.clone()),
+                Err(_) => Some(command.to_string()),
+            });
+            if name.is_none() {
+                eprintln!("ignoring --success {policy}, runall has no equivalent");
+            }
+            name
+        }
+    }
+}
+
+/// Expands `npm:script` (and the yarn, pnpm and bun variants) into the run
+/// command, and picks a name for the command: the script or the program.
+fn expand_shortcut(command: &str) -> (String, String) {
+    if let Some((pm, script)) = command.split_once(':') {
+        if ["npm", "yarn", "pnpm", "bun"].contains(&pm) && !script.contains(' ') {
+            if script.contains('*') {
+                eprintln!("{command}: wildcards are not expanded, the command is kept as is");
+            }
+            return (script.to_string(), format!("{pm} run {script}"));
+        }
+    }
+    let program = command.split_whitespace().next().unwrap_or("cmd");
+    let program = program.rsplit('/').next().unwrap_or(program);
+    (program.to_string(), command.to_string())
+}
+
+/// `name`, or `name-2`, `name-3`... if an entry of that name exists.
+pub fn unique(entries: &[(String, Entry)], name: String) -> String {
+    let taken = |candidate: &str| entries.iter().any(|(n, _)| n == candidate);
+    if !taken(&name) {
+        return name;
+    }
+    (2..)
+        .map(|n| format!("{name}-{n}"))
+        .find(|candidate| !taken(candidate))
+        .expect("free name")
+}
+
+/// Splits a command line into words like a POSIX shell, handling single and
+/// double quotes and backslash escapes.
+fn split_words(cmdline: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = None::<String>;
+    let mut chars = cmdline.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => panic!("unterminated ' in {cmdline:?}"),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => panic!("unterminated \" in {cmdline:?}"),
+                        },
+                        Some(c) => word.push(c),
+                        None => panic!("unterminated \" in {cmdline:?}"),
+                    }
+                }
+            }
+            '\\' => {
+                let word = word.get_or_insert_with(String::new);
+                if let Some(c) = chars.next() {
+                    word.push(c);
+                }
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    words
+}
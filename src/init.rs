@@ -0,0 +1,232 @@
+//! `runall init`: writes a starter runall.toml for the project in the
+//! current directory, based on its package.json scripts, Cargo workspace and
+//! docker compose file.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{config, workspace};
+
+#[derive(clap::Args)]
+pub struct InitArgs {
+    /// The kind of project. Everything that can be found is set up if not
+    /// given.
+    #[clap(value_enum)]
+    pub stack: Option<Stack>,
+
+    /// Overwrite an existing runall.toml.
+    #[clap(long)]
+    pub force: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Stack {
+    /// package.json scripts.
+    Node,
+    /// Binaries of the Cargo package or workspace.
+    Rust,
+    /// docker compose services plus node and rust.
+    Fullstack,
+}
+
+type Entry = (String, config::Entry);
+
+fn entry(name: impl ToString, cmd: impl ToString, cwd: Option<&str>, service: bool) -> Entry {
+    let entry = config::Entry {
+        cmd: cmd.to_string(),
+        cwd: cwd.map(PathBuf::from),
+        kind: service.then(|| "service".to_string()),
+        ..Default::default()
+    };
+    (name.to_string(), entry)
+}
+
+const COMPOSE_FILES: [&str; 4] = [
+    "compose.yaml",
+    "compose.yml",
+    "docker-compose.yml",
+    "docker-compose.yaml",
+];
+
+/// Scripts that keep running, in order of preference.
+const DEV_SCRIPTS: [&str; 4] = ["dev", "start", "serve", "watch"];
+
+/// The package manager of the node project in `dir`, by its lock file.
+pub fn package_manager(dir: &Path) -> &'static str {
+    if dir.join("pnpm-lock.yaml").exists() {
+        "pnpm"
+    } else if dir.join("yarn.lock").exists() {
+        "yarn"
+    } else if dir.join("bun.lockb").exists() || dir.join("bun.lock").exists() {
+        "bun"
+    } else {
+        "npm"
+    }
+}
+
+/// The command that runs a package.json script with `pm`.
+pub fn run_script(pm: &str, script: &str) -> String {
+    match pm {
+        "npm" => format!("npm run {script}"),
+        _ => format!("{pm} {script}"),
+    }
+}
+
+/// The dev server (or build) scripts of the package.json in `root` and in
+/// its direct subdirectories, e.g. frontend/.
+fn node_entries(root: &Path) -> Vec<Entry> {
+    let mut dirs = vec![None];
+    if let Ok(children) = fs::read_dir(root) {
+        let mut children = children
+            .filter_map(Result::ok)
+            .map(|child| child.file_name().to_string_lossy().to_string())
+            .filter(|name| !name.starts_with('.') && name != "node_modules")
+            .filter(|name| root.join(name).join("package.json").is_file())
+            .collect::<Vec<_>>();
+        children.sort();
+        dirs.extend(children.into_iter().map(Some));
+    }
+
+    let mut entries = Vec::new();
+    for dir in dirs {
+        let path = dir.as_ref().map_or(root.to_path_buf(), |d| root.join(d));
+        let Some(manifest) = workspace::read_manifest(&path.join("package.json")) else {
+            continue;
+        };
+        let Some(scripts) = manifest.get("scripts").and_then(|s| s.as_object()) else {
+            continue;
+        };
+        let pm = package_manager(&path);
+        let run = |script: &str| run_script(pm, script);
+        let name = |script: &str| match &dir {
+            Some(dir) => format!("{dir}-{script}"),
+            None => script.to_string(),
+        };
+        let dev = DEV_SCRIPTS.iter().find(|s| scripts.contains_key(**s));
+        if let Some(script) = dev {
+            entries.push(entry(name(script), run(script), dir.as_deref(), true));
+        } else if scripts.contains_key("build") {
+            entries.push(entry(name("build"), run("build"), dir.as_deref(), false));
+        }
+    }
+    entries
+}
+
+/// `cargo run` for every binary package of the Cargo project in `root`.
+fn rust_entries(root: &Path) -> Vec<Entry> {
+    let Ok(content) = fs::read_to_string(root.join("Cargo.toml")) else {
+        return Vec::new();
+    };
+    let manifest = match toml::from_str::<toml::Table>(&content) {
+        Ok(manifest) => manifest,
+        Err(err) => {
+            eprintln!("cannot parse Cargo.toml: {err}");
+            return Vec::new();
+        }
+    };
+    let package_name = |manifest: &toml::Table| {
+        manifest
+            .get("package")
+            .and_then(|p| p.get("name"))
+            .and_then(|n| n.as_str())
+            .map(str::to_string)
+    };
+
+    let members = manifest
+        .get("workspace")
+        .and_then(|w| w.get("members"))
+        .and_then(|m| m.as_array())
+        .map(|members| {
+            members
+                .iter()
+                .filter_map(|m| m.as_str())
+                .filter_map(|m| glob::glob(&root.join(m).to_string_lossy()).ok())
+                .flatten()
+                .filter_map(Result::ok)
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    if members.is_empty() {
+        return match package_name(&manifest) {
+            Some(name) if root.join("src/main.rs").exists() => {
+                vec![entry(name, "cargo run", None, true)]
+            }
+            Some(name) => vec![entry(name, "cargo build", None, false)],
+            None => Vec::new(),
+        };
+    }
+
+    let mut entries = members
+        .iter()
+        .filter(|dir| dir.join("src/main.rs").exists())
+        .filter_map(|dir| {
+            let content = fs::read_to_string(dir.join("Cargo.toml")).ok()?;
+            package_name(&toml::from_str(&content).ok()?)
+        })
+        .map(|name| entry(&name, format!("cargo run -p {name}"), None, true))
+        .collect::<Vec<_>>();
+    if entries.is_empty() {
+        entries.push(entry("build", "cargo build --workspace", None, false));
+    }
+    entries
+}
+
+fn compose_entry(root: &Path) -> Option<Entry> {
+    COMPOSE_FILES
+        .iter()
+        .any(|file| root.join(file).exists())
+        .then(|| entry("compose", "docker compose up", None, true))
+}
+
+const HEADER: &str = "Generated by `runall init`. Run all commands with `runall`, or some of
+them with `runall --config runall.toml NAME...`.";
+
+/// Writes runall.toml, returns false if there was nothing to set up.
+pub fn init(args: InitArgs) -> bool {
+    let root = std::env::current_dir().expect("current dir");
+    let path = root.join(config::DEFAULT_FILE);
+    if path.exists() && !args.force {
+        panic!(
+            "{} already exists, use --force to overwrite it",
+            path.display()
+        );
+    }
+
+    let wants = |stack: Stack| {
+        args.stack
+            .is_none_or(|chosen| chosen == stack || chosen == Stack::Fullstack)
+    };
+    let compose = (args.stack.is_none() || args.stack == Some(Stack::Fullstack))
+        .then(|| compose_entry(&root))
+        .flatten();
+    let mut entries = Vec::new();
+    if wants(Stack::Rust) {
+        entries.extend(rust_entries(&root));
+    }
+    if wants(Stack::Node) {
+        entries.extend(node_entries(&root));
+    }
+    if let Some(compose) = compose {
+        // the app servers start once the containers are up
+        for (_, entry) in entries.iter_mut().filter(|(_, e)| e.kind.is_some()) {
+            entry.needs.push(compose.0.clone());
+        }
+        entries.insert(0, compose);
+    }
+    if entries.is_empty() {
+        eprintln!("found nothing to run in {}", root.display());
+        return false;
+    }
+
+    let names = entries
+        .iter()
+        .map(|(name, _)| name.clone())
+        .collect::<Vec<_>>();
+    let content = config::render(HEADER, config::Settings::default(), entries);
+    fs::write(&path, content)
+        .unwrap_or_else(|err| panic!("cannot write {}: {err}", path.display()));
+    eprintln!("wrote {} with {}", config::DEFAULT_FILE, names.join(", "));
+    true
+}
@@ -0,0 +1,62 @@
+//! Windows Job Objects: terminating a process also terminates everything it
+//! started, like process groups on unix.
+
+use std::{io, os::windows::io::AsRawHandle, process::Child};
+
+use windows_sys::Win32::{
+    Foundation::{CloseHandle, HANDLE},
+    System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, JobObjectExtendedLimitInformation,
+        SetInformationJobObject, TerminateJobObject, JOBOBJECT_EXTENDED_LIMIT_INFORMATION,
+        JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+    },
+};
+
+pub struct JobObject(HANDLE);
+
+// SAFETY: job object handles can be used from any thread
+unsafe impl Send for JobObject {}
+
+impl JobObject {
+    /// Puts `child` into a new job that kills all its processes once the
+    /// job is closed, i.e. when this is dropped or runall exits. Processes
+    /// the child started before it was assigned are not part of the job.
+    pub fn assign(child: &Child) -> io::Result<Self> {
+        // SAFETY: plain FFI calls with valid arguments, the handle is owned
+        // by the returned value
+        unsafe {
+            let handle = CreateJobObjectW(std::ptr::null(), std::ptr::null());
+            if handle.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            let job = Self(handle);
+            let mut info = std::mem::zeroed::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>();
+            info.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+            let set = SetInformationJobObject(
+                job.0,
+                JobObjectExtendedLimitInformation,
+                &info as *const _ as *const std::ffi::c_void,
+                std::mem::size_of_val(&info) as u32,
+            );
+            if set == 0 || AssignProcessToJobObject(job.0, child.as_raw_handle() as HANDLE) == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(job)
+        }
+    }
+
+    /// Terminates all processes of the job.
+    pub fn terminate(&self) {
+        // SAFETY: the handle is valid for as long as self
+        if unsafe { TerminateJobObject(self.0, 1) } == 0 {
+            eprintln!("cannot terminate job: {}", io::Error::last_os_error());
+        }
+    }
+}
+
+impl Drop for JobObject {
+    fn drop(&mut self) {
+        // SAFETY: the handle is valid and not used afterwards
+        unsafe { CloseHandle(self.0) };
+    }
+}
@@ -0,0 +1,95 @@
+//! `--min-level`: recognizes the severity of log lines (`WARN ...`,
+//! `[error] ...`, `level=debug`, `"level":"info"`) so that lines below a
+//! threshold can be left out of the console. They still go to the logs.
+
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "trace" => Ok(Level::Trace),
+            "debug" | "dbg" | "verbose" => Ok(Level::Debug),
+            "info" | "information" | "notice" => Ok(Level::Info),
+            "warn" | "warning" => Ok(Level::Warn),
+            "error" | "fatal" | "critical" | "crit" | "panic" | "severe" => Ok(Level::Error),
+            _ => Err(format!(
+                "invalid level {s:?}, expected trace, debug, info, warn or error"
+            )),
+        }
+    }
+}
+
+/// Only the first words are looked at, where loggers put the level, so that
+/// "retrying after error" in a message doesn't count.
+const WORDS: usize = 6;
+
+/// The level of a log line, if it has one.
+pub fn classify(line: &str) -> Option<Level> {
+    let line = strip_colors(line);
+    let words = line
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty() && !w.starts_with(|c: char| c.is_ascii_digit()));
+    let mut previous = "";
+    for word in words.take(WORDS) {
+        if previous.eq_ignore_ascii_case("level") || previous.eq_ignore_ascii_case("lvl") {
+            return word.parse().ok();
+        }
+        if let Ok(level) = word.parse() {
+            return Some(level);
+        }
+        previous = word;
+    }
+    None
+}
+
+/// Removes the escape sequences of colored output, `ESC [ ... m` and the
+/// like.
+pub fn strip_colors(line: &str) -> String {
+    let mut plain = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            if chars.next() == Some('[') {
+                // parameters up to the final byte
+                chars.by_ref().find(|c| ('@'..='~').contains(c));
+            }
+            continue;
+        }
+        plain.push(c);
+    }
+    plain
+}
+
+/// Decides for each line of a process whether it is shown. Lines without a
+/// level (stack traces, continued messages) go with the line before them.
+pub struct Filter {
+    shown: bool,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self { shown: true }
+    }
+}
+
+impl Filter {
+    /// `min` is passed with every line, `runall ctl set` can change it while
+    /// the process runs.
+    pub fn shows(&mut self, min: Level, line: &str) -> bool {
+        if let Some(level) = classify(line) {
+            self.shown = level >= min;
+        }
+        self.shown
+    }
+}
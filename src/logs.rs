@@ -0,0 +1,463 @@
+//! Per-process session logs (`--log`) and searching them (`runall grep`).
+//!
+//! Logs are stored in `$XDG_STATE_HOME/runall/logs/<session>/<run>/<name>.log`
+//! (`~/.local/state` if `XDG_STATE_HOME` isn't set) with one timestamped
+//! line per output line. Each run of a session gets its own directory named
+//! after its start time and `<session>/latest` links to the most recent one.
+//! `--log-mode` controls whether repeated runs of a job share one file.
+//! `--log-streams` additionally or instead writes `<name>.out.log` and
+//! `<name>.err.log`. While a run is in progress its directory contains a `runall.pid` file.
+//! With `--log-retention`, the logs of finished runs are gzipped to
+//! `<name>.log.gz` and old runs are removed.
+//!
+//! `--log-dir` writes the logs into a directory of the user's choosing
+//! instead, without the run directories. `--log-max-size` rotates a log that
+//! grows past the size to `<name>.log.1`, replacing the previous one.
+//!
+//! The files are written unbuffered, one line at a time, so nothing is lost
+//! when runall is killed.
+
+use std::{
+    fs,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+pub type LogFile = Arc<Mutex<Log>>;
+
+pub struct Log {
+    file: fs::File,
+    path: PathBuf,
+    size: u64,
+    max_size: Option<u64>,
+}
+
+impl Log {
+    /// Moves the file to `<path>.1` and starts a new one.
+    fn rotate(&mut self) -> std::io::Result<()> {
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(".1");
+        fs::rename(&self.path, rotated)?;
+        self.file = fs::File::create(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+pub fn root() -> PathBuf {
+    let state = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/state")))
+        .unwrap_or_else(std::env::temp_dir);
+    state.join("runall").join("logs")
+}
+
+/// Sessions are named after the current directory unless `--session` is
+/// given.
+pub fn default_session() -> String {
+    std::env::current_dir()
+        .ok()
+        .and_then(|dir| dir.file_name().map(|n| n.to_string_lossy().to_string()))
+        .unwrap_or_else(|| "default".to_string())
+}
+
+/// Process names can contain slashes (workspace packages, file paths).
+pub fn file_name(name: &str) -> String {
+    let safe = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || "-_.@".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    format!("{safe}.log")
+}
+
+/// Which log files are written per process.
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum Streams {
+    /// `<name>.log` with stdout and stderr interleaved.
+    Merged,
+    /// `<name>.out.log` and `<name>.err.log`.
+    Split,
+    /// Both the merged and the separate files.
+    Both,
+}
+
+impl Streams {
+    pub fn merged(self) -> bool {
+        matches!(self, Streams::Merged | Streams::Both)
+    }
+
+    pub fn split(self) -> bool {
+        matches!(self, Streams::Split | Streams::Both)
+    }
+}
+
+/// How the logs of a job that runs several times (`--repeat`, `--every`,
+/// `--schedule`) are kept.
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum Mode {
+    /// All runs go into the same file.
+    Append,
+    /// Each run replaces the log of the previous one.
+    Truncate,
+    /// Each run gets its own `<name>.<run>.log`.
+    PerRun,
+}
+
+impl Mode {
+    /// The name the log of the `run`th run (counting from 1) is stored under.
+    pub fn name(self, name: &str, run: u32) -> String {
+        match self {
+            Mode::PerRun => format!("{name}.{run}"),
+            Mode::Append | Mode::Truncate => name.to_string(),
+        }
+    }
+}
+
+pub fn open(dir: &Path, name: &str, mode: Mode, max_size: Option<u64>) -> LogFile {
+    fs::create_dir_all(dir).unwrap_or_else(|err| panic!("create {}: {err}", dir.display()));
+    let path = dir.join(file_name(name));
+    let truncate = matches!(mode, Mode::Truncate);
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(!truncate)
+        .truncate(truncate)
+        .open(&path)
+        .unwrap_or_else(|err| panic!("open {}: {err}", path.display()));
+    let size = file.metadata().map_or(0, |m| m.len());
+    Arc::new(Mutex::new(Log {
+        file,
+        path,
+        size,
+        max_size,
+    }))
+}
+
+/// Appends `line` (which may or may not end in a newline) with a timestamp.
+pub fn write(log: &LogFile, line: &str) {
+    let ts = chrono::Local::now().format("%Y-%m-%dT%H:%M:%S%.3f");
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    let line = format!("{ts} {line}\n");
+    let mut log = log.lock().expect("lock");
+    let full = log
+        .max_size
+        .is_some_and(|max| log.size > 0 && log.size + line.len() as u64 > max);
+    if full {
+        if let Err(err) = log.rotate() {
+            eprintln!("error rotating {}: {err}", log.path.display());
+        }
+    }
+    match log.file.write_all(line.as_bytes()) {
+        Ok(()) => log.size += line.len() as u64,
+        Err(err) => eprintln!("error writing log: {err}"),
+    }
+}
+
+/// `--log-max-size`: a number of bytes with an optional K, M or G suffix
+/// (powers of 1024).
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (digits, unit) = match s.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+        Some((at, _)) => s.split_at(at),
+        None => (s, ""),
+    };
+    let unit = unit.trim().to_ascii_uppercase();
+    let unit = match unit.trim_end_matches(['B', 'I']) {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        _ => return Err(format!("invalid size {s:?}, expected e.g. 10M")),
+    };
+    let n: u64 = digits
+        .parse()
+        .map_err(|_| format!("invalid size {s:?}, expected e.g. 10M"))?;
+    Ok(n * unit)
+}
+
+const PID_FILE: &str = "runall.pid";
+const LATEST: &str = "latest";
+
+/// Creates the directory for a new run of `session`, named after the current
+/// time, and points the session's `latest` symlink at it.
+pub fn new_run(session: &str) -> PathBuf {
+    let session_dir = root().join(session);
+    fs::create_dir_all(&session_dir)
+        .unwrap_or_else(|err| panic!("create {}: {err}", session_dir.display()));
+    let stamp = chrono::Local::now().format("%Y-%m-%dT%H-%M-%S").to_string();
+    let mut run = session_dir.join(&stamp);
+    // runs started within the same second get a counter
+    for n in 2.. {
+        match fs::create_dir(&run) {
+            Ok(()) => break,
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+                run = session_dir.join(format!("{stamp}-{n}"));
+            }
+            Err(err) => panic!("create {}: {err}", run.display()),
+        }
+    }
+
+    #[cfg(unix)]
+    {
+        // replace the link atomically so that `latest` always resolves
+        let latest = session_dir.join(LATEST);
+        let tmp = session_dir.join(format!(".{LATEST}.{}", std::process::id()));
+        let _ = fs::remove_file(&tmp);
+        let linked = std::os::unix::fs::symlink(run.file_name().expect("run name"), &tmp)
+            .and_then(|()| fs::rename(&tmp, &latest));
+        if let Err(err) = linked {
+            eprintln!("cannot update {}: {err}", latest.display());
+        }
+    }
+    run
+}
+
+/// All run directories of all sessions, ordered by session and start time.
+fn runs() -> Vec<PathBuf> {
+    entries(&root())
+        .into_iter()
+        .filter(|session| session.is_dir())
+        .flat_map(|session| entries(&session))
+        .filter(|run| fs::symlink_metadata(run).is_ok_and(|m| m.is_dir()))
+        .collect()
+}
+
+/// Marks a run directory as in use for as long as it is alive.
+pub struct RunLock(PathBuf);
+
+impl RunLock {
+    pub fn acquire(dir: &Path) -> Self {
+        fs::create_dir_all(dir).unwrap_or_else(|err| panic!("create {}: {err}", dir.display()));
+        let path = dir.join(PID_FILE);
+        if let Err(err) = fs::write(&path, std::process::id().to_string()) {
+            eprintln!("cannot write {}: {err}", path.display());
+        }
+        Self(path)
+    }
+}
+
+impl Drop for RunLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+fn pid_alive(pid: u32) -> bool {
+    #[cfg(unix)]
+    {
+        let pid = nix::unistd::Pid::from_raw(pid as i32);
+        !matches!(
+            nix::sys::signal::kill(pid, None),
+            Err(nix::errno::Errno::ESRCH)
+        )
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = pid;
+        true
+    }
+}
+
+fn run_in_progress(dir: &Path) -> bool {
+    fs::read_to_string(dir.join(PID_FILE))
+        .ok()
+        .and_then(|pid| pid.trim().parse().ok())
+        .is_some_and(pid_alive)
+}
+
+#[derive(Clone, Debug)]
+pub enum Retention {
+    /// Remove runs that were last written to longer ago than that.
+    Age(Duration),
+    /// Keep only that many (most recent) runs.
+    Runs(usize),
+}
+
+pub fn parse_retention(s: &str) -> Result<Retention, String> {
+    match s
+        .strip_suffix("-sessions")
+        .or_else(|| s.strip_suffix("-runs"))
+    {
+        Some(n) => n
+            .parse()
+            .map(Retention::Runs)
+            .map_err(|_| format!("invalid run count in {s:?}")),
+        None => crate::duration::parse(s).map(Retention::Age),
+    }
+}
+
+fn last_modified(dir: &Path) -> SystemTime {
+    entries(dir)
+        .iter()
+        .chain([dir.to_path_buf()].iter())
+        .filter_map(|p| p.metadata().and_then(|m| m.modified()).ok())
+        .max()
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+/// Appends `log` as a gzip member to `<log>.gz` and removes it.
+fn compress(log: &Path) -> std::io::Result<()> {
+    let mut gz_name = log.as_os_str().to_owned();
+    gz_name.push(".gz");
+    let gz = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(PathBuf::from(gz_name))?;
+    let mut encoder = flate2::write::GzEncoder::new(gz, flate2::Compression::default());
+    std::io::copy(&mut fs::File::open(log)?, &mut encoder)?;
+    encoder.finish()?;
+    fs::remove_file(log)
+}
+
+/// Compresses the logs of finished runs and removes runs according to
+/// `retention`, as well as sessions that have no runs left. Runs that are
+/// still in progress are left alone.
+pub fn housekeeping(retention: &Retention) {
+    let mut finished = runs()
+        .into_iter()
+        .filter(|dir| !run_in_progress(dir))
+        .map(|dir| (last_modified(&dir), dir))
+        .collect::<Vec<_>>();
+    finished.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+
+    let now = SystemTime::now();
+    for (i, (modified, dir)) in finished.iter().enumerate() {
+        let expired = match retention {
+            Retention::Age(max_age) => now.duration_since(*modified).unwrap_or_default() > *max_age,
+            // the run that is about to start counts as one
+            Retention::Runs(keep) => i + 1 >= *keep,
+        };
+        if expired {
+            if let Err(err) = fs::remove_dir_all(dir) {
+                eprintln!("cannot remove {}: {err}", dir.display());
+            }
+            continue;
+        }
+        for log in entries(dir)
+            .into_iter()
+            .filter(|p| p.extension().is_some_and(|ext| ext == "log"))
+        {
+            if let Err(err) = compress(&log) {
+                eprintln!("cannot compress {}: {err}", log.display());
+            }
+        }
+    }
+
+    for session in entries(&root()).into_iter().filter(|s| s.is_dir()) {
+        let latest = session.join(LATEST);
+        if fs::symlink_metadata(&latest).is_ok() && !latest.exists() {
+            let _ = fs::remove_file(&latest);
+        }
+        // only succeeds if the session is empty
+        let _ = fs::remove_dir(&session);
+    }
+}
+
+#[derive(clap::Args)]
+pub struct GrepArgs {
+    /// Regular expression to search for.
+    pub pattern: String,
+
+    /// Only search this session (default: all sessions).
+    #[clap(long)]
+    pub session: Option<String>,
+
+    /// Only search the logs of this process.
+    #[clap(long)]
+    pub name: Option<String>,
+
+    /// Only search the most recent run of each session.
+    #[clap(long)]
+    pub latest: bool,
+
+    /// Ignore case.
+    #[clap(short, long)]
+    pub ignore_case: bool,
+}
+
+fn has_log(run: &Path, name: &str) -> bool {
+    let log = run.join(format!("{name}.log"));
+    log.exists() || run.join(format!("{name}.log.gz")).exists()
+}
+
+fn entries(path: &Path) -> Vec<PathBuf> {
+    let mut entries = fs::read_dir(path)
+        .map(|entries| entries.flatten().map(|e| e.path()).collect::<Vec<_>>())
+        .unwrap_or_default();
+    entries.sort();
+    entries
+}
+
+/// Prints the matching lines as `<session>/<run> [<name>] <timestamp> <line>`.
+/// Returns whether anything matched.
+pub fn grep(args: GrepArgs) -> bool {
+    let pattern = regex::RegexBuilder::new(&args.pattern)
+        .case_insensitive(args.ignore_case)
+        .build()
+        .unwrap_or_else(|err| panic!("invalid pattern: {err}"));
+    let wanted = args.name.as_deref().map(file_name);
+    let runs = runs().into_iter().filter(|run| {
+        let session = run.parent().expect("session dir");
+        let latest = fs::read_link(session.join(LATEST)).ok();
+        args.session.as_ref().is_none_or(|s| session.ends_with(s))
+            && (!args.latest || latest.is_some_and(|l| run.ends_with(l)))
+    });
+
+    let mut found = false;
+    for run in runs {
+        let run_name = run
+            .strip_prefix(root())
+            .unwrap_or(&run)
+            .to_string_lossy()
+            .to_string();
+        for log in entries(&run) {
+            let Some(file) = log.file_name().map(|f| f.to_string_lossy().to_string()) else {
+                continue;
+            };
+            let plain = file.strip_suffix(".gz").unwrap_or(&file);
+            let Some(name) = plain.strip_suffix(".log") else {
+                continue;
+            };
+            // split stream logs only matter if there is no merged one
+            let (base, stream) = match name.rsplit_once('.') {
+                Some((base, stream @ ("out" | "err"))) => (base, Some(stream)),
+                _ => (name, None),
+            };
+            if stream.is_some() && has_log(&run, base) {
+                continue;
+            }
+            // `--log-mode per-run` appends the run number
+            let process = match base.rsplit_once('.') {
+                Some((process, n)) if n.parse::<u32>().is_ok() => process,
+                _ => base,
+            };
+            if wanted.as_ref().is_some_and(|w| *w != file_name(process)) {
+                continue;
+            }
+            let Ok(file) = fs::File::open(&log) else {
+                continue;
+            };
+            let reader: Box<dyn BufRead> = if log.extension().is_some_and(|ext| ext == "gz") {
+                Box::new(BufReader::new(flate2::read::MultiGzDecoder::new(file)))
+            } else {
+                Box::new(BufReader::new(file))
+            };
+            for line in reader.lines().map_while(Result::ok) {
+                let text = line.split_once(' ').map_or("", |(_, text)| text);
+                if pattern.is_match(text) {
+                    found = true;
+                    println!("{run_name} [{name}] {line}");
+                }
+            }
+        }
+    }
+    found
+}
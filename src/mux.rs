@@ -0,0 +1,74 @@
+//! `--mux-fd`: the exact output bytes of every process as frames on a file
+//! descriptor, for programs that wrap runall and want to tell the processes
+//! apart without parsing prefixes.
+//!
+//! A frame is a 9 byte header followed by the payload:
+//!
+//! | bytes | content                                          |
+//! |-------|--------------------------------------------------|
+//! | 0..4  | payload length, u32 big endian                   |
+//! | 4..8  | stream id: the command's index (from 0), u32 BE  |
+//! | 8     | flags, see the constants below                   |
+//!
+//! Without flags the payload is output of the process on stdout.
+
+use std::{
+    fs::File,
+    io::Write,
+    sync::{Arc, Mutex},
+};
+
+/// The output is from stderr.
+pub const STDERR: u8 = 1;
+/// The process started, the payload is the name of the command.
+pub const START: u8 = 2;
+/// The stream (stdout, or stderr with [`STDERR`]) was closed.
+pub const EOF: u8 = 4;
+/// The process exited, the payload is its exit code as i32 big endian
+/// (128 + the signal number if it was killed).
+pub const EXIT: u8 = 8;
+
+#[derive(Clone)]
+pub struct Mux(Arc<Mutex<File>>);
+
+impl Mux {
+    pub fn new(file: File) -> Self {
+        Self(Arc::new(Mutex::new(file)))
+    }
+
+    pub fn frame(&self, id: usize, flags: u8, payload: &[u8]) {
+        let mut frame = Vec::with_capacity(9 + payload.len());
+        frame.extend((payload.len() as u32).to_be_bytes());
+        frame.extend((id as u32).to_be_bytes());
+        frame.push(flags);
+        frame.extend(payload);
+        // a reader that went away only loses the frames
+        let _ = self.0.lock().expect("lock").write_all(&frame);
+    }
+}
+
+/// One output stream of a process.
+#[derive(Clone)]
+pub struct Stream {
+    pub mux: Mux,
+    pub id: usize,
+    pub stderr: bool,
+}
+
+impl Stream {
+    fn flags(&self, flags: u8) -> u8 {
+        if self.stderr {
+            flags | STDERR
+        } else {
+            flags
+        }
+    }
+
+    pub fn data(&self, bytes: &[u8]) {
+        self.mux.frame(self.id, self.flags(0), bytes);
+    }
+
+    pub fn eof(&self) {
+        self.mux.frame(self.id, self.flags(EOF), &[]);
+    }
+}
@@ -0,0 +1,175 @@
+//! `runall npm-run-all`: runs package.json scripts with the command line
+//! conventions of npm-run-all (`-p`, `-s`, `-c`, `watch:*` patterns). The
+//! binary also behaves like that when it is installed as `npm-run-all`,
+//! `run-p` or `run-s`.
+
+use std::{ffi::OsString, path::Path};
+
+use crate::{
+    config::{Entry, Settings},
+    import, init, workspace,
+};
+
+#[derive(clap::Args)]
+pub struct NpmRunAllArgs {
+    /// Script name patterns and npm-run-all options: -p/--parallel and
+    /// -s/--sequential start a group of scripts, -c/--continue-on-error,
+    /// -r/--race and --max-parallel N.
+    #[clap(
+        trailing_var_arg = true,
+        allow_hyphen_values = true,
+        value_name = "ARGS"
+    )]
+    pub args: Vec<String>,
+}
+
+/// Rewrites the command line of the `npm-run-all`, `run-p` and `run-s`
+/// aliases into `runall npm-run-all ...`.
+pub fn alias(argv: impl IntoIterator<Item = OsString>) -> Vec<OsString> {
+    let mut argv = argv.into_iter().collect::<Vec<_>>();
+    let program = argv
+        .first()
+        .and_then(|arg| Path::new(arg).file_stem())
+        .map(|stem| stem.to_string_lossy().to_string());
+    let group = match program.as_deref() {
+        Some("npm-run-all") => None,
+        Some("run-p") => Some("--parallel"),
+        Some("run-s") => Some("--sequential"),
+        _ => return argv,
+    };
+    let rest = argv.split_off(1);
+    argv.push("npm-run-all".into());
+    argv.extend(group.map(OsString::from));
+    argv.extend(rest);
+    argv
+}
+
+struct Group {
+    parallel: bool,
+    patterns: Vec<String>,
+}
+
+/// The package.json scripts selected by `args` as config entries. Groups run
+/// one after the other, the scripts of a sequential group one by one.
+pub fn entries(args: &[String]) -> (Settings, Vec<(String, Entry)>) {
+    let mut settings = Settings::default();
+    let mut continue_on_error = false;
+    let mut groups = vec![Group {
+        parallel: false,
+        patterns: Vec::new(),
+    }];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut group = |parallel| {
+            groups.push(Group {
+                parallel,
+                patterns: Vec::new(),
+            })
+        };
+        match arg.as_str() {
+            "-p" | "--parallel" => group(true),
+            "-s" | "--sequential" | "--serial" => group(false),
+            "-c" | "--continue-on-error" => continue_on_error = true,
+            "-r" | "--race" => settings.kill_others = Some(true),
+            "--max-parallel" => {
+                let max = args.next().expect("--max-parallel needs a number");
+                let max = max
+                    .parse()
+                    .unwrap_or_else(|_| panic!("invalid --max-parallel {max:?}"));
+                settings.jobs = Some(max);
+            }
+            "-l" | "--print-label" | "-n" | "--print-name" | "--aggregate-output" | "--silent" => {}
+            "--" => {
+                eprintln!("ignoring arguments after --, placeholders like {{1}} are not supported");
+                break;
+            }
+            flag if flag.starts_with('-') => eprintln!("ignoring npm-run-all option {flag}"),
+            pattern => groups
+                .last_mut()
+                .expect("group")
+                .patterns
+                .push(pattern.to_string()),
+        }
+    }
+    if continue_on_error {
+        settings.continue_on_error = Some(true);
+    } else {
+        // npm-run-all aborts all scripts when one of them fails
+        settings.fail_fast = Some(true);
+    }
+
+    let root = std::env::current_dir().expect("current dir");
+    let manifest = workspace::read_manifest(&root.join("package.json"))
+        .unwrap_or_else(|| panic!("no package.json in {}", root.display()));
+    let scripts = manifest
+        .get("scripts")
+        .and_then(|s| s.as_object())
+        .map(|scripts| scripts.keys().cloned().collect::<Vec<_>>())
+        .unwrap_or_default();
+    let pm = init::package_manager(&root);
+
+    let mut entries: Vec<(String, Entry)> = Vec::new();
+    let mut previous = Vec::new();
+    for group in groups {
+        let mut names: Vec<String> = Vec::new();
+        for pattern in &group.patterns {
+            // "build -- --watch" passes the arguments to the script
+            let (pattern, script_args) = match pattern.split_once(' ') {
+                Some((pattern, script_args)) => (pattern, Some(script_args.trim())),
+                None => (pattern.as_str(), None),
+            };
+            let matched = matching(&scripts, pattern);
+            if matched.is_empty() {
+                panic!("no script in package.json matches {pattern:?}");
+            }
+            for script in matched {
+                let mut cmd = init::run_script(pm, script);
+                if let Some(script_args) = script_args {
+                    cmd = format!("{cmd} {script_args}");
+                }
+                let after = if group.parallel {
+                    previous.clone()
+                } else {
+                    names
+                        .last()
+                        .map_or(previous.clone(), |last| vec![last.clone()])
+                };
+                let name = import::unique(&entries, script.clone());
+                let entry = Entry {
+                    cmd,
+                    after,
+                    ..Default::default()
+                };
+                names.push(name.clone());
+                entries.push((name, entry));
+            }
+        }
+        if names.is_empty() {
+            continue;
+        }
+        previous = if group.parallel {
+            names
+        } else {
+            names.split_off(names.len() - 1)
+        };
+    }
+    if entries.is_empty() {
+        panic!("no scripts given");
+    }
+    (settings, entries)
+}
+
+/// The scripts matching `pattern`: `*` matches within a `:` separated part
+/// of the name, `**` across parts.
+fn matching<'a>(scripts: &'a [String], pattern: &str) -> Vec<&'a String> {
+    let glob = glob::Pattern::new(&pattern.replace(':', "/"))
+        .unwrap_or_else(|err| panic!("invalid pattern {pattern:?}: {err}"));
+    let options = glob::MatchOptions {
+        require_literal_separator: true,
+        ..Default::default()
+    };
+    scripts
+        .iter()
+        .filter(|script| glob.matches_with(&script.replace(':', "/"), options))
+        .collect()
+}
@@ -0,0 +1,143 @@
+//! `--output`: whether runall prints text (prefixed lines and notices) or
+//! one JSON object per line on stdout, for `jq`, log pipelines and CI
+//! annotations.
+//!
+//! In JSON mode every object has `ts` and `event`: `line` events carry
+//! `name`, `stream` and `line`, `notice` events runall's own `message`, and
+//! the lifecycle events (`started`, `ready`, `exited`, ...) the same fields
+//! as with `--events`.
+
+use std::{
+    io::Write,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+use serde_json::{json, Value};
+
+use crate::runner::Event;
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+pub fn set(format: Format) {
+    JSON.store(format == Format::Json, Ordering::Relaxed);
+}
+
+pub fn json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+/// Set by `--primary`: stdout is left to the primary command, all other
+/// text goes to stderr.
+static RESERVED: AtomicBool = AtomicBool::new(false);
+
+pub fn reserve_stdout() {
+    RESERVED.store(true, Ordering::Relaxed);
+}
+
+/// Prints an event as one line of JSON.
+pub fn print(event: &Value) {
+    stdout(format!("{event}\n").as_bytes());
+}
+
+/// Set once stdout turned out to be closed, e.g. by `| head`. Nothing is
+/// written to it from then on.
+static CLOSED: AtomicBool = AtomicBool::new(false);
+
+/// Where [`Event::Closed`] is sent.
+static ON_CLOSED: Mutex<Option<flume::Sender<Event>>> = Mutex::new(None);
+
+/// Ends the session through `events` once stdout is closed.
+pub fn on_closed(events: flume::Sender<Event>) {
+    *ON_CLOSED.lock().expect("lock") = Some(events);
+}
+
+/// Writes `bytes` to stdout, unless it was closed.
+pub fn stdout(bytes: &[u8]) {
+    if CLOSED.load(Ordering::Relaxed) {
+        return;
+    }
+    let result = {
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(bytes).and_then(|()| stdout.flush())
+    };
+    let closed = matches!(result, Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe);
+    if closed && !CLOSED.swap(true, Ordering::Relaxed) {
+        if let Some(events) = ON_CLOSED.lock().expect("lock").take() {
+            let _ = events.send(Event::Closed);
+        }
+    }
+}
+
+/// `--stats-bar`: the line below everything else on the terminal.
+static BAR: Mutex<Option<String>> = Mutex::new(None);
+
+/// Shows `text` in the status bar, `None` removes it.
+pub fn bar(text: Option<String>) {
+    let mut bar = BAR.lock().expect("lock");
+    let columns = crate::term::size().map_or(80, |(columns, _)| columns as usize);
+    // a line that wraps could not be cleared
+    let text = text.map(|text| text.chars().take(columns.saturating_sub(1)).collect());
+    let mut stderr = std::io::stderr().lock();
+    if bar.is_some() {
+        let _ = write!(stderr, "\r\x1b[K");
+    }
+    if let Some(text) = &text {
+        let _ = write!(stderr, "{text}");
+    }
+    let _ = stderr.flush();
+    *bar = text;
+}
+
+/// Prints `text` to stdout, above the status bar if there is one.
+pub fn out(text: &str) {
+    write(text, false);
+}
+
+/// Prints `text` to stderr, above the status bar if there is one.
+pub fn err(text: &str) {
+    write(text, true);
+}
+
+fn write(text: &str, to_stderr: bool) {
+    let bar = BAR.lock().expect("lock");
+    let mut stderr = std::io::stderr().lock();
+    if bar.is_some() {
+        let _ = write!(stderr, "\r\x1b[K");
+        let _ = stderr.flush();
+    }
+    if to_stderr || RESERVED.load(Ordering::Relaxed) {
+        let _ = write!(stderr, "{text}");
+    } else {
+        stdout(text.as_bytes());
+    }
+    if let Some(bar) = &*bar {
+        let _ = write!(stderr, "{bar}");
+    }
+    let _ = stderr.flush();
+}
+
+/// See the `notice!` macro.
+pub fn notice(message: std::fmt::Arguments) {
+    // notices quote the commands, which may contain secrets
+    let message = crate::redact::apply(message.to_string().into());
+    if crate::tui::active() {
+        crate::tui::notice(&message);
+    } else if json() {
+        print(&json!({
+            "ts": chrono::Local::now().to_rfc3339(),
+            "event": "notice",
+            "message": message,
+        }));
+    } else {
+        err(&format!("{message}\n"));
+    }
+}
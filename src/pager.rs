@@ -0,0 +1,36 @@
+//! `--page-summary`: the summary and the output of the failed commands are
+//! shown in `$PAGER` at the end of the session, instead of scrolling back
+//! through the output of all commands.
+
+use std::{io::Write, process::Stdio};
+
+use crate::shell;
+
+/// Lines of output kept for each failed command.
+pub const LINES: usize = 10_000;
+
+/// Shows `text` in `$PAGER`, or in `less` if it is not set. Returns false if
+/// the pager could not be started.
+pub fn show(text: &str) -> bool {
+    let pager = std::env::var("PAGER")
+        .ok()
+        .filter(|pager| !pager.trim().is_empty())
+        .unwrap_or_else(|| "less".to_string());
+    let Ok(mut command) = shell::command(&pager) else {
+        return false;
+    };
+    // like git: keep the colors and quit right away if it fits the screen
+    if std::env::var_os("LESS").is_none() {
+        command.env("LESS", "FRX");
+    }
+    let Ok(mut child) = crate::reaper::spawn(command.stdin(Stdio::piped())) else {
+        return false;
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // the pager may be quit before it read everything
+        let _ = stdin.write_all(text.as_bytes());
+    }
+    let status = child.wait();
+    crate::reaper::forget(child.id());
+    status.is_ok_and(|status| status.code() != Some(127))
+}
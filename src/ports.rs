@@ -0,0 +1,132 @@
+//! Detection of the TCP ports a process and its descendants listen on and
+//! of the process holding a port. Only implemented on Linux, where it is
+//! read from /proc.
+
+#[cfg(target_os = "linux")]
+use std::{collections::HashSet, fs};
+
+/// The listening TCP ports of `pid` and its descendants, sorted.
+#[cfg(target_os = "linux")]
+pub fn listening(pid: u32) -> Vec<u16> {
+    let inodes = descendants(pid)
+        .into_iter()
+        .flat_map(sockets)
+        .collect::<HashSet<u64>>();
+    if inodes.is_empty() {
+        return Vec::new();
+    }
+    let mut ports = listen_table()
+        .into_iter()
+        .filter(|(inode, _)| inodes.contains(inode))
+        .map(|(_, port)| port)
+        .collect::<Vec<_>>();
+    ports.sort_unstable();
+    ports.dedup();
+    ports
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn listening(_pid: u32) -> Vec<u16> {
+    Vec::new()
+}
+
+/// Whether something listens on `port` already, so that a command can't.
+pub fn taken(port: u16) -> bool {
+    let bound = std::net::TcpListener::bind((std::net::Ipv4Addr::UNSPECIFIED, port));
+    matches!(bound, Err(err) if err.kind() == std::io::ErrorKind::AddrInUse)
+}
+
+/// The process listening on `port` and its name, if it can be seen.
+#[cfg(target_os = "linux")]
+pub fn holder(port: u16) -> Option<(u32, String)> {
+    let inodes = listen_table()
+        .into_iter()
+        .filter(|&(_, listening)| listening == port)
+        .map(|(inode, _)| inode)
+        .collect::<HashSet<u64>>();
+    let pid = fs::read_dir("/proc")
+        .ok()?
+        .filter_map(Result::ok)
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .find(|&pid| sockets(pid).iter().any(|inode| inodes.contains(inode)))?;
+    let name = fs::read_to_string(format!("/proc/{pid}/comm")).unwrap_or_default();
+    Some((pid, name.trim().to_string()))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn holder(_port: u16) -> Option<(u32, String)> {
+    None
+}
+
+/// The inodes of the sockets `pid` has open.
+#[cfg(target_os = "linux")]
+fn sockets(pid: u32) -> HashSet<u64> {
+    fs::read_dir(format!("/proc/{pid}/fd"))
+        .into_iter()
+        .flat_map(|fds| fds.filter_map(Result::ok))
+        .filter_map(|fd| fs::read_link(fd.path()).ok())
+        .filter_map(|target| {
+            let target = target.to_str()?;
+            target
+                .strip_prefix("socket:[")?
+                .strip_suffix(']')?
+                .parse()
+                .ok()
+        })
+        .collect()
+}
+
+/// The socket inode and port of every listening TCP socket.
+#[cfg(target_os = "linux")]
+fn listen_table() -> Vec<(u64, u16)> {
+    ["/proc/net/tcp", "/proc/net/tcp6"]
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .flat_map(|table| {
+            table
+                .lines()
+                .skip(1)
+                .filter_map(|line| {
+                    let fields = line.split_whitespace().collect::<Vec<_>>();
+                    // 0A is TCP_LISTEN
+                    if fields.get(3) != Some(&"0A") {
+                        return None;
+                    }
+                    let inode = fields.get(9)?.parse::<u64>().ok()?;
+                    let (_, port) = fields.get(1)?.rsplit_once(':')?;
+                    Some((inode, u16::from_str_radix(port, 16).ok()?))
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// `pid` and all processes below it.
+#[cfg(target_os = "linux")]
+pub fn descendants(pid: u32) -> Vec<u32> {
+    let parents = fs::read_dir("/proc")
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let pid = entry.file_name().to_str()?.parse::<u32>().ok()?;
+            let stat = fs::read_to_string(entry.path().join("stat")).ok()?;
+            // the command name in parentheses may contain spaces
+            let (_, rest) = stat.rsplit_once(") ")?;
+            let ppid = rest.split_whitespace().nth(1)?.parse::<u32>().ok()?;
+            Some((pid, ppid))
+        })
+        .collect::<Vec<_>>();
+    let mut tree = vec![pid];
+    let mut i = 0;
+    while let Some(&parent) = tree.get(i) {
+        tree.extend(
+            parents
+                .iter()
+                .filter(|(_, ppid)| *ppid == parent)
+                .map(|(pid, _)| *pid),
+        );
+        i += 1;
+    }
+    tree
+}
@@ -0,0 +1,188 @@
+//! Formatting of the prefix that is put in front of each output line.
+
+use std::{sync::Arc, time::Instant};
+
+use crate::{color, duration, slots};
+
+pub const DEFAULT_FORMAT: &str = "[{name}]";
+
+/// `--prefix` presets for the prefix format.
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum Style {
+    /// `[name]`, the default.
+    Name,
+    /// `[1]`, counting from 1 in the order of the commands. Each name
+    /// keeps its index in later runs of the session.
+    Index,
+    /// `[4242]`, the process id.
+    Pid,
+    /// No prefix at all.
+    None,
+}
+
+/// Adds `--timestamps` and `--elapsed` in front of the prefix `template`.
+pub fn annotate(template: &str, timestamps: Option<&str>, elapsed: bool) -> String {
+    let mut template = template.to_string();
+    if elapsed {
+        template = format!("{{elapsed}} {template}");
+    }
+    if let Some(format) = timestamps {
+        template = format!("{{time:{format}}} {template}");
+    }
+    template.trim_end().to_string()
+}
+
+impl Style {
+    pub fn format(self) -> &'static str {
+        match self {
+            Style::Name => DEFAULT_FORMAT,
+            Style::Index => "[{index}]",
+            Style::Pid => "[{pid}]",
+            Style::None => "",
+        }
+    }
+}
+
+/// Renders the `--prefix-format` template for one job. Supported
+/// placeholders are `{name}`, `{index}` (counting from 1, kept across runs),
+/// `{pid}`, `{time}` (the current time), `{time:FORMAT}` (with a strftime
+/// format) and `{elapsed}` (time since the process started).
+#[derive(Clone)]
+pub struct Prefix {
+    template: Arc<str>,
+    name: String,
+    index: usize,
+    /// Spaces appended so that the output of all jobs lines up.
+    padding: usize,
+    started: Option<Instant>,
+    pid: Option<u32>,
+    /// ANSI color code, if colors are enabled.
+    color: Option<&'static str>,
+}
+
+impl Prefix {
+    /// One prefix per job name, padded to the longest name or to `width`.
+    /// Names longer than `width` are cut off with an ellipsis. Each job gets
+    /// a color of the palette when child output is colored, see [`slots`]
+    /// for which one.
+    pub fn for_names<'a>(
+        template: &str,
+        width: Option<usize>,
+        names: impl IntoIterator<Item = &'a str>,
+    ) -> Vec<Self> {
+        let template: Arc<str> = template.into();
+        let names = names.into_iter().collect::<Vec<_>>();
+        let slots = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| slots::get(name).unwrap_or(i))
+            .collect::<Vec<_>>();
+        let names = names
+            .into_iter()
+            .map(|name| match width {
+                Some(width) => truncate(name, width),
+                None => name.to_string(),
+            })
+            .collect::<Vec<_>>();
+        let width =
+            width.unwrap_or_else(|| names.iter().map(|n| n.chars().count()).max().unwrap_or(0));
+        let colored = color::output_enabled();
+        let padded = template.contains("{name}");
+        names
+            .into_iter()
+            .zip(slots)
+            .map(|(name, slot)| Self {
+                template: template.clone(),
+                padding: if padded {
+                    width.saturating_sub(name.chars().count())
+                } else {
+                    0
+                },
+                name,
+                index: slot + 1,
+                started: None,
+                pid: None,
+                color: colored.then(|| color::palette(slot)),
+            })
+            .collect()
+    }
+
+    pub fn started_at(&self, started: Instant) -> Self {
+        Self {
+            started: Some(started),
+            ..self.clone()
+        }
+    }
+
+    pub fn with_pid(&self, pid: u32) -> Self {
+        Self {
+            pid: Some(pid),
+            ..self.clone()
+        }
+    }
+
+    /// Whether there is no prefix (`--prefix none`).
+    pub fn is_empty(&self) -> bool {
+        self.template.is_empty()
+    }
+
+    /// The prefix without colors, e.g. to measure its width.
+    pub fn plain(&self) -> String {
+        let elapsed = match self.started {
+            Some(started) => duration::format(started.elapsed()),
+            None => "-".to_string(),
+        };
+        let mut rendered = self
+            .template
+            .replace("{name}", &self.name)
+            .replace("{index}", &self.index.to_string())
+            .replace(
+                "{pid}",
+                &self.pid.map_or("-".to_string(), |p| p.to_string()),
+            )
+            .replace("{elapsed}", &format!("{elapsed:>5}"));
+        if rendered.contains("{time}") {
+            let time = chrono::Local::now().format("%H:%M:%S").to_string();
+            rendered = rendered.replace("{time}", &time);
+        }
+        while let Some(start) = rendered.find("{time:") {
+            let Some(len) = rendered[start..].find('}') else {
+                break;
+            };
+            let format = &rendered[start + "{time:".len()..start + len];
+            let time = chrono::Local::now().format(format).to_string();
+            rendered.replace_range(start..=start + len, &time);
+        }
+        format!("{rendered}{:width$}", "", width = self.padding)
+    }
+
+    pub fn render(&self) -> String {
+        let plain = self.plain();
+        match self.color {
+            Some(code) => {
+                let text = plain.trim_end();
+                let padding = &plain[text.len()..];
+                format!("\x1b[{code}m{text}\x1b[0m{padding}")
+            }
+            None => plain,
+        }
+    }
+}
+
+fn truncate(name: &str, width: usize) -> String {
+    if name.chars().count() <= width {
+        return name.to_string();
+    }
+    let mut short = name
+        .chars()
+        .take(width.saturating_sub(1))
+        .collect::<String>();
+    short.push('…');
+    short
+}
+
+impl std::fmt::Display for Prefix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.render())
+    }
+}
@@ -0,0 +1,196 @@
+//! Readiness probes. A job with probes is considered ready once all of them
+//! pass, jobs without probes are ready as soon as they are started.
+
+use std::{
+    net::{Ipv4Addr, SocketAddr, TcpStream},
+    path::PathBuf,
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::runner::Event;
+
+pub const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Debug)]
+pub enum Probe {
+    /// The path exists (e.g. a unix socket or pid file).
+    File(PathBuf),
+    /// The path does not exist (anymore), e.g. a lock file.
+    FileGone(PathBuf),
+    /// A shell command exits successfully.
+    Command(CommandProbe),
+    /// A line of the job's own output matches.
+    Log(LogProbe),
+    /// Something accepts connections on the TCP port on localhost.
+    Port(u16),
+}
+
+/// Set by the output forwarding of the job, see [`LogProbe::check`].
+#[derive(Clone, Debug)]
+pub struct LogProbe {
+    pub regex: regex::Regex,
+    matched: Arc<AtomicBool>,
+}
+
+impl LogProbe {
+    pub fn new(regex: regex::Regex) -> Self {
+        Self {
+            regex,
+            matched: Default::default(),
+        }
+    }
+
+    /// Whether the job printed a matching line.
+    pub fn matched(&self) -> bool {
+        self.matched.load(Ordering::Relaxed)
+    }
+
+    /// Called for every line the job prints.
+    pub fn check(&self, line: &str) {
+        if !self.matched.load(Ordering::Relaxed) && self.regex.is_match(line) {
+            self.matched.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CommandProbe {
+    pub cmd: String,
+    pub cwd: Option<PathBuf>,
+    pub interval: Duration,
+    /// Give up after that many failed attempts. Unlimited if `None`.
+    pub retries: Option<u32>,
+}
+
+impl CommandProbe {
+    pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+
+    pub fn new(cmd: impl ToString, cwd: Option<PathBuf>) -> Self {
+        Self {
+            cmd: cmd.to_string(),
+            cwd,
+            interval: Self::DEFAULT_INTERVAL,
+            retries: None,
+        }
+    }
+
+    fn run(&self) -> bool {
+        let Ok(mut command) = crate::shell::command(&self.cmd) else {
+            return false;
+        };
+        command
+            .stdin(process::Stdio::null())
+            .stdout(process::Stdio::null())
+            .stderr(process::Stdio::null());
+        if let Some(cwd) = &self.cwd {
+            command.current_dir(cwd);
+        }
+        crate::reaper::status(&mut command).is_ok_and(|status| status.success())
+    }
+}
+
+impl Probe {
+    /// The probe for a new run of the job, log probes start over.
+    pub fn fresh(&self) -> Self {
+        match self {
+            Probe::Log(probe) => Probe::Log(LogProbe::new(probe.regex.clone())),
+            probe => probe.clone(),
+        }
+    }
+
+    fn interval(&self) -> Duration {
+        match self {
+            Probe::Command(probe) => probe.interval,
+            _ => POLL_INTERVAL,
+        }
+    }
+
+    fn retries(&self) -> Option<u32> {
+        match self {
+            Probe::Command(probe) => probe.retries,
+            _ => None,
+        }
+    }
+
+    fn check(&self) -> bool {
+        match self {
+            Probe::File(path) => path.exists(),
+            Probe::FileGone(path) => !path.exists(),
+            Probe::Command(probe) => probe.run(),
+            Probe::Log(probe) => probe.matched.load(Ordering::Relaxed),
+            Probe::Port(port) => {
+                let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, *port));
+                TcpStream::connect_timeout(&addr, POLL_INTERVAL).is_ok()
+            }
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Probe::File(path) => format!("waiting for {}", path.display()),
+            Probe::FileGone(path) => format!("waiting for {} to vanish", path.display()),
+            Probe::Command(probe) => format!("ready check {:?}", probe.cmd),
+            Probe::Log(probe) => format!("waiting for output matching {:?}", probe.regex.as_str()),
+            Probe::Port(port) => format!("waiting for port {port}"),
+        }
+    }
+}
+
+/// Polls `probes` until all of them passed and then sends [`Event::Ready`],
+/// or [`Event::NotReady`] if a probe ran out of retries. Polling stops early
+/// when `cancel` is disconnected.
+pub fn watch(
+    idx: usize,
+    probes: Vec<Probe>,
+    events: flume::Sender<Event>,
+    cancel: flume::Receiver<()>,
+) {
+    std::thread::spawn(move || {
+        let now = Instant::now();
+        let mut pending = probes
+            .into_iter()
+            .map(|probe| (probe, 0, now))
+            .collect::<Vec<_>>();
+        loop {
+            let now = Instant::now();
+            let mut failed = None;
+            pending.retain_mut(|(probe, attempts, next_check)| {
+                if *next_check > now {
+                    return true;
+                }
+                if probe.check() {
+                    return false;
+                }
+                *attempts += 1;
+                *next_check = now + probe.interval();
+                if probe.retries().is_some_and(|retries| *attempts > retries) {
+                    failed = Some(format!("{} failed {attempts} times", probe.describe()));
+                }
+                true
+            });
+            if let Some(reason) = failed {
+                let _ = events.send(Event::NotReady(idx, reason));
+                return;
+            }
+            if pending.is_empty() {
+                let _ = events.send(Event::Ready(idx));
+                return;
+            }
+
+            let next = pending
+                .iter()
+                .map(|(_, _, n)| *n)
+                .min()
+                .expect("pending probe");
+            let wait = next.saturating_duration_since(Instant::now());
+            if let Err(flume::RecvTimeoutError::Disconnected) = cancel.recv_timeout(wait) {
+                return;
+            }
+        }
+    });
+}
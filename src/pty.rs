@@ -0,0 +1,96 @@
+//! `--pty`: the commands write to a pseudo-terminal instead of pipes, so
+//! that they keep their colors and progress output. A terminal has one
+//! output, so stdout and stderr arrive together.
+
+use std::{
+    fs::File,
+    io::Read,
+    os::fd::{AsRawFd, FromRawFd},
+    process::{Command, Stdio},
+};
+
+/// The side of the terminal runall reads from.
+pub struct Master(File);
+
+/// Opens a terminal of `size` (columns and rows) and makes it the
+/// controlling terminal, stdout and stderr of `command`.
+pub fn attach(command: &mut Command, size: Option<(u16, u16)>) -> std::io::Result<Master> {
+    let (mut master, mut slave) = (0, 0);
+    let size = size.map(|(columns, rows)| libc::winsize {
+        ws_row: rows,
+        ws_col: columns,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    });
+    let size_ptr = size
+        .as_ref()
+        .map_or(std::ptr::null(), |size| size as *const libc::winsize);
+    // SAFETY: the pointers are valid for the duration of the call, the
+    // name is not requested
+    let opened = unsafe {
+        libc::openpty(
+            &mut master,
+            &mut slave,
+            std::ptr::null_mut(),
+            std::ptr::null(),
+            size_ptr,
+        )
+    };
+    if opened != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // SAFETY: openpty returned both descriptors, nothing else owns them
+    let (master, slave) = unsafe { (File::from_raw_fd(master), File::from_raw_fd(slave)) };
+    for fd in [master.as_raw_fd(), slave.as_raw_fd()] {
+        // SAFETY: fcntl on a descriptor that is open
+        unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+    }
+    command
+        .stdout(Stdio::from(slave.try_clone()?))
+        .stderr(Stdio::from(slave));
+    // SAFETY: only async-signal-safe calls between fork and exec
+    unsafe {
+        std::os::unix::process::CommandExt::pre_exec(command, || {
+            // a session of its own, with the terminal on stdout as its
+            // controlling terminal
+            if libc::setsid() == -1 || libc::ioctl(libc::STDOUT_FILENO, libc::TIOCSCTTY, 0) == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    Ok(Master(master))
+}
+
+impl Master {
+    /// The output of the command, see [`Reader`].
+    pub fn reader(&self) -> std::io::Result<Reader> {
+        Ok(Reader(self.0.try_clone()?))
+    }
+
+    /// Tells the command that runall's terminal changed its size; the
+    /// kernel sends it SIGWINCH.
+    pub fn resize(&self, (columns, rows): (u16, u16)) {
+        let size = libc::winsize {
+            ws_row: rows,
+            ws_col: columns,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        // SAFETY: TIOCSWINSZ reads a winsize from the valid pointer
+        unsafe { libc::ioctl(self.0.as_raw_fd(), libc::TIOCSWINSZ, &size) };
+    }
+}
+
+/// Reading from the master fails with EIO once all processes closed the
+/// terminal, which is the end of the output.
+pub struct Reader(File);
+
+impl Read for Reader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self.0.read(buf) {
+            Err(err) if err.raw_os_error() == Some(libc::EIO) => Ok(0),
+            result => result,
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//! On linux runall is a child subreaper: processes that the commands
+//! started and left behind, like daemons that fork twice, are re-parented
+//! to runall instead of init. They are reaped once they exit, so that no
+//! zombies pile up, and the ones still running when the session ends are
+//! stopped with it.
+//!
+//! runall's own processes, the commands, their `--pipe` filters, readiness
+//! checks, the git calls and the like, are started through [`spawn`],
+//! [`status`] or [`output`] so that they can be told apart. They are waited
+//! for where they were started, a plain [`Command::output`] could find its
+//! child reaped already.
+
+use std::{
+    collections::BTreeSet,
+    io,
+    process::{Child, Command, ExitStatus, Output, Stdio},
+    sync::Mutex,
+};
+
+/// The processes started by runall that haven't been waited for yet.
+static OWN: Mutex<BTreeSet<u32>> = Mutex::new(BTreeSet::new());
+
+/// Starts `command` as one of runall's own processes. Pass the pid to
+/// [`forget`] once it was waited for.
+pub fn spawn(command: &mut Command) -> io::Result<Child> {
+    // held until the pid is known, so that the reaper can't take the
+    // process for one that was left behind
+    let mut own = OWN.lock().expect("lock");
+    let child = command.spawn()?;
+    own.insert(child.id());
+    Ok(child)
+}
+
+pub fn forget(pid: u32) {
+    OWN.lock().expect("lock").remove(&pid);
+}
+
+/// Like [`Command::status`], for one of runall's own processes.
+pub fn status(command: &mut Command) -> io::Result<ExitStatus> {
+    let mut child = spawn(command)?;
+    let status = child.wait();
+    forget(child.id());
+    status
+}
+
+/// Like [`Command::output`], for one of runall's own processes. Only stdout
+/// is captured, stderr goes wherever `command` sends it.
+pub fn output(command: &mut Command) -> io::Result<Output> {
+    let child = spawn(command.stdin(Stdio::null()).stdout(Stdio::piped()))?;
+    let pid = child.id();
+    let output = child.wait_with_output();
+    forget(pid);
+    output
+}
+
+#[cfg(target_os = "linux")]
+pub use linux::{start, stop_orphans};
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::{
+        sync::atomic::{AtomicBool, Ordering},
+        time::{Duration, Instant},
+    };
+
+    use nix::{
+        sys::wait::{waitpid, WaitPidFlag},
+        unistd::Pid,
+    };
+
+    use super::OWN;
+
+    const INTERVAL: Duration = Duration::from_secs(1);
+    /// How long the processes left behind get to stop before they are
+    /// killed.
+    const GRACE: Duration = Duration::from_secs(2);
+
+    /// Set once the session is over, what runall starts then is waited
+    /// for without the reaper.
+    static STOPPED: AtomicBool = AtomicBool::new(false);
+
+    /// Makes runall the subreaper of the commands and reaps what they
+    /// leave behind from now on.
+    pub fn start() {
+        // SAFETY: plain FFI call without pointers
+        if unsafe { libc::prctl(libc::PR_SET_CHILD_SUBREAPER, 1, 0, 0, 0) } != 0 {
+            notice!(
+                "cannot become a subreaper: {}",
+                std::io::Error::last_os_error()
+            );
+            return;
+        }
+        std::thread::spawn(|| loop {
+            std::thread::sleep(INTERVAL);
+            // the session may have ended while sleeping
+            if STOPPED.load(Ordering::Relaxed) {
+                break;
+            }
+            reap();
+        });
+    }
+
+    /// Reaps the processes left behind that exited. Returns the ones still
+    /// running.
+    fn reap() -> Vec<u32> {
+        let own = OWN.lock().expect("lock");
+        let mut running = Vec::new();
+        for (pid, zombie) in children() {
+            if own.contains(&pid) {
+                continue;
+            }
+            if zombie {
+                let _ = waitpid(Pid::from_raw(pid as i32), Some(WaitPidFlag::WNOHANG));
+            } else {
+                running.push(pid);
+            }
+        }
+        running
+    }
+
+    /// runall's children, and whether each is a zombie.
+    fn children() -> Vec<(u32, bool)> {
+        let me = std::process::id();
+        std::fs::read_dir("/proc")
+            .into_iter()
+            .flatten()
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let pid = entry.file_name().to_str()?.parse::<u32>().ok()?;
+                let stat = std::fs::read_to_string(entry.path().join("stat")).ok()?;
+                // the command name in parentheses may contain spaces
+                let (_, rest) = stat.rsplit_once(") ")?;
+                let mut fields = rest.split_whitespace();
+                let state = fields.next()?;
+                let ppid = fields.next()?.parse::<u32>().ok()?;
+                (ppid == me).then_some((pid, state == "Z"))
+            })
+            .collect()
+    }
+
+    /// Once the commands exited: stops the processes they left behind and
+    /// the reaper.
+    pub fn stop_orphans() {
+        let orphans = reap();
+        STOPPED.store(true, Ordering::Relaxed);
+        if orphans.is_empty() {
+            return;
+        }
+        notice!(
+            "stopping {} processes the commands left behind",
+            orphans.len()
+        );
+        for &pid in &orphans {
+            crate::runner::signal_tree(pid, "SIGTERM");
+        }
+        let deadline = Instant::now() + GRACE;
+        while Instant::now() < deadline {
+            if reap().is_empty() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        for pid in reap() {
+            crate::runner::signal_tree(pid, "SIGKILL");
+            let _ = waitpid(Pid::from_raw(pid as i32), None);
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! `--redact`, `--redact-env` and `--redact-tokens`: secrets are masked in
+//! the output of the processes before it is printed, logged or kept for the
+//! failure summary, and in runall's own messages and bug report bundles.
+//! The raw bytes of `--mux-fd` are passed on unchanged.
+
+use std::{borrow::Cow, sync::OnceLock};
+
+use regex::Regex;
+
+const MASK: &str = "[redacted]";
+
+/// Well known token formats masked by `--redact-tokens`.
+const TOKENS: [&str; 6] = [
+    // AWS access key ids
+    r"\b(AKIA|ASIA)[0-9A-Z]{16}\b",
+    // GitHub tokens
+    r"\bgh[pousr]_[A-Za-z0-9]{36,}\b",
+    r"\bgithub_pat_[A-Za-z0-9_]{22,}\b",
+    // Slack tokens
+    r"\bxox[abposr]-[A-Za-z0-9-]{10,}",
+    // JSON web tokens
+    r"\beyJ[A-Za-z0-9_-]{8,}\.[A-Za-z0-9_-]{8,}\.[A-Za-z0-9_-]{8,}",
+    // bearer tokens in headers
+    r"(?i)\bbearer\s+[A-Za-z0-9._~+/=-]{16,}",
+];
+
+static SECRETS: OnceLock<Option<Regex>> = OnceLock::new();
+
+/// Masks matches of `patterns`, the `values` of the `--redact-env` variables
+/// and, with `tokens`, [`TOKENS`].
+pub fn set(patterns: &[String], values: impl IntoIterator<Item = String>, tokens: bool) {
+    let mut alternatives = patterns.to_vec();
+    alternatives.extend(
+        values
+            .into_iter()
+            // short values would mask too much
+            .filter(|value| value.len() >= 4)
+            .map(|value| regex::escape(&value)),
+    );
+    if tokens {
+        alternatives.extend(TOKENS.map(str::to_string));
+    }
+    let regex = (!alternatives.is_empty()).then(|| {
+        let combined = alternatives
+            .iter()
+            .map(|pattern| format!("(?:{pattern})"))
+            .collect::<Vec<_>>()
+            .join("|");
+        Regex::new(&combined).unwrap_or_else(|err| panic!("--redact: {err}"))
+    });
+    if SECRETS.set(regex).is_err() {
+        panic!("redaction set twice");
+    }
+}
+
+/// `line` with the secrets masked.
+pub fn apply(line: Cow<str>) -> Cow<str> {
+    match SECRETS.get().and_then(Option::as_ref) {
+        Some(secrets) if secrets.is_match(&line) => {
+            Cow::Owned(secrets.replace_all(&line, MASK).into_owned())
+        }
+        _ => line,
+    }
+}
@@ -0,0 +1,193 @@
+//! `runall report diff`: compares the summaries of two sessions run with
+//! `--output json`, to see which commands got slower, started failing or
+//! print more than before.
+
+use std::{collections::BTreeMap, path::Path, time::Duration};
+
+use crate::{color, duration, status, usage};
+
+#[derive(clap::Args)]
+pub struct ReportArgs {
+    #[clap(subcommand)]
+    pub action: ReportAction,
+}
+
+#[derive(clap::Subcommand)]
+pub enum ReportAction {
+    /// Compare two reports and highlight the regressions. Exits with 1 if
+    /// there are any.
+    Diff(DiffArgs),
+}
+
+#[derive(clap::Args)]
+pub struct DiffArgs {
+    /// The output of `runall --output json` of the earlier session, or its
+    /// summary line alone.
+    pub old: std::path::PathBuf,
+
+    /// The same for the later session.
+    pub new: std::path::PathBuf,
+
+    /// How much longer a command may run before it counts as a regression,
+    /// in percent.
+    #[clap(long, value_name = "PERCENT", default_value_t = 10.0)]
+    pub threshold: f64,
+}
+
+/// Differences in duration below that are noise, whatever the percentage.
+const NOISE: Duration = Duration::from_millis(100);
+
+/// A command in the summary of a session.
+#[derive(serde::Deserialize)]
+struct Command {
+    name: String,
+    code: Option<i32>,
+    success: bool,
+    #[serde(default)]
+    timed_out: bool,
+    duration_ms: u64,
+    cpu_ms: Option<u64>,
+    peak_rss_kb: Option<u64>,
+    lines: u64,
+}
+
+#[derive(serde::Deserialize)]
+struct Summary {
+    commands: Vec<Command>,
+}
+
+/// The last summary in `path`, one JSON object per line.
+fn read(path: &Path) -> BTreeMap<String, Command> {
+    let text = std::fs::read_to_string(path)
+        .unwrap_or_else(|err| panic!("cannot read {}: {err}", path.display()));
+    let summary = text
+        .lines()
+        .rev()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .find(|value| value.get("commands").is_some())
+        .unwrap_or_else(|| panic!("no summary in {}, run with --output json", path.display()));
+    let summary = serde_json::from_value::<Summary>(summary)
+        .unwrap_or_else(|err| panic!("invalid summary in {}: {err}", path.display()));
+    summary
+        .commands
+        .into_iter()
+        .map(|command| (command.name.clone(), command))
+        .collect()
+}
+
+fn exit(command: &Command) -> String {
+    match command.code {
+        _ if command.timed_out => "timed out".to_string(),
+        Some(code) => code.to_string(),
+        None if command.success => "0".to_string(),
+        None => "signal".to_string(),
+    }
+}
+
+/// `old → new`, or `new` alone if nothing changed.
+fn change(old: String, new: String) -> String {
+    if old == new {
+        new
+    } else {
+        format!("{old} → {new}")
+    }
+}
+
+/// The change of a number as a percentage, if there was one before.
+fn percent(old: u64, new: u64) -> Option<f64> {
+    (old > 0).then(|| (new as f64 - old as f64) * 100.0 / old as f64)
+}
+
+fn with_percent(old: String, new: String, percent: Option<f64>) -> String {
+    match percent {
+        Some(percent) if old != new => format!("{} ({percent:+.0}%)", change(old, new)),
+        _ => change(old, new),
+    }
+}
+
+/// Prints the table of both reports side by side and returns whether there
+/// were no regressions.
+pub fn report(args: ReportArgs) -> bool {
+    let ReportAction::Diff(args) = args.action;
+    let old = read(&args.old);
+    let new = read(&args.new);
+    let millis = |ms: u64| duration::format(Duration::from_millis(ms));
+    let size = |kb: Option<u64>| kb.map_or("-".to_string(), |kb| usage::format_size(kb * 1024));
+
+    let mut cells = Vec::new();
+    let mut regressions = 0;
+    for (name, after) in &new {
+        let Some(before) = old.get(name) else {
+            cells.push([
+                name.clone(),
+                exit(after),
+                millis(after.duration_ms),
+                after.cpu_ms.map_or("-".to_string(), millis),
+                size(after.peak_rss_kb),
+                after.lines.to_string(),
+                "new".to_string(),
+            ]);
+            continue;
+        };
+        let mut notes = Vec::new();
+        if before.success && !after.success {
+            notes.push("fails");
+        } else if !before.success && after.success {
+            notes.push("fixed");
+        }
+        let slower = after.duration_ms.saturating_sub(before.duration_ms);
+        let duration = percent(before.duration_ms, after.duration_ms);
+        if slower > NOISE.as_millis() as u64 && duration.is_some_and(|p| p > args.threshold) {
+            notes.push("slower");
+        }
+        let regressed = notes.iter().any(|note| *note != "fixed");
+        regressions += usize::from(regressed);
+        cells.push([
+            name.clone(),
+            change(exit(before), exit(after)),
+            with_percent(
+                millis(before.duration_ms),
+                millis(after.duration_ms),
+                duration,
+            ),
+            change(
+                before.cpu_ms.map_or("-".to_string(), millis),
+                after.cpu_ms.map_or("-".to_string(), millis),
+            ),
+            change(size(before.peak_rss_kb), size(after.peak_rss_kb)),
+            with_percent(
+                before.lines.to_string(),
+                after.lines.to_string(),
+                percent(before.lines, after.lines),
+            ),
+            // the report goes to stdout, unlike runall's own colored messages
+            if regressed && color::output_enabled() {
+                format!("\x1b[31m{}\x1b[0m", notes.join(", "))
+            } else {
+                notes.join(", ")
+            },
+        ]);
+    }
+    for (name, before) in &old {
+        if !new.contains_key(name) {
+            cells.push([
+                name.clone(),
+                exit(before),
+                millis(before.duration_ms),
+                before.cpu_ms.map_or("-".to_string(), millis),
+                size(before.peak_rss_kb),
+                before.lines.to_string(),
+                "gone".to_string(),
+            ]);
+        }
+    }
+
+    let header = ["NAME", "EXIT", "DURATION", "CPU", "PEAK MEM", "LINES", ""];
+    print!("{}", status::align(&header, &cells));
+    if regressions == 0 {
+        println!("no regressions");
+    } else {
+        println!("{regressions} regressed");
+    }
+    regressions == 0
+}
@@ -0,0 +1,143 @@
+//! `runall rerun-failed`: every session leaves the command line it was
+//! started with and the result of each command in a state file. Rerunning
+//! resolves the commands the same way and starts only the tasks that failed
+//! or never got to run, together with the commands they `--needs`, keeping
+//! their names, environment and order.
+
+use std::{ffi::OsString, path::PathBuf, process::ExitStatus, sync::OnceLock};
+
+use crate::{daemon::SessionArgs, logs, Job, Kind};
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct State {
+    cwd: PathBuf,
+    argv: Vec<String>,
+    commands: Vec<Command>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Command {
+    name: String,
+    result: Outcome,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Outcome {
+    Passed,
+    Failed,
+    /// Not started, e.g. because a dependency failed.
+    Skipped,
+    /// Services and commands without autostart don't count either way.
+    NotCounted,
+}
+
+/// The command line of the session being rerun, and the names of the
+/// commands to run again.
+static RERUN: OnceLock<(Vec<String>, Vec<String>)> = OnceLock::new();
+
+fn path(session: &str) -> PathBuf {
+    let root = logs::root();
+    let name = logs::file_name(session);
+    let dir = root.parent().unwrap_or(&root).join("last");
+    dir.join(format!("{}.json", name.trim_end_matches(".log")))
+}
+
+/// The command line of the session, the one that it reruns if it was
+/// started by `runall rerun-failed`.
+pub fn argv() -> Vec<String> {
+    match RERUN.get() {
+        Some((argv, _)) => argv.clone(),
+        None => std::env::args_os()
+            .map(|arg| arg.to_string_lossy().to_string())
+            .collect(),
+    }
+}
+
+/// Records the results of the session for a later `runall rerun-failed`.
+pub fn save(session: &str, jobs: &[Job], statuses: &[Option<ExitStatus>]) {
+    let argv = argv();
+    let commands = jobs
+        .iter()
+        .zip(statuses)
+        .map(|(job, status)| Command {
+            name: job.name.clone(),
+            result: match status {
+                _ if job.kind == Kind::Service => Outcome::NotCounted,
+                Some(status) if status.success() => Outcome::Passed,
+                Some(_) => Outcome::Failed,
+                None if job.autostart => Outcome::Skipped,
+                None => Outcome::NotCounted,
+            },
+        })
+        .collect();
+    let Ok(cwd) = std::env::current_dir() else {
+        return;
+    };
+    let state = State {
+        cwd,
+        argv,
+        commands,
+    };
+    let path = path(session);
+    let written = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|()| {
+            let json = serde_json::to_string(&state).expect("serialize state");
+            std::fs::write(&path, format!("{json}\n"))
+        });
+    if let Err(err) = written {
+        notice!("cannot write {}: {err}", path.display());
+    }
+}
+
+/// Changes to the directory of the last run of the session and returns its
+/// command line. Exits if nothing failed.
+pub fn load(args: &SessionArgs) -> Vec<OsString> {
+    let session = args.session.clone().unwrap_or_else(logs::default_session);
+    let path = path(&session);
+    let text = std::fs::read_to_string(&path)
+        .unwrap_or_else(|err| panic!("no earlier run of session {session:?} ({err})"));
+    let state = serde_json::from_str::<State>(&text)
+        .unwrap_or_else(|err| panic!("invalid state {}: {err}", path.display()));
+    let failed = state
+        .commands
+        .into_iter()
+        .filter(|command| matches!(command.result, Outcome::Failed | Outcome::Skipped))
+        .map(|command| command.name)
+        .collect::<Vec<_>>();
+    if failed.is_empty() {
+        eprintln!("nothing failed in the last run of session {session}");
+        std::process::exit(0);
+    }
+    std::env::set_current_dir(&state.cwd)
+        .unwrap_or_else(|err| panic!("cannot change to {}: {err}", state.cwd.display()));
+    eprintln!("running {} again", failed.join(", "));
+    let argv = state.argv.iter().map(OsString::from).collect();
+    let _ = RERUN.set((state.argv, failed));
+    argv
+}
+
+/// With `runall rerun-failed`: which of `jobs` run again, the failed ones
+/// and what they need.
+pub fn selected(jobs: &[Job]) -> Option<Vec<bool>> {
+    let (_, failed) = RERUN.get()?;
+    let mut selected = jobs
+        .iter()
+        .map(|job| failed.contains(&job.name))
+        .collect::<Vec<_>>();
+    let mut todo = (0..jobs.len())
+        .filter(|&idx| selected[idx])
+        .collect::<Vec<_>>();
+    while let Some(idx) = todo.pop() {
+        let job = &jobs[idx];
+        let handoffs = job.after_output.iter().map(|(dep, _)| *dep);
+        for dep in job.needs.iter().copied().chain(handoffs) {
+            if !std::mem::replace(&mut selected[dep], true) {
+                todo.push(dep);
+            }
+        }
+    }
+    Some(selected)
+}
@@ -0,0 +1,2874 @@
+//! Supervision of the running jobs: starts them once their dependencies are
+//! met, forwards their output and reacts to exits, readiness and ctrl-c.
+
+use std::{
+    cell::Cell,
+    io::Write,
+    process,
+    sync::{atomic::AtomicBool, Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use chrono::Local;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    artifacts, browser,
+    budget::{self, Budget},
+    color,
+    condition::Condition,
+    duration, embed,
+    forward::{fwd_stream, Compact, Forward, Ordered, Release, Sink, Tail},
+    logs, output, pager, ports,
+    prefix::Prefix,
+    probe::{self, Probe},
+    redact, shell, stats, status, template, term,
+    throttle::Throttle,
+    usage, Job, Kind,
+};
+
+pub enum Event {
+    Exited(usize, Exit),
+    Ready(usize),
+    /// A readiness probe gave up, with the reason.
+    NotReady(usize, String),
+    Interrupt,
+    /// Ctrl-c, an interrupt unless it is passed on (`--forward-ctrlc`).
+    CtrlC,
+    /// runall was suspended (ctrl-z).
+    #[cfg(unix)]
+    Suspend,
+    /// runall was continued after being suspended.
+    #[cfg(unix)]
+    Resume,
+    /// The terminal was resized.
+    #[cfg(unix)]
+    Resize,
+    /// runall got SIGUSR1 or SIGUSR2 to pass on, see `--forward-signals`.
+    #[cfg(unix)]
+    Relay(&'static str),
+    /// A command entered at the `--interactive` prompt.
+    Launch(Box<Job>),
+    /// Print the status table (`:status`, SIGUSR1 or SIGINFO).
+    Status,
+    /// Files watched by the job changed (`--watch`).
+    Changed(usize),
+    /// Start, restart or stop a job, from the `--tui` keys.
+    Start(usize),
+    Restart(usize),
+    Stop(usize),
+    /// A request of `runall ctl`.
+    Control(crate::ctl::Request),
+    /// runall's stdout was closed, e.g. by `| head`.
+    Closed,
+}
+
+pub struct Exit {
+    pub status: process::ExitStatus,
+    /// Peak memory usage in KiB, if known.
+    pub peak_rss: Option<u64>,
+    /// CPU time of the process and the descendants it waited for.
+    pub cpu: Option<Duration>,
+    /// Hex SHA-256 of stdout with `--checksum`.
+    pub checksum: Option<String>,
+    /// The last lines of output, see `--failure-context`.
+    pub tail: Vec<String>,
+    /// Number of lines of output.
+    pub lines: u64,
+}
+
+struct Process {
+    idx: usize,
+    pid: u32,
+    prefix: Prefix,
+    started: Instant,
+    timeout: Timeout,
+    /// Warned that it takes longer than its `--expected-duration`.
+    overdue: bool,
+    shutdown: Cell<Shutdown>,
+    /// When the process last printed something, or was last reported as
+    /// silent by `--still-running`.
+    last_output: Arc<Mutex<Instant>>,
+    /// The last line the process printed, for the status table.
+    last_line: Arc<Mutex<String>>,
+    /// Dropped together with the process to stop its probes.
+    _probe_cancel: flume::Sender<()>,
+    /// The terminal of the process with `--pty`.
+    #[cfg(unix)]
+    pty: Option<crate::pty::Master>,
+    /// Kills the whole process tree when terminated or dropped.
+    #[cfg(windows)]
+    job_object: Option<crate::job_object::JobObject>,
+}
+
+impl Process {
+    /// Starts the next run of the job `idx`, or says why it can't be
+    /// started.
+    fn spawn(runner: &Runner, idx: usize) -> Result<Self, String> {
+        let job = &runner.jobs[idx];
+        let runs = &runner.runs[idx];
+        let run = runs.passed + runs.failed + 1;
+        let options = &runner.options;
+        let visible = &runner.visible[idx];
+        let events = runner.events_tx.clone();
+        let started = Instant::now();
+        let prefix = runner.prefixes[idx].started_at(started);
+        let name = &job.name;
+        let cmd = &job.cmd;
+
+        if job.start_banner {
+            notice!("starting {cmd} as {name}");
+        }
+
+        let mut command = match options.line_buffer {
+            true => shell::line_buffered(cmd),
+            false => shell::command(cmd),
+        }
+        .map_err(|err| format!("cannot start {cmd}: {err}"))?;
+        if options.line_buffer {
+            // python buffers on its own, stdbuf doesn't reach it
+            command.env("PYTHONUNBUFFERED", "1");
+        }
+        if let Some(path) = &job.stdin_file {
+            let file = std::fs::File::open(path)
+                .map_err(|err| format!("cannot open {}: {err}", path.display()))?;
+            command.stdin(file);
+        } else {
+            command.stdin(match job.stdin_policy {
+                StdinPolicy::Inherit if runner.reads_stdin => {
+                    // stdin belongs to runall
+                    process::Stdio::null()
+                }
+                StdinPolicy::Inherit => process::Stdio::inherit(),
+                StdinPolicy::Null => process::Stdio::null(),
+                StdinPolicy::Pipe => process::Stdio::piped(),
+            });
+        }
+        command
+            .stdout(process::Stdio::piped())
+            .stderr(process::Stdio::piped());
+        // so that it can be sent ctrl-break events on its own
+        #[cfg(windows)]
+        std::os::windows::process::CommandExt::creation_flags(
+            &mut command,
+            windows_sys::Win32::System::Threading::CREATE_NEW_PROCESS_GROUP,
+        );
+        if let Some(cwd) = &job.cwd {
+            command.current_dir(cwd);
+        }
+        command.envs(job.env.iter().map(|(key, value)| (key, value)));
+        if let Some(port) = job.port {
+            command.env("PORT", port.to_string());
+        }
+        let size = child_size(options, &prefix);
+        if let Some((columns, lines)) = size {
+            command
+                .env("COLUMNS", columns.to_string())
+                .env("LINES", lines.to_string());
+        }
+        #[cfg(unix)]
+        let pty = match options.pty {
+            true => Some(
+                crate::pty::attach(&mut command, size.or(Some((80, 24))))
+                    .map_err(|err| format!("cannot open a terminal for {name}: {err}"))?,
+            ),
+            false => None,
+        };
+        #[cfg(not(unix))]
+        if options.pty {
+            return Err("--pty is only supported on unix".to_string());
+        }
+        let mut proc = crate::reaper::spawn(&mut command)
+            .map_err(|err| format!("cannot start {cmd}: {err}"))?;
+        // `--pipe`: stdout goes through the filter, whose own errors are
+        // shown like those of the process
+        let mut filter = match &job.pipe {
+            Some(pipe) => {
+                let out = proc.stdout.take().expect("piped stdout");
+                let spawned = shell::command(pipe).and_then(|mut command| {
+                    if let Some(cwd) = &job.cwd {
+                        command.current_dir(cwd);
+                    }
+                    command
+                        .stdin(out)
+                        .stdout(process::Stdio::piped())
+                        .stderr(process::Stdio::piped());
+                    crate::reaper::spawn(&mut command)
+                });
+                match spawned {
+                    Ok(filter) => Some(filter),
+                    Err(err) => {
+                        let _ = proc.kill();
+                        let _ = proc.wait();
+                        crate::reaper::forget(proc.id());
+                        return Err(format!("cannot start pipe {pipe:?}: {err}"));
+                    }
+                }
+            }
+            None => None,
+        };
+        let prefix = prefix.with_pid(proc.id());
+        // before any of its output
+        runner.publish(
+            "started",
+            idx,
+            || serde_json::json!({"pid": proc.id(), "cmd": cmd}),
+        );
+        runner.report(|| embed::Event::Started {
+            name: name.clone(),
+            pid: proc.id(),
+        });
+        if let Some(mux) = &options.mux {
+            mux.frame(idx, crate::mux::START, name.as_bytes());
+        }
+        if let Some(stdin) = proc.stdin.take() {
+            // after runall's stdin was closed the child gets EOF right away
+            if let Some(pipes) = &mut *runner.stdin_pipes.lock().expect("lock") {
+                pipes.push((idx, stdin));
+            }
+        }
+        #[cfg(windows)]
+        let job_object = crate::job_object::JobObject::assign(&proc)
+            .map_err(|err| notice!("{prefix} cannot create job object: {err}"))
+            .ok();
+
+        let probes = job.probes.iter().map(Probe::fresh).collect::<Vec<_>>();
+        let success_when = job.success_when.as_ref().map(Condition::fresh);
+        let context = if options.page_summary || options.triage {
+            options.failure_context.max(pager::LINES)
+        } else {
+            options.failure_context
+        };
+        let fwd = Forward {
+            prefix: prefix.clone(),
+            log_probes: probes
+                .iter()
+                .filter_map(|probe| match probe {
+                    Probe::Log(probe) => Some(probe.clone()),
+                    _ => None,
+                })
+                // the jobs waiting for this one's output
+                .chain(runner.jobs.iter().flat_map(|job| {
+                    job.after_output
+                        .iter()
+                        .filter(|(dep, _)| *dep == idx)
+                        .map(|(_, probe)| probe.clone())
+                }))
+                .chain(success_when.iter().flat_map(Condition::probes))
+                .collect(),
+            last_output: Arc::new(Mutex::new(started)),
+            last_line: Default::default(),
+            lines: Default::default(),
+            encoding: job.encoding,
+            hyperlinks: options.hyperlinks,
+            visible: visible.clone(),
+            logs: Vec::new(),
+            fifo: runner.fifos[idx].clone(),
+            ship: None,
+            checksum: None,
+            tail: (context > 0).then(|| Tail::new(context)),
+            throttle: job
+                .rate_limit
+                .map(|limit| Arc::new(Mutex::new(Throttle::new(limit)))),
+            min_level: runner.min_levels[idx].clone(),
+            filter: job.filter.clone(),
+            exclude: job.exclude.clone(),
+            highlight: options.highlight.clone(),
+            dedup: options.dedup_lines,
+            compact: options.compact.then(|| Compact {
+                idx,
+                last: runner.last_source.clone(),
+            }),
+            ordered: runner
+                .release
+                .clone()
+                .map(|release| Ordered { idx, release }),
+            sink: None,
+            json: None,
+            mux: None,
+            tui: options.tui.then_some(idx),
+            raw: None,
+            grouped: options.grouped.then(Default::default),
+        };
+        let checksum = options.checksum.then(Default::default);
+        let [mut out_logs, mut err_logs] = [Vec::new(), Vec::new()];
+        if let Some(dir) = &options.log_dir {
+            let mode = options.log_mode;
+            let max = options.log_max_size;
+            let log_name = mode.name(name, run);
+            if options.log_streams.merged() {
+                let log = logs::open(dir, &log_name, mode, max);
+                out_logs.push(log.clone());
+                err_logs.push(log);
+            }
+            if options.log_streams.split() {
+                out_logs.push(logs::open(dir, &format!("{log_name}.out"), mode, max));
+                err_logs.push(logs::open(dir, &format!("{log_name}.err"), mode, max));
+            }
+        }
+        let sink = |stream| {
+            options.embed.as_ref().map(|embed| Sink {
+                events: embed.events.clone(),
+                name: name.clone(),
+                stream,
+            })
+        };
+        let json = |stream| output::json().then(|| (name.clone(), stream));
+        let ship = |stream| {
+            options
+                .ship_logs
+                .as_ref()
+                .map(|shipper| crate::ship::Stream {
+                    shipper: shipper.clone(),
+                    name: name.clone(),
+                    pid: proc.id(),
+                    stream,
+                })
+        };
+        let mux = |stderr| {
+            options.mux.as_ref().map(|mux| crate::mux::Stream {
+                mux: mux.clone(),
+                id: idx,
+                stderr,
+            })
+        };
+        let stdout = Forward {
+            checksum: checksum.clone(),
+            logs: out_logs,
+            sink: sink(embed::Stream::Stdout),
+            json: json(embed::Stream::Stdout),
+            ship: ship("stdout"),
+            mux: mux(false),
+            raw: (options.raw || options.primary == Some(idx)).then_some(embed::Stream::Stdout),
+            ..fwd.clone()
+        };
+        let stderr = Forward {
+            logs: err_logs,
+            sink: sink(embed::Stream::Stderr),
+            json: json(embed::Stream::Stderr),
+            ship: ship("stderr"),
+            mux: mux(true),
+            raw: options.raw.then_some(embed::Stream::Stderr),
+            ..fwd.clone()
+        };
+        let filter_out = filter.as_mut().and_then(|filter| filter.stdout.take());
+        let filter_err = filter.as_mut().and_then(|filter| filter.stderr.take());
+        let filter_stderr = Forward {
+            mux: None,
+            ..stderr.clone()
+        };
+        #[cfg(unix)]
+        let terminal = match &pty {
+            Some(pty) => Some(
+                pty.reader()
+                    .map_err(|err| format!("cannot read {name}: {err}"))?,
+            ),
+            None => None,
+        };
+        #[cfg(not(unix))]
+        let terminal = None::<std::fs::File>;
+        let forwarders = [
+            fwd_stream(&stdout, terminal),
+            fwd_stream(&stdout, filter_out.or_else(|| proc.stdout.take())),
+            fwd_stream(&stderr, proc.stderr.take()),
+            fwd_stream(&filter_stderr, filter_err),
+        ];
+        let last_output = fwd.last_output;
+        let last_line = fwd.last_line;
+        let tail = fwd.tail;
+        let lines = fwd.lines;
+        let grouped = fwd.grouped;
+
+        let pid = proc.id();
+        crate::crash::started(pid);
+        let (probe_cancel, cancel) = flume::bounded(0);
+        let exit_prefix = prefix.clone();
+        if !job.probes.is_empty() {
+            probe::watch(idx, probes, events.clone(), cancel);
+        }
+        std::thread::spawn(move || {
+            let (status, peak_rss, cpu) = wait(&mut proc);
+            crate::reaper::forget(pid);
+            crate::crash::exited(pid);
+            for forwarder in forwarders.into_iter().flatten() {
+                let _ = forwarder.join();
+            }
+            let status = match &success_when {
+                Some(condition) => {
+                    let counted = condition.classify(status);
+                    if counted.success() != status.success() {
+                        let verdict = if counted.success() {
+                            "a success"
+                        } else {
+                            "a failure"
+                        };
+                        notice!(
+                            "{exit_prefix} {}, counted as {verdict} by --success-when",
+                            describe_exit(status)
+                        );
+                    }
+                    counted
+                }
+                None => status,
+            };
+            if let Some(block) = grouped {
+                output::out(&std::mem::take(&mut *block.lock().expect("lock")));
+            }
+            if let Some(mut filter) = filter {
+                let _ = filter.wait();
+                crate::reaper::forget(filter.id());
+            }
+            let checksum = checksum.map(|hasher: Arc<Mutex<Sha256>>| {
+                let hasher = std::mem::take(&mut *hasher.lock().expect("lock"));
+                hasher
+                    .finalize()
+                    .iter()
+                    .map(|b| format!("{b:02x}"))
+                    .collect()
+            });
+            let exit = Exit {
+                status,
+                peak_rss,
+                cpu,
+                checksum,
+                tail: tail.map_or_else(Vec::new, |tail| tail.take()),
+                lines: lines.load(std::sync::atomic::Ordering::Relaxed),
+            };
+            let _ = events.send(Event::Exited(idx, exit));
+        });
+
+        Ok(Self {
+            idx,
+            pid,
+            prefix,
+            started,
+            timeout: match job.timeout {
+                Some(timeout) => Timeout::Armed(started + timeout),
+                None => Timeout::Off,
+            },
+            overdue: false,
+            shutdown: Cell::new(Shutdown::Running),
+            last_output,
+            last_line,
+            _probe_cancel: probe_cancel,
+            #[cfg(unix)]
+            pty,
+            #[cfg(windows)]
+            job_object,
+        })
+    }
+
+    /// Whether the process was asked to stop.
+    fn stopping(&self) -> bool {
+        !matches!(self.shutdown.get(), Shutdown::Running)
+    }
+
+    pub fn sigterm(&self) {
+        self.stop("SIGTERM");
+    }
+
+    /// Asks the process and its descendants to stop with `name` (e.g.
+    /// SIGINT). On windows the process tree is terminated instead, unless
+    /// it is asked to stop with SIGINT (a ctrl-break event).
+    pub fn stop(&self, name: &str) {
+        if let Shutdown::Running = self.shutdown.get() {
+            self.shutdown.set(Shutdown::Stopped(Instant::now()));
+        }
+        #[cfg(windows)]
+        if let Some(job) = self.job_object.as_ref().filter(|_| name != "SIGINT") {
+            notice!("{} terminating {}", self.prefix, self.pid);
+            job.terminate();
+            return;
+        }
+        notice!(
+            "{} sending {} to {}",
+            self.prefix,
+            name.to_lowercase(),
+            self.pid
+        );
+        signal_tree(self.pid, name);
+    }
+
+    /// Kills the process and its descendants, for processes that did not
+    /// stop within `--kill-timeout`.
+    fn kill(&self) {
+        self.shutdown.set(Shutdown::Killed(Instant::now()));
+        #[cfg(windows)]
+        if let Some(job) = &self.job_object {
+            job.terminate();
+            return;
+        }
+        signal_tree(self.pid, "SIGKILL");
+    }
+}
+
+/// Waits for `proc` to exit. On unix this uses `wait4` to also get the peak
+/// resident set size (of the process or the largest of its descendants that
+/// it waited for) and the CPU time of the process and those descendants.
+#[cfg(unix)]
+fn wait(proc: &mut process::Child) -> (process::ExitStatus, Option<u64>, Option<Duration>) {
+    use std::os::unix::process::ExitStatusExt;
+
+    let mut status = 0;
+    // SAFETY: rusage is plain data, all zeroes is a valid value
+    let mut usage = unsafe { std::mem::zeroed::<libc::rusage>() };
+    loop {
+        // SAFETY: both pointers are valid for the duration of the call
+        let pid = unsafe { libc::wait4(proc.id() as i32, &mut status, 0, &mut usage) };
+        if pid >= 0 {
+            break;
+        }
+        let err = std::io::Error::last_os_error();
+        if err.kind() != std::io::ErrorKind::Interrupted {
+            panic!("wait for process: {err}");
+        }
+    }
+    // bytes on macOS, KiB elsewhere
+    let max_rss = usage.ru_maxrss as u64;
+    let max_rss = if cfg!(target_os = "macos") {
+        max_rss / 1024
+    } else {
+        max_rss
+    };
+    let time = |time: libc::timeval| {
+        Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+    };
+    let cpu = time(usage.ru_utime) + time(usage.ru_stime);
+    (
+        process::ExitStatus::from_raw(status),
+        Some(max_rss),
+        Some(cpu),
+    )
+}
+
+#[cfg(not(unix))]
+fn wait(proc: &mut process::Child) -> (process::ExitStatus, Option<u64>, Option<Duration>) {
+    (proc.wait().expect("wait for process"), None, None)
+}
+
+/// "exited with code 1" or "terminated by signal: 15 (SIGTERM)".
+pub fn describe_exit(status: process::ExitStatus) -> String {
+    match status.code() {
+        Some(code) => format!("exited with code {code}"),
+        None => format!("terminated by {status}"),
+    }
+}
+
+/// The exit code, or 128 + the signal number for processes that were
+/// killed, like shells report it.
+pub fn exit_code(status: process::ExitStatus) -> i32 {
+    #[cfg(unix)]
+    if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+        return 128 + signal;
+    }
+    status.code().unwrap_or(1)
+}
+
+/// How long to wait before restart `attempt` of `job`, counting from 1.
+/// With `--retries` the delay doubles with every attempt, unless the job
+/// has a fixed `--restart-delay` and no `--retry-backoff`.
+fn restart_delay(job: &Job, attempt: u32) -> Duration {
+    match (job.retries, job.restart_delay) {
+        (Some(_), delay) if job.retry_backoff.is_some() || delay.is_none() => {
+            let backoff = job.retry_backoff.unwrap_or(DEFAULT_RESTART_DELAY);
+            backoff.saturating_mul(1 << (attempt - 1).min(16))
+        }
+        (_, delay) => delay.unwrap_or(DEFAULT_RESTART_DELAY),
+    }
+}
+
+/// Whether the `wanted` lock and units fit next to the locks that are
+/// `held`, each lock has one unit unless `limits` says otherwise.
+fn lock_fits<'a>(
+    held: impl IntoIterator<Item = &'a (String, u32)>,
+    (lock, weight): &(String, u32),
+    limits: &std::collections::BTreeMap<String, u32>,
+) -> bool {
+    let held: u32 = held
+        .into_iter()
+        .filter(|(other, _)| other == lock)
+        .map(|(_, weight)| weight)
+        .sum();
+    let limit = limits.get(lock).copied().unwrap_or(1);
+    held + weight <= limit
+}
+
+/// When a process is started again after it exited (`--restart`).
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, clap::ValueEnum)]
+pub enum Restart {
+    #[default]
+    Never,
+    OnFailure,
+    Always,
+}
+
+impl Restart {
+    fn applies(self, status: process::ExitStatus) -> bool {
+        match self {
+            Restart::Never => false,
+            Restart::OnFailure => !status.success(),
+            Restart::Always => true,
+        }
+    }
+}
+
+/// What happened to a job that starts a command with `--on`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum)]
+pub enum Trigger {
+    Started,
+    Ready,
+    /// Exited successfully.
+    Success,
+    /// Failed on its own, not stopped by runall.
+    Failure,
+    /// Exited either way.
+    Exit,
+}
+
+/// Where a process reads its stdin from (`--stdin-policy`).
+#[derive(Clone, Copy, Default, clap::ValueEnum)]
+pub enum StdinPolicy {
+    /// Share runall's stdin (the terminal).
+    #[default]
+    Inherit,
+    /// Empty input.
+    Null,
+    /// Lines typed into runall are copied to the process.
+    Pipe,
+}
+
+/// Stdin of the processes with `StdinPolicy::Pipe` by job index, `None` once
+/// runall's stdin is closed.
+type StdinPipes = Arc<Mutex<Option<Vec<(usize, process::ChildStdin)>>>>;
+
+/// Progress of stopping a process, see `--kill-timeout`.
+#[derive(Clone, Copy)]
+enum Shutdown {
+    Running,
+    /// Asked to stop at the given time.
+    Stopped(Instant),
+    /// Sent SIGKILL at the given time.
+    Killed(Instant),
+}
+
+/// Progress of a job's `--timeout`.
+enum Timeout {
+    Off,
+    /// Fires at the given time.
+    Armed(Instant),
+    /// The warning signal was sent, terminate at the given time.
+    Warned(Instant),
+    Fired,
+}
+
+const SIGNALS: &[&str] = &[
+    "HUP", "INT", "QUIT", "ABRT", "KILL", "USR1", "USR2", "PIPE", "ALRM", "TERM", "CONT", "STOP",
+    "TSTP", "TTIN", "TTOU", "WINCH",
+];
+
+/// Normalizes signal names like `usr1` or `SIGUSR1` to `SIGUSR1`.
+pub fn signal_name(name: &str) -> Result<String, String> {
+    let upper = name.to_uppercase();
+    let short = upper.strip_prefix("SIG").unwrap_or(&upper);
+    if SIGNALS.contains(&short) {
+        Ok(format!("SIG{short}"))
+    } else {
+        Err(format!("unknown signal {name:?}"))
+    }
+}
+
+/// The size of runall's terminal without the prefix, which is what is left
+/// for the output of a process.
+fn child_size(options: &Options, prefix: &Prefix) -> Option<(u16, u16)> {
+    let prefix_width = if options.compact || prefix.is_empty() {
+        0
+    } else {
+        prefix.plain().chars().count() as u16 + 1
+    };
+    term::size().map(|(columns, lines)| (columns.saturating_sub(prefix_width).max(1), lines))
+}
+
+pub fn signal(pid: u32, signal: &str) {
+    send(signal, &[pid]);
+}
+
+/// Sends `signal` to `pid` and (on Linux) all of its descendants, so that
+/// the processes started by `bash -c` get it as well.
+pub fn signal_tree(pid: u32, signal: &str) {
+    #[cfg(target_os = "linux")]
+    let pids = crate::ports::descendants(pid);
+    #[cfg(not(target_os = "linux"))]
+    let pids = [pid];
+    send(signal, &pids);
+}
+
+#[cfg(unix)]
+fn send(signal: &str, pids: &[u32]) {
+    use nix::{sys::signal::Signal, unistd::Pid};
+
+    let signal = signal
+        .parse::<Signal>()
+        .unwrap_or_else(|_| panic!("unknown signal {signal}"));
+    for &pid in pids {
+        // fails for processes that exited in the meantime
+        let _ = nix::sys::signal::kill(Pid::from_raw(pid as i32), signal);
+    }
+}
+
+/// Windows has no signals: SIGINT becomes a ctrl-break event for the
+/// process group of the child, the terminating signals end the process and
+/// the others are ignored.
+#[cfg(windows)]
+fn send(signal: &str, pids: &[u32]) {
+    use windows_sys::Win32::{
+        Foundation::CloseHandle,
+        System::{
+            Console::{GenerateConsoleCtrlEvent, CTRL_BREAK_EVENT},
+            Threading::{OpenProcess, TerminateProcess, PROCESS_TERMINATE},
+        },
+    };
+
+    for &pid in pids {
+        match signal {
+            // SAFETY: plain FFI call, the children are started as process
+            // group leaders
+            "SIGINT" => unsafe {
+                GenerateConsoleCtrlEvent(CTRL_BREAK_EVENT, pid);
+            },
+            "SIGTERM" | "SIGKILL" | "SIGHUP" | "SIGQUIT" | "SIGABRT" => {
+                // SAFETY: the handle is checked and closed again
+                unsafe {
+                    let handle = OpenProcess(PROCESS_TERMINATE, 0, pid);
+                    if !handle.is_null() {
+                        TerminateProcess(handle, 1);
+                        CloseHandle(handle);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+enum State {
+    Pending,
+    Running(Box<Process>),
+    /// `None` if the job never started.
+    Done(Option<process::ExitStatus>),
+}
+
+impl State {
+    fn succeeded(&self) -> Option<bool> {
+        match self {
+            State::Done(status) => Some(status.is_some_and(|s| s.success())),
+            _ => None,
+        }
+    }
+}
+
+enum Readiness {
+    Waiting,
+    Ready,
+    Failed,
+}
+
+const DEFAULT_TIMEOUT_GRACE: Duration = Duration::from_secs(5);
+const DEFAULT_RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// `--expected-duration`: a run counts as overdue once it takes a tenth
+/// longer than expected.
+fn overdue_after(expected: Duration) -> Duration {
+    expected + expected / 10
+}
+
+pub struct Options {
+    /// Maximum number of jobs running at the same time.
+    pub max_running: Option<usize>,
+    /// Units of each lock that running jobs may hold, 1 if not listed.
+    pub lock_limits: std::collections::BTreeMap<String, u32>,
+    pub prefix_format: String,
+    /// Fixed width of the names in prefixes.
+    pub prefix_width: Option<usize>,
+    /// Report processes that didn't print anything for that long.
+    pub still_running: Option<Duration>,
+    /// Time between asking a process to stop and killing it.
+    pub kill_timeout: Duration,
+    /// Don't end the session when all jobs are done, only on ctrl-c.
+    pub keep_open: bool,
+    /// Don't handle ctrl-c (`--no-ctrlc-handler`).
+    pub no_ctrlc_handler: bool,
+    /// Send SIGINT to all jobs on the first ctrl-c (`--forward-ctrlc`).
+    pub forward_ctrlc: bool,
+    /// Pass OSC-8 hyperlinks through to the terminal.
+    pub hyperlinks: bool,
+    /// Matches of this are shown in reverse video (`--highlight`).
+    pub highlight: Option<regex::Regex>,
+    /// Write per-process logs into this directory.
+    pub log_dir: Option<std::path::PathBuf>,
+    /// Run the jobs in a terminal of their own (`--pty`).
+    pub pty: bool,
+    /// Run the jobs with `stdbuf` (`--line-buffer`).
+    pub line_buffer: bool,
+    /// Create a named pipe per job in this directory (`--fifo-dir`).
+    pub fifo_dir: Option<std::path::PathBuf>,
+    pub log_streams: logs::Streams,
+    pub log_mode: logs::Mode,
+    /// Rotate log files that grow past that many bytes.
+    pub log_max_size: Option<u64>,
+    /// Append a row per finished process to this CSV file.
+    pub stats_export: Option<std::path::PathBuf>,
+    /// Report a SHA-256 of each process's stdout.
+    pub checksum: bool,
+    /// Read commands to add to the session from stdin.
+    pub interactive: bool,
+    /// Stop everything as soon as a task fails.
+    pub fail_fast: bool,
+    /// Stop everything as soon as any command exits.
+    pub kill_others: bool,
+    /// `--after` only waits for the dependencies to exit.
+    pub continue_on_error: bool,
+    /// Collapse runs of identical lines.
+    pub dedup_lines: bool,
+    /// Open the `--open` URLs of jobs when they are ready.
+    pub open_urls: bool,
+    /// Print a header when the output switches processes instead of
+    /// prefixing every line.
+    pub compact: bool,
+    /// Hold back output until the jobs before have finished.
+    pub ordered: bool,
+    /// Lines of output repeated at the end for each failed job.
+    pub failure_context: usize,
+    /// Print a table of all jobs at the end.
+    pub summary: bool,
+    /// Show the summary and the output of the failed jobs in a pager.
+    pub page_summary: bool,
+    /// Keep the output of failed jobs for the menu after the session.
+    pub triage: bool,
+    /// Show the session in a terminal UI.
+    pub tui: bool,
+    /// Print the output of a process once it exited (`--grouped`).
+    pub grouped: bool,
+    /// Poll the usage of the processes (`--stats`), and show it in a status
+    /// bar (`--stats-bar`).
+    pub stats: bool,
+    pub stats_bar: bool,
+    /// Pass the output through unchanged (`--raw`).
+    pub raw: bool,
+    /// The job that gets typed lines not addressed to another job, all
+    /// piped jobs if unset.
+    pub input_target: Option<usize>,
+    /// The job whose stdout goes unchanged to runall's stdout (`--primary`).
+    pub primary: Option<usize>,
+    /// Print a status line when no process printed anything for that long.
+    pub heartbeat: Option<Duration>,
+    /// Listen for `runall ctl` on this socket.
+    pub ctl: Option<std::path::PathBuf>,
+    /// Stop the session that long after it started (`--stop-at`,
+    /// `--stop-after`).
+    pub stop_after: Option<Duration>,
+    /// Stop the session when nobody used it for that long, see
+    /// `--idle-timeout`.
+    pub idle_timeout: Option<Duration>,
+    /// Memory and CPU for all processes together.
+    pub budget: budget::Limits,
+    /// Receives a `name<TAB>exit code` line per finished process.
+    pub exit_codes: Option<std::fs::File>,
+    /// Receives the output as frames, see `--mux-fd`.
+    pub mux: Option<crate::mux::Mux>,
+    /// Publishes lifecycle events, see `--events`.
+    #[cfg(feature = "broker")]
+    pub events: Option<(crate::events::Source, crate::broker::Publisher)>,
+    /// `--ship-logs`: the output also goes to a log collector.
+    pub ship_logs: Option<Arc<crate::ship::Shipper>>,
+    /// Set when the session is run through the library API.
+    pub embed: Option<Embed>,
+}
+
+/// The channels to the program that runs the session, see [`embed::Runner`].
+pub struct Embed {
+    pub events: flume::Sender<embed::Event>,
+    /// Stops the session like ctrl-c.
+    pub stop: flume::Receiver<()>,
+}
+
+/// Completed runs of a job that runs more than once.
+#[derive(Default)]
+struct Runs {
+    passed: u32,
+    failed: u32,
+    first_failure: Option<process::ExitStatus>,
+    last: Option<process::ExitStatus>,
+    /// Restarts by the `--restart` policy.
+    restarts: u32,
+    /// Restarts asked for: `--watch`, `runall ctl restart`, the --tui keys
+    /// and `--propagate-restart`.
+    reloads: u32,
+    /// The last lines of the last run that failed on its own (not stopped
+    /// by runall), see `--failure-context`.
+    failure_tail: Vec<String>,
+    /// Time spent running and lines of output, over all runs.
+    busy: Duration,
+    lines: u64,
+    /// Stopped because of `--watch`, starts again once it exited.
+    reload: bool,
+    /// The last run was ended by its `--timeout`.
+    timed_out: bool,
+    /// CPU time over all runs and the peak memory of any run in KiB, where
+    /// known.
+    cpu: Option<Duration>,
+    peak_rss: Option<u64>,
+    /// How much longer than its `--expected-duration` the last run took.
+    overshoot: Option<Duration>,
+    /// Hex SHA-256 of the stdout of the last run with `--checksum`.
+    checksum: Option<String>,
+}
+
+struct Runner {
+    jobs: Vec<Job>,
+    options: Options,
+    prefixes: Vec<Prefix>,
+    /// Whether the output of a job is printed, see `--show`.
+    visible: Vec<Arc<AtomicBool>>,
+    /// `--min-level` of each job, shared with its forwarders so that
+    /// `runall ctl set` reaches the running processes.
+    min_levels: Vec<Arc<Mutex<Option<crate::level::Level>>>>,
+    states: Vec<State>,
+    /// Whether the job is to run, only set for jobs without autostart once
+    /// they are needed.
+    requested: Vec<bool>,
+    readiness: Vec<Readiness>,
+    /// Next start of recurring (`--schedule`, `--every`) jobs.
+    next_run: Vec<Option<chrono::DateTime<Local>>>,
+    runs: Vec<Runs>,
+    /// How many jobs the session started with, the others were added while
+    /// it ran.
+    initial_jobs: usize,
+    /// Jobs in the order they finished.
+    finished: Vec<usize>,
+    interrupted: bool,
+    /// All tasks finished and the services were told to stop.
+    services_stopped: bool,
+    /// Whether runall reads its stdin, see `read_stdin`.
+    reads_stdin: bool,
+    stdin_pipes: StdinPipes,
+    /// The job that printed the last line, see `--compact`.
+    last_source: Arc<Mutex<Option<usize>>>,
+    /// The output held back by `--ordered`.
+    release: Option<Arc<Mutex<Release>>>,
+    /// The pipes of `--fifo-dir`, which last for the whole session.
+    fifos: Vec<Option<Arc<crate::fifo::Fifo>>>,
+    started: Instant,
+    /// When `--heartbeat` last printed something.
+    last_heartbeat: Instant,
+    /// When somebody last used the session: `runall ctl`, the `--tui` keys
+    /// or the `--interactive` prompt.
+    last_contact: Instant,
+    /// Set with `--session-mem-limit` or `--session-cpu-limit`.
+    budget: Option<Budget>,
+    /// Set with `--stats`.
+    sampler: Option<usage::Sampler>,
+    events_tx: flume::Sender<Event>,
+}
+
+impl Runner {
+    fn running(&self) -> usize {
+        self.states
+            .iter()
+            .filter(|s| matches!(s, State::Running(_)))
+            .count()
+    }
+
+    /// Prints the output `--ordered` held back for jobs whose turn came.
+    fn release_output(&self) {
+        let Some(release) = &self.release else {
+            return;
+        };
+        let mut release = release.lock().expect("lock");
+        for (idx, state) in self.states.iter().enumerate() {
+            if matches!(state, State::Done(_)) {
+                release.finish(idx);
+            }
+        }
+    }
+
+    /// Whether the lock of `job` has enough units left for it to start.
+    fn lock_available(&self, job: &Job) -> bool {
+        let Some(wanted) = &job.lock else {
+            return true;
+        };
+        let held = self
+            .jobs
+            .iter()
+            .zip(&self.states)
+            .filter(|(_, state)| matches!(state, State::Running(_)))
+            .filter_map(|(job, _)| job.lock.as_ref());
+        lock_fits(held, wanted, &self.options.lock_limits)
+    }
+
+    /// Whether the session lasts until ctrl-c: with `--keep-open` and
+    /// `--tui`, and while jobs wait for their `--watch` paths to change.
+    fn keep_open(&self) -> bool {
+        self.options.keep_open
+            || self.options.tui
+            || self.jobs.iter().any(|job| !job.watch.is_empty())
+    }
+
+    /// Recurring jobs only run while the session has something else to do,
+    /// unless there is nothing but recurring jobs.
+    fn session_active(&self) -> bool {
+        if self.interrupted {
+            return false;
+        }
+        let mut unscheduled = self
+            .jobs
+            .iter()
+            .zip(&self.states)
+            .zip(&self.requested)
+            .filter(|((job, _), requested)| !job.recurring() && **requested)
+            .map(|(job, _)| job)
+            .peekable();
+        unscheduled.peek().is_none() || unscheduled.any(|(_, s)| !matches!(s, State::Done(_)))
+    }
+
+    /// Stops the services once there were (non-recurring) tasks and all of
+    /// them are done, unless the session is kept open.
+    fn stop_services(&mut self) {
+        if self.services_stopped || self.keep_open() {
+            return;
+        }
+        let mut tasks = self
+            .jobs
+            .iter()
+            .zip(&self.states)
+            .zip(&self.requested)
+            .filter(|((job, _), requested)| {
+                job.kind == Kind::Task && !job.recurring() && **requested
+            })
+            .map(|(job, _)| job)
+            .peekable();
+        if tasks.peek().is_none() || !tasks.all(|(_, s)| matches!(s, State::Done(_))) {
+            return;
+        }
+        self.services_stopped = true;
+        let services = self
+            .states
+            .iter()
+            .filter_map(|state| match state {
+                State::Running(proc) if self.jobs[proc.idx].kind == Kind::Service => Some(proc),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if !services.is_empty() {
+            notice!("all tasks finished, stopping services");
+        }
+        for proc in services {
+            let job = &self.jobs[proc.idx];
+            proc.stop(job.interrupt_signal.as_deref().unwrap_or("SIGTERM"));
+        }
+    }
+
+    /// Stops the running jobs with the lowest `--priority` when the session
+    /// is interrupted. The next ones are stopped once those exited, so that
+    /// the important commands are the last to go.
+    fn stop_lowest_priority(&self) {
+        let running = self
+            .states
+            .iter()
+            .filter_map(|state| match state {
+                State::Running(proc) if self.jobs[proc.idx].forward_interrupt => Some(proc),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let Some(lowest) = running
+            .iter()
+            .map(|proc| self.jobs[proc.idx].priority)
+            .min()
+        else {
+            return;
+        };
+        let tier = running
+            .iter()
+            .filter(|proc| self.jobs[proc.idx].priority == lowest && !proc.stopping())
+            .collect::<Vec<_>>();
+        let rest = running
+            .iter()
+            .any(|proc| self.jobs[proc.idx].priority > lowest);
+        if rest && !tier.is_empty() {
+            let lowest = format!("{lowest:?}").to_lowercase();
+            notice!("stopping the {lowest} priority commands first");
+        }
+        for proc in tier {
+            let job = &self.jobs[proc.idx];
+            proc.stop(job.interrupt_signal.as_deref().unwrap_or("SIGTERM"));
+        }
+    }
+
+    fn dependency_failed(&self, job: &Job) -> bool {
+        let continues = self.options.continue_on_error;
+        job.after
+            .iter()
+            .any(|&dep| !continues && self.states[dep].succeeded() == Some(false))
+            || job.needs.iter().any(|&dep| match self.readiness[dep] {
+                Readiness::Waiting => self.states[dep].succeeded().is_some(),
+                Readiness::Ready => false,
+                Readiness::Failed => true,
+            })
+            || job
+                .after_output
+                .iter()
+                .any(|(dep, probe)| !probe.matched() && self.states[*dep].succeeded().is_some())
+    }
+
+    fn dependencies_met(&self, job: &Job) -> bool {
+        let continues = self.options.continue_on_error;
+        job.after
+            .iter()
+            .all(|&dep| match self.states[dep].succeeded() {
+                Some(succeeded) => succeeded || continues,
+                None => false,
+            })
+            && job
+                .needs
+                .iter()
+                .all(|&dep| matches!(self.readiness[dep], Readiness::Ready))
+            && job.after_output.iter().all(|(_, probe)| probe.matched())
+    }
+
+    /// Adds a job to the running session, e.g. from the `--interactive`
+    /// prompt. The name is made unique by appending a number. A command
+    /// that `--no-shell` can't run is left out with a notice.
+    fn add_job(&mut self, mut job: Job) {
+        if let Err(err) = shell::check(&job.cmd) {
+            notice!("cannot add {}: {err}", job.name);
+            return;
+        }
+        let base = job.name.clone();
+        for n in 2.. {
+            if self.jobs.iter().all(|j| j.name != job.name) {
+                break;
+            }
+            job.name = format!("{base}-{n}");
+        }
+        self.jobs.push(job);
+        // keep the output aligned for the jobs that are started from now on
+        self.prefixes = Prefix::for_names(
+            &self.options.prefix_format,
+            self.options.prefix_width,
+            self.jobs.iter().map(|j| j.name.as_str()),
+        );
+        self.visible.push(Arc::new(AtomicBool::new(true)));
+        let min_level = self.jobs.last().and_then(|job| job.min_level);
+        self.min_levels.push(Arc::new(Mutex::new(min_level)));
+        self.states.push(State::Pending);
+        self.requested.push(true);
+        self.readiness.push(Readiness::Waiting);
+        self.next_run.push(None);
+        self.runs.push(Runs::default());
+        self.fifos.push(None);
+    }
+
+    /// Requests the jobs without autostart that jobs which are going to run
+    /// depend on.
+    fn request_dependencies(&mut self) {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (idx, job) in self.jobs.iter().enumerate() {
+                if !self.requested[idx] || matches!(self.states[idx], State::Done(_)) {
+                    continue;
+                }
+                let handoffs = job.after_output.iter().map(|(dep, _)| dep);
+                for &dep in job.after.iter().chain(&job.needs).chain(handoffs) {
+                    changed |= !std::mem::replace(&mut self.requested[dep], true);
+                }
+            }
+        }
+    }
+
+    /// The `--on` commands of job `idx` for `trigger`, as jobs named after
+    /// the job and the trigger.
+    fn triggered(&self, idx: usize, trigger: Trigger) -> Vec<Job> {
+        if self.interrupted {
+            return Vec::new();
+        }
+        let job = &self.jobs[idx];
+        job.triggers
+            .iter()
+            .filter(|(on, _)| *on == trigger)
+            .map(|(on, cmd)| {
+                let on = format!("{on:?}").to_lowercase();
+                notice!("{} {on}, running {cmd}", self.prefixes[idx]);
+                Job::new(format!("{}-{on}", job.name), cmd)
+            })
+            .collect()
+    }
+
+    /// Starts every pending job whose dependencies are satisfied, and gives
+    /// up on those whose dependencies failed.
+    fn start_jobs(&mut self) {
+        self.request_dependencies();
+        let now = Local::now();
+        let active = self.session_active();
+        let mut changed = true;
+        let mut launched = Vec::new();
+        let mut ready = Vec::new();
+        while changed {
+            changed = false;
+            // the important jobs first, which matters with --jobs and locks
+            let mut order = (0..self.jobs.len()).collect::<Vec<_>>();
+            order.sort_by_key(|&idx| std::cmp::Reverse(self.jobs[idx].priority));
+            for idx in order {
+                let job = &self.jobs[idx];
+                if !matches!(self.states[idx], State::Pending) {
+                    continue;
+                }
+                let stopped = self.services_stopped && job.kind == Kind::Service;
+                if self.interrupted || stopped || (job.recurring() && !active) {
+                    self.states[idx] = State::Done(None);
+                    continue;
+                }
+                if !self.requested[idx] {
+                    continue;
+                }
+                if self.dependency_failed(job) {
+                    notice!("{} not starting, a dependency failed", self.prefixes[idx]);
+                    self.publish(
+                        "skipped",
+                        idx,
+                        || serde_json::json!({"reason": "a dependency failed"}),
+                    );
+                    self.states[idx] = State::Done(None);
+                    changed = true;
+                    continue;
+                }
+                if self
+                    .options
+                    .max_running
+                    .is_some_and(|max| self.running() >= max)
+                {
+                    break;
+                }
+                if !self.lock_available(job) {
+                    continue;
+                }
+                if let Some(schedule) = &job.schedule {
+                    if self.next_run[idx].is_none() {
+                        let Some(next) = schedule.next_after(now) else {
+                            notice!(
+                                "{} not scheduled again, its schedule has no next run",
+                                self.prefixes[idx]
+                            );
+                            self.states[idx] = State::Done(None);
+                            changed = true;
+                            continue;
+                        };
+                        self.next_run[idx] = Some(next);
+                    }
+                }
+                if self.next_run[idx].is_some_and(|next| next > now) {
+                    continue;
+                }
+                if self.dependencies_met(job) {
+                    // without a next run the job is given up on once it is pending again
+                    self.next_run[idx] = job.schedule.as_ref().and_then(|s| s.next_after(now));
+                    let proc = match Process::spawn(self, idx) {
+                        Ok(proc) => proc,
+                        Err(err) if self.first_start(idx) => {
+                            self.spawn_failed(idx, err);
+                            return;
+                        }
+                        Err(err) => {
+                            notice!("{} {err}", self.prefixes[idx]);
+                            self.publish("skipped", idx, || serde_json::json!({"reason": err}));
+                            self.states[idx] = State::Done(None);
+                            changed = true;
+                            continue;
+                        }
+                    };
+                    self.states[idx] = State::Running(Box::new(proc));
+                    launched.extend(self.triggered(idx, Trigger::Started));
+                    if job.probes.is_empty() {
+                        self.readiness[idx] = Readiness::Ready;
+                        self.open_browser(idx);
+                        launched.extend(self.triggered(idx, Trigger::Ready));
+                        ready.push(idx);
+                        changed = true;
+                    }
+                }
+            }
+            for idx in ready.drain(..) {
+                self.restart_dependents(idx);
+            }
+            for job in launched.drain(..) {
+                self.add_job(job);
+                changed = true;
+            }
+        }
+    }
+
+    /// Whether job `idx` is about to run for the first time and was there
+    /// from the start of the session, so that a broken setup fails fast.
+    /// Restarts, retries, recurring runs and added jobs that cannot be
+    /// started only end themselves.
+    fn first_start(&self, idx: usize) -> bool {
+        let runs = &self.runs[idx];
+        idx < self.initial_jobs && runs.passed + runs.failed == 0
+    }
+
+    /// Gives up on the session when job `idx` could not be started: the jobs
+    /// that already run are stopped and no others are started.
+    fn spawn_failed(&mut self, idx: usize, err: String) {
+        notice!("{} {err}, stopping the other commands", self.prefixes[idx]);
+        self.publish("skipped", idx, || serde_json::json!({"reason": err}));
+        self.states[idx] = State::Done(None);
+        self.interrupted = true;
+        for state in &self.states {
+            if let State::Running(proc) = state {
+                proc.sigterm();
+            }
+        }
+        // the pending jobs are given up on by the next round
+        self.start_jobs();
+    }
+
+    /// `--after-output`: when to look again whether a job that waits for the
+    /// output of another can start.
+    fn check_handoffs(&self) -> Option<Instant> {
+        let waiting = self.jobs.iter().enumerate().any(|(idx, job)| {
+            self.requested[idx]
+                && matches!(self.states[idx], State::Pending)
+                && job.after_output.iter().any(|(dep, probe)| {
+                    !probe.matched() && matches!(self.states[*dep], State::Running(_))
+                })
+        });
+        waiting.then(|| Instant::now() + probe::POLL_INTERVAL)
+    }
+
+    /// Advances the timeouts of running jobs and returns when the next one is
+    /// due.
+    fn check_timeouts(&mut self) -> Option<Instant> {
+        let now = Instant::now();
+        let mut next = None::<Instant>;
+        let mut not_ready = Vec::new();
+        let mut overdue = Vec::new();
+        let mut abandoned = Vec::new();
+        let kill_timeout = self.options.kill_timeout;
+        for (idx, state) in self.states.iter_mut().enumerate() {
+            let State::Running(proc) = state else {
+                continue;
+            };
+            let job = &self.jobs[idx];
+            if let Shutdown::Stopped(at) | Shutdown::Killed(at) = proc.shutdown.get() {
+                let at = at + kill_timeout;
+                if at > now {
+                    next = Some(next.map_or(at, |n| n.min(at)));
+                } else if let Shutdown::Stopped(_) = proc.shutdown.get() {
+                    notice!(
+                        "{} still running {} after it was told to stop, sending sigkill",
+                        proc.prefix,
+                        duration::format(kill_timeout)
+                    );
+                    proc.kill();
+                    next = Some(next.map_or(now + kill_timeout, |n| n.min(now + kill_timeout)));
+                } else {
+                    // e.g. a daemonized child still holds the output pipes
+                    notice!(
+                        "{} did not exit after sigkill, giving up on it",
+                        proc.prefix
+                    );
+                    abandoned.push(idx);
+                    next = Some(now);
+                }
+            }
+            if let (Some(start_timeout), Readiness::Waiting) =
+                (job.start_timeout, &self.readiness[idx])
+            {
+                let at = proc.started + start_timeout;
+                if at <= now {
+                    let reason = format!("not ready after {}", duration::format(start_timeout));
+                    notice!("{} {reason}, terminating", proc.prefix);
+                    self.readiness[idx] = Readiness::Failed;
+                    proc.sigterm();
+                    not_ready.push((idx, reason));
+                } else {
+                    next = Some(next.map_or(at, |n| n.min(at)));
+                }
+            }
+            if let (Some(expected), false) = (job.expected_duration, proc.overdue) {
+                let at = proc.started + overdue_after(expected);
+                if at <= now {
+                    notice!(
+                        "{} still running after {}, it usually takes {}",
+                        proc.prefix,
+                        duration::format(proc.started.elapsed()),
+                        duration::format(expected)
+                    );
+                    proc.overdue = true;
+                    overdue.push((idx, expected));
+                } else {
+                    next = Some(next.map_or(at, |n| n.min(at)));
+                }
+            }
+            match proc.timeout {
+                Timeout::Armed(at) if at <= now => {
+                    let timeout = duration::format(job.timeout.unwrap_or_default());
+                    if let Some(warning) = &job.timeout_warning {
+                        let grace = job.timeout_grace.unwrap_or(DEFAULT_TIMEOUT_GRACE);
+                        notice!(
+                            "{} timed out after {timeout}, sending {warning} and terminating in {}",
+                            proc.prefix,
+                            duration::format(grace)
+                        );
+                        signal(proc.pid, warning);
+                        proc.timeout = Timeout::Warned(now + grace);
+                    } else {
+                        notice!("{} timed out after {timeout}", proc.prefix);
+                        proc.sigterm();
+                        proc.timeout = Timeout::Fired;
+                    }
+                }
+                Timeout::Warned(at) if at <= now => {
+                    proc.sigterm();
+                    proc.timeout = Timeout::Fired;
+                }
+                _ => {}
+            }
+            if let Timeout::Armed(at) | Timeout::Warned(at) = proc.timeout {
+                next = Some(next.map_or(at, |n| n.min(at)));
+            }
+        }
+        for (idx, reason) in not_ready {
+            self.publish("not_ready", idx, || serde_json::json!({"reason": reason}));
+        }
+        for (idx, expected) in overdue {
+            let expected = expected.as_millis() as u64;
+            self.publish(
+                "overdue",
+                idx,
+                || serde_json::json!({"expected_ms": expected}),
+            );
+        }
+        for idx in abandoned {
+            self.states[idx] = State::Done(None);
+            self.finished.push(idx);
+        }
+        next
+    }
+
+    /// Prints a line for processes that have been silent for longer than
+    /// `--still-running` and returns when to check again.
+    fn check_silent(&mut self) -> Option<Instant> {
+        let interval = self.options.still_running?;
+        let now = Instant::now();
+        let mut next = None::<Instant>;
+        for state in &self.states {
+            let State::Running(proc) = state else {
+                continue;
+            };
+            let mut last_output = proc.last_output.lock().expect("lock");
+            if now.duration_since(*last_output) >= interval {
+                notice!(
+                    "still running: {} ({})",
+                    self.jobs[proc.idx].name,
+                    duration::format(proc.started.elapsed())
+                );
+                *last_output = now;
+            }
+            let due = *last_output + interval;
+            next = Some(next.map_or(due, |n| n.min(due)));
+        }
+        next
+    }
+
+    /// Polls the usage of the running jobs for `--stats`, updates the
+    /// `--stats-bar` and returns when to poll again.
+    fn sample(&mut self) -> Option<Instant> {
+        let sampler = self.sampler.as_mut()?;
+        let now = Instant::now();
+        if let Some(next) = sampler.next.filter(|&next| next > now) {
+            return Some(next);
+        }
+        let next = now + usage::INTERVAL;
+        sampler.next = Some(next);
+        for (idx, state) in self.states.iter().enumerate() {
+            match state {
+                State::Running(proc) => match usage::of(proc.pid) {
+                    Some(usage) => sampler.record(idx, usage),
+                    None => sampler.idle(idx),
+                },
+                _ => sampler.idle(idx),
+            }
+        }
+        if self.options.stats_bar {
+            let running = sampler
+                .current
+                .iter()
+                .enumerate()
+                .filter_map(|(idx, current)| current.map(|current| (idx, current)))
+                .collect::<Vec<_>>();
+            let cpu = running.iter().map(|(_, (cpu, _))| cpu).sum::<f64>();
+            let rss = running.iter().map(|(_, (_, rss))| rss).sum::<u64>();
+            let mut bar = format!(
+                "{} running  cpu {cpu:.0}%  mem {}",
+                running.len(),
+                usage::format_size(rss)
+            );
+            for (idx, (cpu, rss)) in running {
+                let name = &self.jobs[idx].name;
+                bar.push_str(&format!(" │ {name} {cpu:.0}% {}", usage::format_size(rss)));
+            }
+            output::bar(Some(color::paint(&bar, "7")));
+        }
+        Some(next)
+    }
+
+    /// Enforces `--session-mem-limit` and `--session-cpu-limit` and returns
+    /// when to check again.
+    fn check_budget(&mut self) -> Option<Instant> {
+        let mut budget = self.budget.take()?;
+        let next = self.enforce_budget(&mut budget);
+        self.budget = Some(budget);
+        Some(next)
+    }
+
+    fn enforce_budget(&mut self, budget: &mut Budget) -> Instant {
+        // stopped processes would not react to being asked to exit
+        let interrupted = self.interrupted;
+        budget.paused.retain(|&(idx, _)| match &self.states[idx] {
+            State::Running(proc) if interrupted || proc.stopping() => {
+                signal_tree(proc.pid, "SIGCONT");
+                false
+            }
+            State::Running(_) => true,
+            _ => false,
+        });
+        let now = Instant::now();
+        if now < budget.next {
+            return budget.next;
+        }
+        budget.next = now + usage::INTERVAL;
+        let mut running = Vec::new();
+        for (idx, state) in self.states.iter().enumerate() {
+            match state {
+                State::Running(proc) => {
+                    let usage = usage::of(proc.pid).unwrap_or_default();
+                    running.push((idx, usage.rss, budget.meter.cpu(idx, usage.cpu)));
+                }
+                _ => budget.forget(idx),
+            }
+        }
+        if interrupted || now < budget.settled {
+            return budget.next;
+        }
+        let rss = running.iter().map(|(_, rss, _)| rss).sum::<u64>();
+        let cpu = running.iter().map(|(_, _, cpu)| cpu).sum::<f64>();
+        if let Some(limit) = budget.limits.mem.filter(|&limit| rss > limit) {
+            let priority = |idx: usize| self.jobs[idx].priority;
+            // the least important command, the biggest among equals
+            let victim = running
+                .iter()
+                .filter(|(idx, ..)| !self.runs[*idx].reload)
+                .min_by(|a, b| priority(a.0).cmp(&priority(b.0)).then(b.1.cmp(&a.1)));
+            if let Some(&(idx, ..)) = victim {
+                budget.settled = now + budget::SETTLE;
+                let reason = format!(
+                    "the session uses {} of its {} of memory",
+                    usage::format_size(rss),
+                    usage::format_size(limit)
+                );
+                self.reload(idx, &reason);
+            }
+            return budget.next;
+        }
+
+        let Some(limit) = budget.limits.cpu else {
+            return budget.next;
+        };
+        let priority = |idx: usize| self.jobs[idx].priority;
+        let paused = |idx: usize| budget.paused.iter().any(|(paused, _)| *paused == idx);
+        if cpu > limit {
+            let victim = running
+                .iter()
+                .filter(|(idx, ..)| !paused(*idx))
+                .min_by(|a, b| priority(a.0).cmp(&priority(b.0)).then(b.2.total_cmp(&a.2)));
+            if let Some(&(idx, _, used)) = victim {
+                if let State::Running(proc) = &self.states[idx] {
+                    notice!(
+                        "{} pausing, the session uses {cpu:.0}% of its {limit:.0}% of CPU",
+                        proc.prefix
+                    );
+                    signal_tree(proc.pid, "SIGSTOP");
+                }
+                budget.paused.push((idx, used));
+            }
+            return budget.next;
+        }
+        // the most important one first; once all are paused one goes on
+        // regardless, so that a command that alone is over the budget runs
+        // at least part of the time
+        let all_paused = running.iter().all(|(idx, ..)| paused(*idx));
+        let next = budget
+            .paused
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, (idx, _))| priority(*idx))
+            .filter(|(_, (_, used))| cpu + used <= limit || all_paused)
+            .map(|(at, _)| at);
+        if let Some(at) = next {
+            let (idx, _) = budget.paused.remove(at);
+            if let State::Running(proc) = &self.states[idx] {
+                notice!("{} continuing, there is CPU to spare again", proc.prefix);
+                signal_tree(proc.pid, "SIGCONT");
+            }
+        }
+        budget.next
+    }
+
+    /// Prints a status line if no process printed anything for
+    /// `--heartbeat` and returns when to check again.
+    fn check_heartbeat(&mut self) -> Option<Instant> {
+        let interval = self.options.heartbeat?;
+        let running = self
+            .states
+            .iter()
+            .filter_map(|state| match state {
+                State::Running(proc) => Some(proc),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let last_output = running
+            .iter()
+            .map(|proc| *proc.last_output.lock().expect("lock"))
+            .chain([self.last_heartbeat])
+            .max()
+            .expect("last heartbeat");
+        let now = Instant::now();
+        if now.duration_since(last_output) < interval {
+            return Some(last_output + interval);
+        }
+        let names = running
+            .iter()
+            .map(|proc| self.jobs[proc.idx].name.as_str())
+            .collect::<Vec<_>>();
+        notice!(
+            "heartbeat: {} running ({}), session running for {}",
+            names.len(),
+            names.join(", "),
+            duration::format(self.started.elapsed())
+        );
+        self.last_heartbeat = now;
+        Some(now + interval)
+    }
+
+    /// Opens the `--open` URL of a job that just became ready, on its first
+    /// run only.
+    fn open_browser(&self, idx: usize) {
+        let (Some(url), State::Running(proc)) = (&self.jobs[idx].open, &self.states[idx]) else {
+            return;
+        };
+        let runs = &self.runs[idx];
+        if self.options.open_urls && runs.passed + runs.failed == 0 {
+            browser::open_when_listening(proc.prefix.to_string(), url.clone(), proc.pid);
+        }
+    }
+
+    fn status_rows(&self) -> Vec<status::Row<'_>> {
+        let now = Local::now();
+        self.jobs
+            .iter()
+            .enumerate()
+            .map(|(idx, job)| {
+                let runs = &self.runs[idx];
+                let (state, proc) = match &self.states[idx] {
+                    State::Pending if self.next_run[idx].is_some_and(|next| next > now) => {
+                        let restarting = runs.restarts > 0 && job.restart != Restart::Never;
+                        (if restarting { "backoff" } else { "waiting" }, None)
+                    }
+                    State::Pending if !self.requested[idx] => ("lazy", None),
+                    State::Pending => ("pending", None),
+                    State::Running(proc) => {
+                        let state = match self.readiness[idx] {
+                            _ if job.probes.is_empty() => "running",
+                            Readiness::Waiting => "starting",
+                            Readiness::Ready => "ready",
+                            Readiness::Failed => "not ready",
+                        };
+                        (state, Some(proc))
+                    }
+                    State::Done(None) => ("skipped", None),
+                    State::Done(Some(_)) => ("exited", None),
+                };
+                status::Row {
+                    name: &job.name,
+                    pid: proc.map(|proc| proc.pid),
+                    state,
+                    uptime: proc.map(|proc| proc.started.elapsed()),
+                    restarts: runs.restarts + runs.reloads,
+                    last_exit: runs.last,
+                    port: job.port,
+                    ports: proc.map_or_else(Vec::new, |proc| ports::listening(proc.pid)),
+                    usage: proc.and_then(|proc| usage::of(proc.pid)),
+                    last_line: proc.map(|proc| proc.last_line.lock().expect("lock").clone()),
+                }
+            })
+            .collect()
+    }
+
+    /// Publishes a lifecycle event of job `idx` if `--events` is set, and
+    /// prints it with `--output json`.
+    fn publish(&self, kind: &str, idx: usize, fields: impl FnOnce() -> serde_json::Value) {
+        #[cfg(feature = "broker")]
+        let broker = self.options.events.as_ref();
+        #[cfg(not(feature = "broker"))]
+        let broker = None::<()>;
+        if broker.is_none() && !output::json() {
+            return;
+        }
+        let name = &self.jobs[idx].name;
+        let fields = fields();
+        if output::json() {
+            output::print(&crate::events::event(kind, name, fields.clone()));
+        }
+        #[cfg(feature = "broker")]
+        if let Some((source, publisher)) = broker {
+            publisher.publish(&source.event(kind, name, fields));
+        }
+    }
+
+    /// Sends an event to the program that embeds the session.
+    fn report(&self, event: impl FnOnce() -> embed::Event) {
+        if let Some(embed) = &self.options.embed {
+            let _ = embed.events.send(event());
+        }
+    }
+
+    /// Starts job `idx` again: stops it first if it is running, otherwise
+    /// starts it once more after it finished.
+    fn reload(&mut self, idx: usize, reason: &str) {
+        if self.interrupted || self.runs[idx].reload {
+            return;
+        }
+        match &self.states[idx] {
+            State::Running(proc) => {
+                notice!("{} {reason}, restarting", proc.prefix);
+                self.runs[idx].reload = true;
+                let job = &self.jobs[idx];
+                proc.stop(job.interrupt_signal.as_deref().unwrap_or("SIGTERM"));
+            }
+            State::Done(_) => {
+                notice!("{} {reason}, starting again", self.prefixes[idx]);
+                self.finished.retain(|&finished| finished != idx);
+                self.readiness[idx] = Readiness::Waiting;
+                self.states[idx] = State::Pending;
+            }
+            State::Pending => {}
+        }
+    }
+
+    /// `--propagate-restart`: once job `idx` is ready after it ran before,
+    /// restarts the running jobs that need it.
+    fn restart_dependents(&mut self, idx: usize) {
+        let runs = &self.runs[idx];
+        if !self.jobs[idx].propagate_restart || runs.passed + runs.failed == 0 {
+            return;
+        }
+        let reason = format!("{} restarted", self.jobs[idx].name);
+        for dependent in 0..self.jobs.len() {
+            let running = matches!(self.states[dependent], State::Running(_));
+            if running && self.jobs[dependent].needs.contains(&idx) {
+                self.reload(dependent, &reason);
+            }
+        }
+    }
+
+    /// Answers a `runall ctl` request.
+    fn control(&mut self, request: &crate::ctl::Request) -> String {
+        use crate::ctl::Action;
+
+        match request.action {
+            Action::Status => return status::table(&self.status_rows()),
+            Action::Ready => return self.ready(),
+            Action::Quit => {
+                notice!("stopping the session, runall ctl asked for it");
+                self.handle(Event::Interrupt);
+                return "stopping the session\n".to_string();
+            }
+            Action::Set => return self.set(&request.name),
+            Action::Adopt => return self.adopt(&request.name),
+            Action::Add => return self.add(&request.name),
+            _ => {}
+        }
+        let name = &request.name;
+        let Some(idx) = self.jobs.iter().position(|job| job.name == *name) else {
+            return format!("error: no command named {name:?}\n");
+        };
+        let running = matches!(self.states[idx], State::Running(_));
+        let (event, answer) = match request.action {
+            Action::Start if running => return format!("error: {name} is already running\n"),
+            Action::Stop if !running => return format!("error: {name} is not running\n"),
+            Action::Start => (Event::Start(idx), "starting"),
+            Action::Stop => (Event::Stop(idx), "stopping"),
+            Action::Restart => (Event::Restart(idx), "restarting"),
+            Action::Show | Action::Hide => {
+                let show = matches!(request.action, Action::Show);
+                self.visible[idx].store(show, std::sync::atomic::Ordering::Relaxed);
+                let answer = if show { "showing" } else { "hiding" };
+                return format!("{answer} the output of {name}\n");
+            }
+            Action::Status
+            | Action::Ready
+            | Action::Quit
+            | Action::Set
+            | Action::Adopt
+            | Action::Add => unreachable!("answered above"),
+        };
+        self.handle(event);
+        format!("{answer} {name}\n")
+    }
+
+    /// `runall ctl ready`: whether the jobs that run are ready, or the tasks
+    /// among them succeeded. Failures are answered as errors.
+    fn ready(&self) -> String {
+        let mut waiting = Vec::new();
+        for (idx, job) in self.jobs.iter().enumerate() {
+            match &self.states[idx] {
+                _ if !self.requested[idx] => {}
+                State::Running(_) => match self.readiness[idx] {
+                    Readiness::Ready => {}
+                    Readiness::Waiting => waiting.push(job.name.as_str()),
+                    Readiness::Failed => return format!("error: {} is not ready\n", job.name),
+                },
+                State::Pending => waiting.push(job.name.as_str()),
+                State::Done(Some(status)) if status.success() => {}
+                State::Done(Some(_)) => return format!("error: {} failed\n", job.name),
+                State::Done(None) => return format!("error: {} was skipped\n", job.name),
+            }
+        }
+        if waiting.is_empty() {
+            "ready\n".to_string()
+        } else {
+            format!("waiting for {}\n", waiting.join(", "))
+        }
+    }
+
+    /// `runall ctl set KEY=VALUE`: changes an option of the session while
+    /// it runs.
+    fn set(&mut self, setting: &str) -> String {
+        let Some((key, value)) = setting.split_once('=') else {
+            return format!("error: expected KEY=VALUE, got {setting:?}\n");
+        };
+        let flag = match key {
+            "kill-others" => &mut self.options.kill_others,
+            "fail-fast" => &mut self.options.fail_fast,
+            "continue-on-error" => &mut self.options.continue_on_error,
+            "min-level" | "log-level" => {
+                let level = match value {
+                    "off" => None,
+                    _ => match value.parse::<crate::level::Level>() {
+                        Ok(level) => Some(level),
+                        Err(err) => return format!("error: {err}\n"),
+                    },
+                };
+                for (job, shared) in self.jobs.iter_mut().zip(&self.min_levels) {
+                    job.min_level = level;
+                    *shared.lock().expect("min level") = level;
+                }
+                notice!("min-level set to {value}, runall ctl asked for it");
+                return format!("set min-level to {value}\n");
+            }
+            _ => {
+                return format!(
+                    "error: cannot set {key:?}, expected kill-others, fail-fast, \
+                     continue-on-error or min-level\n"
+                )
+            }
+        };
+        let Ok(value) = value.parse::<bool>() else {
+            return format!("error: expected true or false for {key}, got {value:?}\n");
+        };
+        *flag = value;
+        notice!("{key} set to {value}, runall ctl asked for it");
+        format!("set {key} to {value}\n")
+    }
+
+    /// `runall ctl add`: runs `NAME=COMMAND` as a new job of the session.
+    fn add(&mut self, request: &str) -> String {
+        let Some((name, cmd)) = request.split_once('=') else {
+            return format!("error: expected NAME=COMMAND, got {request:?}\n");
+        };
+        let (name, cmd) = (name.trim(), cmd.trim());
+        if name.is_empty() || cmd.is_empty() {
+            return format!("error: expected NAME=COMMAND, got {request:?}\n");
+        }
+        if self.jobs.iter().any(|job| job.name == name) {
+            return format!("error: there already is a command named {name:?}\n");
+        }
+        if self.interrupted {
+            return "error: the session is ending\n".to_string();
+        }
+        if let Err(err) = shell::check(cmd) {
+            return format!("error: {err}\n");
+        }
+        notice!("adding {cmd} as {name}, runall ctl asked for it");
+        self.handle(Event::Launch(Box::new(Job::new(name, cmd))));
+        format!("added {name}\n")
+    }
+
+    /// `runall adopt`: watches the process `NAME=PID` as a new service.
+    fn adopt(&mut self, request: &str) -> String {
+        let Some((name, pid)) = request.rsplit_once('=') else {
+            return format!("error: expected NAME=PID, got {request:?}\n");
+        };
+        let Ok(pid) = pid.parse::<u32>() else {
+            return format!("error: invalid pid {pid:?}\n");
+        };
+        if self.jobs.iter().any(|job| job.name == name) {
+            return format!("error: there already is a command named {name:?}\n");
+        }
+        if !crate::adopt::alive(pid) {
+            return format!("error: no process with pid {pid}\n");
+        }
+        let exe = std::env::current_exe().expect("path of runall");
+        let cmd = format!(
+            "{} adopt --watch --pid {pid} --name {}",
+            template::shell_quote(&exe.to_string_lossy()),
+            template::shell_quote(name)
+        );
+        let mut job = Job::new(name, cmd);
+        job.kind = Kind::Service;
+        job.start_banner = false;
+        notice!("adopting process {pid} as {name}, runall ctl asked for it");
+        self.handle(Event::Launch(Box::new(job)));
+        format!("adopted process {pid} as {name}\n")
+    }
+
+    fn handle(&mut self, event: Event) {
+        if matches!(
+            event,
+            Event::Control(_)
+                | Event::Start(_)
+                | Event::Restart(_)
+                | Event::Stop(_)
+                | Event::Launch(_)
+                | Event::Status
+        ) {
+            self.last_contact = Instant::now();
+        }
+        match event {
+            Event::Exited(idx, exit) => {
+                let status = exit.status;
+                let job = &self.jobs[idx];
+                let elapsed = match &self.states[idx] {
+                    State::Running(proc) => proc.started.elapsed(),
+                    _ => Duration::ZERO,
+                };
+                let timed_out = matches!(
+                    &self.states[idx],
+                    State::Running(proc) if matches!(proc.timeout, Timeout::Warned(_) | Timeout::Fired)
+                );
+                let runs = &mut self.runs[idx];
+                runs.timed_out = timed_out;
+                runs.busy += elapsed;
+                runs.lines += exit.lines;
+                // the sampled peak covers all processes of the run together
+                let sampled = self
+                    .sampler
+                    .as_mut()
+                    .map_or(0, |sampler| sampler.finish(idx));
+                let peak_rss = exit.peak_rss.max((sampled > 0).then_some(sampled / 1024));
+                runs.peak_rss = runs.peak_rss.max(peak_rss);
+                if let Some(cpu) = exit.cpu {
+                    runs.cpu = Some(runs.cpu.unwrap_or_default() + cpu);
+                }
+                runs.overshoot = job
+                    .expected_duration
+                    .filter(|&expected| elapsed > overdue_after(expected))
+                    .map(|expected| elapsed - expected);
+                let reload = std::mem::take(&mut runs.reload) && !self.interrupted;
+                if status.success() {
+                    runs.passed += 1;
+                } else {
+                    runs.failed += 1;
+                    // being stopped for a reload is no failure
+                    if !reload {
+                        runs.first_failure.get_or_insert(status);
+                    }
+                    if !self.interrupted && !reload {
+                        runs.failure_tail = exit.tail;
+                    }
+                }
+                runs.last = Some(status);
+                runs.checksum = exit.checksum.clone();
+                let run = runs.passed + runs.failed;
+                if let Some(file) = &mut self.options.exit_codes {
+                    let code = exit_code(status);
+                    if let Err(err) = writeln!(file, "{}\t{code}", job.name) {
+                        notice!("error writing exit codes: {err}");
+                    }
+                }
+                if let Some(path) = &self.options.stats_export {
+                    stats::append(
+                        path,
+                        &stats::Row {
+                            name: &job.name,
+                            start: Local::now()
+                                - chrono::Duration::from_std(elapsed).unwrap_or_default(),
+                            duration: elapsed,
+                            status,
+                            restarts: runs.restarts + runs.reloads,
+                            peak_rss_kb: peak_rss,
+                        },
+                    );
+                }
+                let mut banner = format!(
+                    "{} after {}",
+                    describe_exit(status),
+                    duration::format(elapsed)
+                );
+                if timed_out {
+                    banner = format!("timed out, {banner}");
+                }
+                if let Some(repeat) = job.repeat {
+                    banner = format!("run {run}/{repeat} {banner}");
+                } else if job.every.is_some() {
+                    banner = format!("──── run {run} {banner} ────");
+                }
+                let banner = if status.success() {
+                    color::green(&banner)
+                } else {
+                    color::red(&banner)
+                };
+                let prefix = match &self.states[idx] {
+                    State::Running(proc) => &proc.prefix,
+                    _ => &self.prefixes[idx],
+                };
+                notice!("{prefix} {banner}");
+                if let Some(checksum) = &exit.checksum {
+                    notice!("{prefix} stdout sha256 {checksum}");
+                }
+                if let (false, Some(run_dir)) = (job.artifacts.is_empty(), &self.options.log_dir) {
+                    let cwd = job.cwd.as_deref().unwrap_or(std::path::Path::new(""));
+                    let dest = artifacts::dir(run_dir, &job.name);
+                    match artifacts::collect(&job.artifacts, cwd, &dest) {
+                        Ok(0) => {
+                            notice!("{prefix} no artifacts matched {}", job.artifacts.join(", "))
+                        }
+                        Ok(n) => notice!(
+                            "{prefix} collected the artifacts, {n} files, into {}",
+                            dest.display()
+                        ),
+                        Err(err) => notice!("{prefix} cannot collect the artifacts: {err}"),
+                    }
+                }
+
+                if let Some(every) = job.every {
+                    self.next_run[idx] = Some(Local::now() + every);
+                }
+
+                let stopped =
+                    self.interrupted || (self.services_stopped && job.kind == Kind::Service);
+                let retried = status.success()
+                    || job.retry_on.is_empty()
+                    || job.retry_on.contains(&exit_code(status));
+                let restart = !stopped && !reload && job.restart.applies(status) && retried;
+                let mut restarting = None;
+                if restart {
+                    match job.max_restarts {
+                        Some(max) if runs.restarts >= max => {
+                            let what = if job.retries.is_some() {
+                                "retries"
+                            } else {
+                                "restarts"
+                            };
+                            notice!("{prefix} giving up after {max} {what}");
+                        }
+                        _ => {
+                            runs.restarts += 1;
+                            let attempt = match job.max_restarts {
+                                Some(max) => format!("{}/{max}", runs.restarts),
+                                None => runs.restarts.to_string(),
+                            };
+                            let delay = restart_delay(job, runs.restarts);
+                            let verb = if job.retries.is_some() {
+                                "retrying"
+                            } else {
+                                "restarting"
+                            };
+                            notice!(
+                                "{prefix} {verb} in {} (attempt {attempt})",
+                                duration::format(delay)
+                            );
+                            restarting = Some((delay, runs.restarts));
+                            self.next_run[idx] = Some(
+                                Local::now()
+                                    + chrono::Duration::from_std(delay).unwrap_or_default(),
+                            );
+                        }
+                    }
+                }
+                let restart = (restart && self.next_run[idx].is_some()) || reload;
+                runs.reloads += u32::from(reload);
+
+                let again = restart
+                    || job.recurring()
+                    || job.repeat.is_some_and(|n| runs.passed + runs.failed < n);
+                self.states[idx] = if again && !self.interrupted {
+                    self.readiness[idx] = Readiness::Waiting;
+                    State::Pending
+                } else {
+                    self.finished.push(idx);
+                    // with a restart policy the last run counts
+                    let failure = runs.first_failure.filter(|_| job.restart == Restart::Never);
+                    State::Done(Some(failure.unwrap_or(status)))
+                };
+                let failed = !status.success() && job.kind == Kind::Task && !restart;
+                let ended = matches!(self.states[idx], State::Done(_));
+                let reason = if (self.options.fail_fast || job.oneshot) && failed {
+                    Some("failed")
+                } else if self.options.kill_others && ended {
+                    Some("exited")
+                } else {
+                    None
+                };
+                if let Some(reason) = reason.filter(|_| !self.interrupted) {
+                    notice!(
+                        "{} {reason}, stopping the other commands",
+                        self.prefixes[idx]
+                    );
+                    self.interrupted = true;
+                    for state in &self.states {
+                        if let State::Running(proc) = state {
+                            proc.sigterm();
+                        }
+                    }
+                } else if self.interrupted {
+                    self.stop_lowest_priority();
+                }
+                self.publish("exited", idx, || {
+                    let mut fields = crate::events::exit_fields(status, elapsed);
+                    fields["run"] = run.into();
+                    fields["timed_out"] = timed_out.into();
+                    fields
+                });
+                if let Some((delay, attempt)) = restarting {
+                    let max = self.jobs[idx].max_restarts;
+                    self.publish("restarting", idx, || {
+                        serde_json::json!({
+                            "delay_ms": delay.as_millis() as u64,
+                            "attempt": attempt,
+                            "max_attempts": max,
+                        })
+                    });
+                }
+                if let Some(mux) = &self.options.mux {
+                    mux.frame(idx, crate::mux::EXIT, &exit_code(status).to_be_bytes());
+                }
+                self.report(|| embed::Event::Exited {
+                    name: self.jobs[idx].name.clone(),
+                    status,
+                });
+                if !reload {
+                    let outcome = if status.success() {
+                        Trigger::Success
+                    } else {
+                        Trigger::Failure
+                    };
+                    let mut launched = self.triggered(idx, outcome);
+                    launched.extend(self.triggered(idx, Trigger::Exit));
+                    for job in launched {
+                        self.add_job(job);
+                    }
+                }
+            }
+            Event::Ready(idx) => {
+                if matches!(self.states[idx], State::Running(_)) {
+                    notice!("{} ready", self.prefixes[idx]);
+                    self.publish("ready", idx, || serde_json::json!({}));
+                    self.readiness[idx] = Readiness::Ready;
+                    self.open_browser(idx);
+                    for job in self.triggered(idx, Trigger::Ready) {
+                        self.add_job(job);
+                    }
+                    self.restart_dependents(idx);
+                }
+            }
+            Event::NotReady(idx, reason) => {
+                notice!("{} not ready: {reason}", self.prefixes[idx]);
+                self.publish("not_ready", idx, || serde_json::json!({"reason": reason}));
+                self.readiness[idx] = Readiness::Failed;
+            }
+            #[cfg(unix)]
+            Event::Suspend => {
+                notice!("suspending");
+                for state in &self.states {
+                    if let State::Running(proc) = state {
+                        signal(proc.pid, "SIGTSTP");
+                    }
+                }
+                if let Err(err) = nix::sys::signal::raise(nix::sys::signal::Signal::SIGSTOP) {
+                    notice!("cannot suspend: {err}");
+                }
+            }
+            #[cfg(unix)]
+            Event::Resume => {
+                for state in &self.states {
+                    if let State::Running(proc) = state {
+                        signal(proc.pid, "SIGCONT");
+                    }
+                }
+            }
+            #[cfg(unix)]
+            Event::Relay(name) => {
+                for state in &self.states {
+                    if let State::Running(proc) = state {
+                        if self.jobs[proc.idx].forward_signals {
+                            notice!(
+                                "{} sending {} to {}",
+                                proc.prefix,
+                                name.to_lowercase(),
+                                proc.pid
+                            );
+                            // not its descendants, which may not expect it
+                            signal(proc.pid, name);
+                        }
+                    }
+                }
+            }
+            #[cfg(unix)]
+            Event::Resize => {
+                for state in &self.states {
+                    if let State::Running(proc) = state {
+                        match (&proc.pty, child_size(&self.options, &proc.prefix)) {
+                            (Some(pty), Some(size)) => pty.resize(size),
+                            _ => signal(proc.pid, "SIGWINCH"),
+                        }
+                    }
+                }
+            }
+            Event::Launch(job) => {
+                if self.interrupted {
+                    return;
+                }
+                self.add_job(*job);
+            }
+            Event::Changed(idx) => self.reload(idx, "files changed"),
+            Event::Start(idx) => {
+                if self.interrupted {
+                    return;
+                }
+                self.requested[idx] = true;
+                if let State::Done(_) = self.states[idx] {
+                    self.reload(idx, "start requested");
+                }
+            }
+            Event::Restart(idx) => self.reload(idx, "restart requested"),
+            Event::Stop(idx) => {
+                if let State::Running(proc) = &self.states[idx] {
+                    proc.sigterm();
+                }
+            }
+            Event::Control(request) => {
+                let answer = self.control(&request);
+                let _ = request.reply.send(answer);
+            }
+            Event::Status => {
+                if embed::notices() && !crate::tui::active() {
+                    output::err(&status::table(&self.status_rows()));
+                }
+            }
+            Event::Closed => {
+                if !self.interrupted {
+                    notice!("stdout was closed, stopping the commands");
+                    self.handle(Event::Interrupt);
+                }
+            }
+            Event::CtrlC if self.options.forward_ctrlc && !self.interrupted => {
+                self.interrupted = true;
+                for state in &self.states {
+                    if let State::Running(proc) = state {
+                        proc.stop("SIGINT");
+                    }
+                }
+            }
+            Event::CtrlC => self.handle(Event::Interrupt),
+            Event::Interrupt => {
+                // jobs that don't forward the interrupt are only stopped by a
+                // second ctrl-c
+                let again = std::mem::replace(&mut self.interrupted, true);
+                for state in &self.states {
+                    let State::Running(proc) = state else {
+                        continue;
+                    };
+                    let job = &self.jobs[proc.idx];
+                    if again {
+                        proc.stop(job.interrupt_signal.as_deref().unwrap_or("SIGTERM"));
+                    } else if !job.forward_interrupt {
+                        notice!(
+                            "{} keeps running, press ctrl-c again to stop it",
+                            proc.prefix
+                        );
+                    }
+                }
+                if !again {
+                    self.stop_lowest_priority();
+                }
+            }
+        }
+    }
+}
+
+/// Parses every command with `bash -n` (or the `--shell`) without running
+/// anything and reports syntax errors. Returns whether all commands are fine.
+pub fn check_syntax(jobs: &[Job]) -> bool {
+    let mut ok = true;
+    for job in jobs {
+        let Some(mut command) = shell::syntax_check(&job.cmd) else {
+            panic!("--check-syntax needs a POSIX shell like bash or sh");
+        };
+        let output =
+            crate::reaper::output(command.stderr(process::Stdio::piped())).expect("start shell");
+        if !output.status.success() {
+            ok = false;
+            let errors = String::from_utf8_lossy(&output.stderr);
+            notice!("{}: syntax error in {:?}", job.name, job.cmd);
+            for line in errors.lines() {
+                notice!("  {line}");
+            }
+        }
+    }
+    ok
+}
+
+/// Reads runall's stdin. Lines are copied to the processes with
+/// `--stdin-policy pipe`; with `--interactive`, lines like `:[name] command`
+/// or `:command` add a command to the session instead. Unnamed commands are
+/// named after the program they run.
+fn read_stdin(
+    events: flume::Sender<Event>,
+    pipes: StdinPipes,
+    interactive: bool,
+    names: Vec<String>,
+    target: Option<usize>,
+) {
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lines().map_while(Result::ok) {
+            let command = line.trim().strip_prefix(':').filter(|_| interactive);
+            let Some(cmd) = command.map(str::trim) else {
+                let mut pipes = pipes.lock().expect("lock");
+                let pipes = pipes.as_mut().expect("stdin open");
+                if pipes.is_empty() && interactive && !line.trim().is_empty() {
+                    notice!("commands start with ':', e.g. :[name] command");
+                }
+                // "name: text" sends the text to that job
+                let addressed = line.split_once(':').and_then(|(name, text)| {
+                    let idx = names.iter().position(|n| n == name)?;
+                    let piped = pipes.iter().any(|(i, _)| *i == idx);
+                    piped.then(|| (idx, text.strip_prefix(' ').unwrap_or(text)))
+                });
+                let (to, text) = match addressed {
+                    Some((idx, text)) => (Some(idx), text),
+                    None => (target, line.as_str()),
+                };
+                pipes.retain_mut(|(idx, pipe)| {
+                    to.is_some_and(|to| to != *idx) || writeln!(pipe, "{text}").is_ok()
+                });
+                continue;
+            };
+            if cmd == "status" {
+                let _ = events.send(Event::Status);
+                continue;
+            }
+            let (name, cmd) = match cmd.strip_prefix('[').and_then(|c| c.split_once(']')) {
+                Some((name, cmd)) => (name.to_string(), cmd.trim()),
+                None => (crate::template::fill_name("{cmd}", 0, cmd, None), cmd),
+            };
+            if cmd.is_empty() {
+                notice!("missing command");
+                continue;
+            }
+            let _ = events.send(Event::Launch(Box::new(Job::new(name, cmd))));
+        }
+        // closes the pipes
+        pipes.lock().expect("lock").take();
+    });
+}
+
+/// Turns ctrl-c and (on unix) termination, job control, resize, SIGINFO and
+/// SIGUSR1 signals into events. Ctrl-c is left alone without `ctrlc`. With
+/// `relay` SIGUSR1 and SIGUSR2 are passed on to the processes instead.
+fn handle_signals(events: flume::Sender<Event>, ctrlc: bool, relay: bool) {
+    let interrupt_tx = events.clone();
+    if ctrlc {
+        ctrlc::set_handler(move || {
+            notice!("got ctrl-c");
+
+            if let Err(err) = interrupt_tx.send(Event::CtrlC) {
+                notice!("error sending stop signal: {err}");
+            }
+        })
+        .expect("set ctrl-c handler");
+    }
+
+    // Stops and continues the children together with runall (without the
+    // handler runall would be stopped while its children keep running) and
+    // tells them about terminal resizes.
+    // SIGTERM (docker stop, systemd), SIGHUP (the terminal closed) and
+    // SIGQUIT shut down like ctrl-c instead of leaving the processes behind
+    #[cfg(unix)]
+    {
+        use signal_hook::consts::{
+            SIGCONT, SIGHUP, SIGQUIT, SIGTERM, SIGTSTP, SIGUSR1, SIGUSR2, SIGWINCH,
+        };
+        let mut handled = vec![
+            SIGTSTP, SIGCONT, SIGWINCH, SIGUSR1, SIGTERM, SIGHUP, SIGQUIT,
+        ];
+        if relay {
+            handled.push(SIGUSR2);
+        }
+        // ctrl-t, where the terminal has it
+        #[cfg(any(
+            target_os = "macos",
+            target_os = "freebsd",
+            target_os = "netbsd",
+            target_os = "openbsd",
+            target_os = "dragonfly"
+        ))]
+        handled.push(libc::SIGINFO);
+        let mut signals =
+            signal_hook::iterator::Signals::new(handled).expect("set signal handlers");
+        std::thread::spawn(move || {
+            for signal in signals.forever() {
+                let event = match signal {
+                    SIGTSTP => Event::Suspend,
+                    SIGCONT => Event::Resume,
+                    SIGUSR1 if relay => Event::Relay("SIGUSR1"),
+                    SIGUSR2 => Event::Relay("SIGUSR2"),
+                    SIGUSR1 => Event::Status,
+                    #[cfg(any(
+                        target_os = "macos",
+                        target_os = "freebsd",
+                        target_os = "netbsd",
+                        target_os = "openbsd",
+                        target_os = "dragonfly"
+                    ))]
+                    libc::SIGINFO => Event::Status,
+                    SIGTERM | SIGHUP | SIGQUIT => {
+                        let name = signal_hook::low_level::signal_name(signal).unwrap_or("signal");
+                        notice!("got {}", name.to_lowercase());
+                        Event::Interrupt
+                    }
+                    _ => Event::Resize,
+                };
+                let _ = events.send(event);
+            }
+        });
+    }
+    #[cfg(not(unix))]
+    let _ = relay;
+}
+
+/// How the session ended.
+pub struct Outcome {
+    /// Exit status of every job (`None` for jobs that never started),
+    /// followed by those of jobs that were added while running.
+    pub statuses: Vec<Option<process::ExitStatus>>,
+    /// Jobs in the order they finished.
+    pub finished: Vec<usize>,
+    /// The last lines of the jobs that failed, see `--failure-context`.
+    pub failed_output: String,
+}
+
+/// Runs `jobs` until all of them are done.
+pub fn run(jobs: &[Job], options: Options) -> Outcome {
+    let prefixes = Prefix::for_names(
+        &options.prefix_format,
+        options.prefix_width,
+        jobs.iter().map(|j| j.name.as_str()),
+    );
+
+    let (events_tx, events_rx) = flume::unbounded();
+
+    match &options.embed {
+        // the signal handlers belong to the embedding program
+        Some(embed) => {
+            let stop = embed.stop.clone();
+            let events = events_tx.clone();
+            std::thread::spawn(move || {
+                if stop.recv().is_ok() {
+                    let _ = events.send(Event::Interrupt);
+                }
+            });
+        }
+        None => {
+            output::on_closed(events_tx.clone());
+            handle_signals(
+                events_tx.clone(),
+                !options.no_ctrlc_handler,
+                jobs.iter().any(|job| job.forward_signals),
+            );
+        }
+    }
+
+    if options.interactive {
+        notice!("type :[name] command to add a command to the session, :status to list them");
+    }
+    let reads_stdin = options.interactive
+        || options.tui
+        || jobs
+            .iter()
+            .any(|j| matches!(j.stdin_policy, StdinPolicy::Pipe));
+
+    let _ctl = options
+        .ctl
+        .as_deref()
+        .and_then(|path| crate::ctl::serve(path, events_tx.clone()));
+
+    let release = options.ordered.then(Default::default);
+    let fifos = jobs
+        .iter()
+        .map(|job| {
+            let dir = options.fifo_dir.as_ref()?;
+            Some(Arc::new(crate::fifo::Fifo::create(dir, &job.name)))
+        })
+        .collect();
+    let limits = options.budget;
+    let sampler = options.stats.then(usage::Sampler::default);
+    let budget = (limits.mem.is_some() || limits.cpu.is_some()).then(|| Budget::new(limits));
+    let mut runner = Runner {
+        jobs: jobs.to_vec(),
+        options,
+        prefixes,
+        visible: jobs
+            .iter()
+            .map(|job| Arc::new(AtomicBool::new(!job.hidden)))
+            .collect(),
+        min_levels: jobs
+            .iter()
+            .map(|job| Arc::new(Mutex::new(job.min_level)))
+            .collect(),
+        states: jobs.iter().map(|_| State::Pending).collect(),
+        requested: jobs.iter().map(|job| job.autostart).collect(),
+        readiness: jobs.iter().map(|_| Readiness::Waiting).collect(),
+        next_run: jobs.iter().map(|_| None).collect(),
+        runs: jobs.iter().map(|_| Runs::default()).collect(),
+        initial_jobs: jobs.len(),
+        finished: Vec::new(),
+        interrupted: false,
+        services_stopped: false,
+        reads_stdin,
+        stdin_pipes: StdinPipes::new(Mutex::new(Some(Vec::new()))),
+        last_source: Default::default(),
+        release,
+        fifos,
+        started: Instant::now(),
+        last_heartbeat: Instant::now(),
+        last_contact: Instant::now(),
+        budget,
+        sampler,
+        events_tx,
+    };
+
+    for (idx, job) in runner.jobs.iter().enumerate() {
+        if !job.watch.is_empty() {
+            let paths = job
+                .watch
+                .iter()
+                .map(|path| match &job.cwd {
+                    Some(cwd) => cwd.join(path),
+                    None => path.clone(),
+                })
+                .collect();
+            crate::watch::watch(idx, paths, runner.events_tx.clone());
+        }
+    }
+
+    if reads_stdin && !runner.options.tui {
+        // start reading once the first pipes are there
+        runner.start_jobs();
+        let interactive = runner.options.interactive;
+        read_stdin(
+            runner.events_tx.clone(),
+            runner.stdin_pipes.clone(),
+            interactive,
+            runner.jobs.iter().map(|job| job.name.clone()).collect(),
+            runner.options.input_target,
+        );
+    }
+
+    if runner.options.tui {
+        let names = runner.jobs.iter().map(|job| job.name.clone()).collect();
+        crate::tui::start(names, runner.events_tx.clone());
+    }
+
+    let mut kept_open = false;
+    loop {
+        runner.start_jobs();
+        runner.stop_services();
+        runner.release_output();
+        if runner.options.tui {
+            crate::tui::update(&status::table(&runner.status_rows()));
+        }
+
+        if runner.running() == 0 {
+            let waiting = runner.session_active() && runner.next_run.iter().any(|n| n.is_some());
+            let keep_open = runner.keep_open() && !runner.interrupted;
+            if keep_open && !waiting && !kept_open {
+                notice!("all commands finished, keeping the session open (ctrl-c to quit)");
+                kept_open = true;
+            }
+            if !waiting && !keep_open {
+                for (idx, state) in runner.states.iter_mut().enumerate() {
+                    if matches!(state, State::Pending) && !runner.requested[idx] {
+                        *state = State::Done(None);
+                    } else if matches!(state, State::Pending) {
+                        notice!(
+                            "{} not starting, dependencies can't be met",
+                            runner.prefixes[idx]
+                        );
+                        *state = State::Done(None);
+                    }
+                }
+                break;
+            }
+        }
+
+        let next_run =
+            runner.next_run.iter().flatten().min().map(|next| {
+                Instant::now() + (*next - Local::now()).to_std().unwrap_or(Duration::ZERO)
+            });
+        let next_timeout = runner.check_timeouts();
+        let next_silent = runner.check_silent();
+        let next_heartbeat = runner.check_heartbeat();
+        let next_budget = runner.check_budget();
+        let next_sample = runner.sample();
+        let next_handoff = runner.check_handoffs();
+        let deadline = runner
+            .options
+            .stop_after
+            .filter(|_| !runner.interrupted)
+            .map(|after| runner.started + after);
+        if deadline.is_some_and(|deadline| deadline <= Instant::now()) {
+            notice!("stopping the session, its time is up");
+            runner.handle(Event::Interrupt);
+            continue;
+        }
+        let idle_deadline = runner
+            .options
+            .idle_timeout
+            .filter(|_| !runner.interrupted)
+            .map(|timeout| runner.last_contact + timeout);
+        if idle_deadline.is_some_and(|deadline| deadline <= Instant::now()) {
+            notice!(
+                "stopping the session, nobody used it for {}",
+                crate::duration::format(runner.last_contact.elapsed())
+            );
+            runner.handle(Event::Interrupt);
+            continue;
+        }
+        // the uptimes in the table
+        let next_tui = runner
+            .options
+            .tui
+            .then(|| Instant::now() + Duration::from_secs(1));
+        let wakeup = next_run
+            .into_iter()
+            .chain(next_timeout)
+            .chain(next_silent)
+            .chain(next_heartbeat)
+            .chain(next_budget)
+            .chain(next_sample)
+            .chain(next_handoff)
+            .chain(deadline)
+            .chain(idle_deadline)
+            .chain(next_tui)
+            .min();
+        let event = match wakeup {
+            Some(wakeup) => match events_rx.recv_deadline(wakeup) {
+                Ok(event) => event,
+                Err(flume::RecvTimeoutError::Timeout) => continue,
+                Err(err) => panic!("receive event: {err}"),
+            },
+            None => events_rx.recv().expect("receive event"),
+        };
+        runner.handle(event);
+    }
+
+    runner.release_output();
+    crate::tui::stop();
+    output::bar(None);
+
+    // for --page-summary and --triage
+    let mut failed_output = String::new();
+    for (idx, job) in runner.jobs.iter().enumerate() {
+        let runs = &runner.runs[idx];
+        let failed = matches!(runner.states[idx], State::Done(Some(status)) if !status.success());
+        let prefix = &runner.prefixes[idx];
+        if failed && !runs.failure_tail.is_empty() {
+            let lines = runs.failure_tail.len();
+            failed_output.push_str(&format!(
+                "\n{prefix} failed, its last {lines} lines were:\n"
+            ));
+            for line in &runs.failure_tail {
+                failed_output.push_str(&format!("{prefix}   {line}\n"));
+            }
+        }
+        // with one command its output is right above
+        if failed
+            && !runs.failure_tail.is_empty()
+            && !runner.options.page_summary
+            && runner.jobs.len() > 1
+        {
+            notice!(
+                "{prefix} failed, its last {} lines were:",
+                runs.failure_tail.len()
+            );
+            for line in &runs.failure_tail {
+                notice!("{prefix}   {line}");
+            }
+        }
+        if job.repeat.is_some() {
+            notice!(
+                "{} {} of {} runs passed, {} failed",
+                runner.prefixes[idx],
+                runs.passed,
+                runs.passed + runs.failed,
+                runs.failed
+            );
+        }
+    }
+
+    if runner.options.summary && embed::notices() {
+        let rows = runner
+            .jobs
+            .iter()
+            .zip(&runner.states)
+            .zip(&runner.runs)
+            .map(|((job, state), runs)| status::Summary {
+                name: &job.name,
+                status: match state {
+                    State::Done(status) => *status,
+                    _ => None,
+                },
+                duration: runs.busy,
+                restarts: runs.restarts + runs.reloads,
+                lines: runs.lines,
+                timed_out: runs.timed_out,
+                cpu: runs.cpu,
+                peak_rss: runs.peak_rss,
+                overshoot: runs.overshoot,
+                checksum: runs.checksum.as_deref(),
+                tail: &runs.failure_tail,
+            })
+            .collect::<Vec<_>>();
+        if output::json() {
+            let commands = rows
+                .iter()
+                .map(|row| {
+                    let failed = row.status.is_some_and(|s| !s.success());
+                    serde_json::json!({
+                        "name": row.name,
+                        "code": row.status.and_then(|s| s.code()),
+                        "success": row.status.is_some_and(|s| s.success()),
+                        "timed_out": row.timed_out,
+                        "cpu_ms": row.cpu.map(|cpu| cpu.as_millis() as u64),
+                        "peak_rss_kb": row.peak_rss,
+                        "overshoot_ms": row.overshoot.map(|over| over.as_millis() as u64),
+                        "duration_ms": row.duration.as_millis() as u64,
+                        "restarts": row.restarts,
+                        "lines": row.lines,
+                        "checksum": row.checksum,
+                        "tail": failed.then_some(row.tail),
+                    })
+                })
+                .collect::<Vec<_>>();
+            output::print(&serde_json::json!({
+                "ts": Local::now().to_rfc3339(),
+                "event": "summary",
+                "commands": commands,
+            }));
+        } else if runner.options.page_summary {
+            let paged = format!("{}{failed_output}", status::summary(&rows));
+            let paged = redact::apply(paged.into());
+            if !pager::show(&paged) {
+                output::err(&paged);
+            }
+        } else {
+            output::err(&status::summary(&rows));
+        }
+    }
+
+    Outcome {
+        statuses: runner
+            .states
+            .into_iter()
+            .map(|state| match state {
+                State::Done(status) => status,
+                _ => None,
+            })
+            .collect(),
+        finished: runner.finished,
+        failed_output: redact::apply(failed_output.into()).into_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn lock(name: &str, weight: u32) -> (String, u32) {
+        (name.to_string(), weight)
+    }
+
+    #[test]
+    fn retries_back_off() {
+        let mut job = Job::new("flaky", "false");
+        job.retries = Some(5);
+        job.retry_backoff = Some(Duration::from_millis(100));
+        let delays = (1..=4).map(|attempt| restart_delay(&job, attempt).as_millis());
+        assert_eq!(delays.collect::<Vec<_>>(), [100, 200, 400, 800]);
+        // capped instead of overflowing
+        assert_eq!(restart_delay(&job, 40), restart_delay(&job, 17));
+        job.retry_backoff = Some(Duration::MAX);
+        assert_eq!(restart_delay(&job, 3), Duration::MAX);
+    }
+
+    #[test]
+    fn retries_default_to_doubling_the_restart_delay() {
+        let mut job = Job::new("flaky", "false");
+        job.retries = Some(3);
+        assert_eq!(restart_delay(&job, 1), DEFAULT_RESTART_DELAY);
+        assert_eq!(restart_delay(&job, 3), DEFAULT_RESTART_DELAY * 4);
+        // a fixed delay stays fixed
+        job.restart_delay = Some(Duration::from_secs(3));
+        assert_eq!(restart_delay(&job, 3), Duration::from_secs(3));
+        job.retry_backoff = Some(Duration::from_secs(1));
+        assert_eq!(restart_delay(&job, 3), Duration::from_secs(4));
+    }
+
+    #[test]
+    fn restarts_wait_the_restart_delay() {
+        let mut job = Job::new("server", "serve");
+        assert_eq!(restart_delay(&job, 5), DEFAULT_RESTART_DELAY);
+        job.restart_delay = Some(Duration::from_millis(250));
+        assert_eq!(restart_delay(&job, 5), Duration::from_millis(250));
+    }
+
+    #[test]
+    fn locks_have_one_unit_by_default() {
+        let limits = BTreeMap::new();
+        assert!(lock_fits([], &lock("db", 1), &limits));
+        assert!(!lock_fits(&[lock("db", 1)], &lock("db", 1), &limits));
+        assert!(lock_fits(&[lock("cache", 1)], &lock("db", 1), &limits));
+    }
+
+    #[test]
+    fn locks_count_weights_up_to_their_limit() {
+        let limits = BTreeMap::from([("gpu".to_string(), 4)]);
+        let held = [lock("gpu", 2), lock("gpu", 1), lock("db", 1)];
+        assert!(lock_fits(&held, &lock("gpu", 1), &limits));
+        assert!(!lock_fits(&held, &lock("gpu", 2), &limits));
+        assert!(lock_fits(&held[..1], &lock("gpu", 2), &limits));
+    }
+}
@@ -0,0 +1,315 @@
+//! The shell that runs the commands: bash on unix, cmd on windows, or the
+//! one given with `--shell`. With `--no-shell` the commands are split into
+//! words and run directly.
+
+use std::{io, process, sync::OnceLock};
+
+enum Strategy {
+    /// The program of the shell and its arguments.
+    Shell(Vec<String>),
+    /// `--no-shell`
+    Direct,
+}
+
+static STRATEGY: OnceLock<Strategy> = OnceLock::new();
+
+#[cfg(unix)]
+const DEFAULT: &str = "bash";
+#[cfg(windows)]
+const DEFAULT: &str = "cmd";
+
+/// `--shell`: a program, optionally with arguments, e.g. `sh` or
+/// `bash -o pipefail`.
+pub fn set(shell: &str) {
+    let words = shell
+        .split_whitespace()
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    if words.is_empty() {
+        panic!("--shell: missing program");
+    }
+    if STRATEGY.set(Strategy::Shell(words)).is_err() {
+        panic!("shell set twice");
+    }
+}
+
+/// `--no-shell`
+pub fn disable() {
+    if STRATEGY.set(Strategy::Direct).is_err() {
+        panic!("shell set twice");
+    }
+}
+
+/// The shell with its arguments, `None` with `--no-shell`.
+fn words() -> Option<&'static [String]> {
+    match STRATEGY.get_or_init(|| Strategy::Shell(vec![DEFAULT.to_string()])) {
+        Strategy::Shell(words) => Some(words),
+        Strategy::Direct => None,
+    }
+}
+
+fn program() -> &'static str {
+    let Some(words) = words() else {
+        return "--no-shell";
+    };
+    let program = &words[0];
+    let name = program.rsplit(['/', '\\']).next().unwrap_or(program);
+    name.strip_suffix(".exe").unwrap_or(name)
+}
+
+/// The flag that makes the shell run a command line.
+fn command_flag() -> &'static str {
+    match program().to_lowercase().as_str() {
+        "cmd" => "/C",
+        "pwsh" | "powershell" => "-Command",
+        _ => "-c",
+    }
+}
+
+/// Whether the shell understands `-n -c CMD` (bash, sh, zsh...).
+fn posix() -> bool {
+    words().is_some() && command_flag() == "-c"
+}
+
+/// A command running `cmd` in the shell. Fails with `--no-shell` for what
+/// only a shell can run, see [`split`].
+pub fn command(cmd: &str) -> io::Result<process::Command> {
+    wrapped(&[], cmd)
+}
+
+/// `--line-buffer`: the shell runs under `stdbuf`, which makes it and the
+/// programs it starts flush their output after every line.
+pub fn line_buffered(cmd: &str) -> io::Result<process::Command> {
+    wrapped(&["stdbuf", "-oL", "-eL"], cmd)
+}
+
+/// Whether `cmd` can be run, which with `--no-shell` needs [`split`] to
+/// succeed.
+pub fn check(cmd: &str) -> Result<(), String> {
+    match words() {
+        Some(_) => Ok(()),
+        None => split(cmd).map(|_| ()),
+    }
+}
+
+/// Whether `program` is in the `PATH`.
+pub fn installed(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .is_some_and(|path| std::env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+}
+
+/// The shell running `cmd`, started by the program in `wrapper` if there
+/// is one.
+fn wrapped(wrapper: &[&str], cmd: &str) -> io::Result<process::Command> {
+    let Some(words) = words() else {
+        let Words { env, words } =
+            split(cmd).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let mut command = match wrapper.split_first() {
+            Some((program, args)) => {
+                let mut command = process::Command::new(program);
+                command.args(args).args(&words);
+                command
+            }
+            None => {
+                let mut command = process::Command::new(&words[0]);
+                command.args(&words[1..]);
+                command
+            }
+        };
+        command.envs(env);
+        return Ok(command);
+    };
+    let mut command = match wrapper.split_first() {
+        Some((program, args)) => {
+            let mut command = process::Command::new(program);
+            command.args(args).arg(&words[0]);
+            command
+        }
+        None => process::Command::new(&words[0]),
+    };
+    command.args(&words[1..]).arg(command_flag());
+    // cmd does its own parsing of the command line, which the quoting of
+    // regular arguments would break
+    #[cfg(windows)]
+    if command_flag() == "/C" {
+        std::os::windows::process::CommandExt::raw_arg(&mut command, cmd);
+        return Ok(command);
+    }
+    command.arg(cmd);
+    Ok(command)
+}
+
+/// One command line that runs `cmds` at the same time and exits when all of
+/// them did, with the exit code of the last one that failed (`--group`).
+pub fn group(cmds: &[String]) -> String {
+    if !posix() {
+        panic!("--group needs a POSIX shell, not {}", program());
+    }
+    let mut script = String::new();
+    let mut pids = Vec::new();
+    for (i, cmd) in cmds.iter().enumerate() {
+        script.push_str(&format!("( {cmd}\n) & p{i}=$!; "));
+        pids.push(format!("$p{i}"));
+    }
+    let pids = pids.join(" ");
+    // the members are stopped together with the group
+    script.push_str(&format!(
+        "trap 'kill {pids} 2>/dev/null' INT TERM HUP; s=0; \
+         for p in {pids}; do wait $p || s=$?; done; exit $s"
+    ));
+    script
+}
+
+/// A command that parses `cmd` without running it, `None` if the shell
+/// can't do that.
+pub fn syntax_check(cmd: &str) -> Option<process::Command> {
+    let words = words().filter(|_| posix())?;
+    let mut command = process::Command::new(&words[0]);
+    command.args(&words[1..]).args(["-n", "-c", cmd]);
+    Some(command)
+}
+
+/// `--no-shell`: splits `cmd` into words like a POSIX shell does, without
+/// expanding anything. `NAME=VALUE` words before the program are returned
+/// as environment variables. Fails for what only a shell can run, like
+/// pipes or variables.
+pub fn split(cmd: &str) -> Result<Words, String> {
+    let needs_shell = |c: char| {
+        Err(format!(
+            "{cmd:?} needs a shell for {c:?}, leave out --no-shell"
+        ))
+    };
+    let mut words = Vec::new();
+    let mut word = None::<String>;
+    let mut quote = None;
+    let mut chars = cmd.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, '#') if word.is_none() => break,
+            (None, '\\') => match chars.next() {
+                Some('\n') | None => {}
+                Some(c) => word.get_or_insert_default().push(c),
+            },
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                word.get_or_insert_default();
+            }
+            (None | Some('"'), '$' | '`') => return needs_shell(c),
+            (None, '|' | '&' | ';' | '<' | '>' | '(' | ')') => return needs_shell(c),
+            (Some(q), c) if q == c => quote = None,
+            (Some('"'), '\\') => {
+                let word = word.get_or_insert_default();
+                match chars.next() {
+                    Some(c @ ('$' | '`' | '"' | '\\')) => word.push(c),
+                    Some('\n') => {}
+                    Some(c) => {
+                        word.push('\\');
+                        word.push(c);
+                    }
+                    None => word.push('\\'),
+                }
+            }
+            (_, c) => word.get_or_insert_default().push(c),
+        }
+    }
+    if quote.is_some() {
+        return Err(format!("{cmd:?} has an unterminated quote"));
+    }
+    words.extend(word);
+    let assignments = words
+        .iter()
+        .take_while(|word| {
+            word.split_once('=').is_some_and(|(name, _)| {
+                !name.is_empty()
+                    && !name.starts_with(|c: char| c.is_ascii_digit())
+                    && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            })
+        })
+        .count();
+    let env = words
+        .drain(..assignments)
+        .map(|word| {
+            let (name, value) = word.split_once('=').expect("assignment");
+            (name.to_string(), value.to_string())
+        })
+        .collect();
+    if words.is_empty() {
+        return Err(format!("{cmd:?} has no program to run"));
+    }
+    Ok(Words { env, words })
+}
+
+/// A command line split by [`split`].
+pub struct Words {
+    pub env: Vec<(String, String)>,
+    /// The program and its arguments.
+    pub words: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(cmd: &str) -> Vec<String> {
+        split(cmd).expect("splits").words
+    }
+
+    #[test]
+    fn splits_words() {
+        assert_eq!(
+            words("cargo  test\t--release"),
+            ["cargo", "test", "--release"]
+        );
+        assert_eq!(
+            words("echo 'a b' \"c d\" e' 'f"),
+            ["echo", "a b", "c d", "e f"]
+        );
+        assert_eq!(words("echo '' \"\""), ["echo", "", ""]);
+        assert_eq!(words("echo a # comment"), ["echo", "a"]);
+        assert_eq!(words("echo a#b"), ["echo", "a#b"]);
+        assert_eq!(words("echo ' $HOME | x '"), ["echo", " $HOME | x "]);
+    }
+
+    #[test]
+    fn handles_backslashes() {
+        assert_eq!(words(r"echo a\ b \$x \'"), ["echo", "a b", "$x", "'"]);
+        assert_eq!(words("echo a \\\n b"), ["echo", "a", "b"]);
+        assert_eq!(words(r"echo 'a\b'"), ["echo", r"a\b"]);
+        assert_eq!(words(r#"echo "\$ \" \\ \n""#), ["echo", r#"$ " \ \n"#]);
+    }
+
+    #[test]
+    fn splits_assignments() {
+        let split = split("RUST_LOG=debug A_1='x y' cargo run B=1").expect("splits");
+        assert_eq!(
+            split.env,
+            [
+                ("RUST_LOG".to_string(), "debug".to_string()),
+                ("A_1".to_string(), "x y".to_string())
+            ]
+        );
+        assert_eq!(split.words, ["cargo", "run", "B=1"]);
+        assert_eq!(words("1A=x cmd"), ["1A=x", "cmd"]);
+        assert_eq!(words("=x cmd"), ["=x", "cmd"]);
+    }
+
+    #[test]
+    fn rejects_what_needs_a_shell() {
+        let error = |cmd| split(cmd).err().expect("fails");
+        assert_eq!(
+            error("echo $HOME"),
+            r#""echo $HOME" needs a shell for '$', leave out --no-shell"#
+        );
+        assert!(error("echo \"$HOME\"").contains("'$'"));
+        assert!(error("echo `date`").contains("'`'"));
+        assert!(error("a | b").contains("'|'"));
+        assert!(error("a && b").contains("'&'"));
+        assert!(error("a; b").contains("';'"));
+        assert!(error("a > f").contains("'>'"));
+        assert!(error("(a)").contains("'('"));
+        assert_eq!(error("echo 'a"), r#""echo 'a" has an unterminated quote"#);
+        assert_eq!(error("A=1"), r#""A=1" has no program to run"#);
+        assert_eq!(error(""), r#""" has no program to run"#);
+    }
+}
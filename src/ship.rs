@@ -0,0 +1,192 @@
+//! `--ship-logs`: the output of the commands also goes to a log collector
+//! over HTTP, as gzipped batches of JSON lines with the session, host,
+//! command and pid of every line. Like `--events` this speaks the protocol
+//! directly over TCP, so only plain `http://` is supported. Lines that
+//! can't be delivered are dropped rather than holding up the commands.
+
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde_json::Value;
+
+use crate::events::Source;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// A batch is sent once it has that many lines, or after [`LINGER`].
+const BATCH: usize = 1000;
+const LINGER: Duration = Duration::from_secs(1);
+/// Lines waiting to be sent; more are dropped.
+const QUEUE: usize = 100_000;
+
+/// Where to send, parsed from `http://host[:port][/path]`.
+#[derive(Clone, Debug)]
+pub struct Collector {
+    host: String,
+    addr: String,
+    path: String,
+}
+
+pub fn parse(url: &str) -> Result<Collector, String> {
+    let rest = match url.split_once("://") {
+        Some(("http", rest)) => rest,
+        Some((scheme, _)) => {
+            return Err(format!(
+                "unsupported scheme {scheme:?}, only http:// is supported"
+            ))
+        }
+        None => return Err(format!("expected http://..., got {url:?}")),
+    };
+    let (host, path) = match rest.find('/') {
+        Some(slash) => (&rest[..slash], &rest[slash..]),
+        None => (rest, "/"),
+    };
+    if host.is_empty() {
+        return Err(format!("missing host in {url:?}"));
+    }
+    let has_port = host
+        .rsplit_once(':')
+        .is_some_and(|(_, p)| !p.ends_with(']'));
+    Ok(Collector {
+        host: host.to_string(),
+        addr: if has_port {
+            host.to_string()
+        } else {
+            format!("{host}:80")
+        },
+        path: path.to_string(),
+    })
+}
+
+impl Collector {
+    /// POSTs `body`, newline separated JSON, and fails unless the collector
+    /// answers with a 2xx status.
+    fn post(&self, body: &[u8]) -> io::Result<()> {
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(body)?;
+        let body = gzip.finish()?;
+        let addr = self
+            .addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address"))?;
+        let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: runall/{}\r\n\
+             Content-Type: application/x-ndjson\r\nContent-Encoding: gzip\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n",
+            self.path,
+            self.host,
+            env!("CARGO_PKG_VERSION"),
+            body.len()
+        )?;
+        stream.write_all(&body)?;
+        let mut status = String::new();
+        BufReader::new(stream).read_line(&mut status)?;
+        match status.split_whitespace().nth(1) {
+            Some(code) if code.starts_with('2') => Ok(()),
+            _ => Err(io::Error::other(format!(
+                "collector answered {:?}",
+                status.trim_end()
+            ))),
+        }
+    }
+}
+
+fn ship_loop(collector: Collector, lines: flume::Receiver<Value>) {
+    let mut batch = String::new();
+    let mut count = 0;
+    let mut first = Instant::now();
+    let mut failing = false;
+    loop {
+        let line = match count {
+            0 => lines.recv().ok(),
+            _ => lines.recv_deadline(first + LINGER).ok(),
+        };
+        let done = line.is_none() && lines.is_disconnected();
+        if let Some(line) = line {
+            if count == 0 {
+                first = Instant::now();
+            }
+            batch.push_str(&format!("{line}\n"));
+            count += 1;
+        }
+        let due = count >= BATCH || (count > 0 && (done || first.elapsed() >= LINGER));
+        if due {
+            match collector.post(batch.as_bytes()) {
+                Ok(()) => failing = false,
+                Err(err) => {
+                    if !failing {
+                        eprintln!("ship-logs: cannot send to {}: {err}", collector.addr);
+                    }
+                    failing = true;
+                }
+            }
+            batch.clear();
+            count = 0;
+        }
+        if done {
+            break;
+        }
+    }
+}
+
+/// Sends the lines from a background thread. Dropping it waits for the
+/// queued lines to be sent.
+pub struct Shipper {
+    source: Source,
+    lines: Option<flume::Sender<Value>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Shipper {
+    pub fn start(collector: Collector, source: Source) -> Arc<Self> {
+        let (lines, rx) = flume::bounded(QUEUE);
+        let thread = thread::spawn(move || ship_loop(collector, rx));
+        Arc::new(Self {
+            source,
+            lines: Some(lines),
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for Shipper {
+    fn drop(&mut self) {
+        self.lines.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The lines of one stream of a process.
+#[derive(Clone)]
+pub struct Stream {
+    pub shipper: Arc<Shipper>,
+    pub name: String,
+    pub pid: u32,
+    pub stream: &'static str,
+}
+
+impl Stream {
+    pub fn line(&self, line: &str) {
+        let Some(lines) = &self.shipper.lines else {
+            return;
+        };
+        let line = crate::level::strip_colors(line.trim_end_matches(['\r', '\n']));
+        let fields = serde_json::json!({
+            "pid": self.pid,
+            "stream": self.stream,
+            "line": line,
+        });
+        let _ = lines.try_send(self.shipper.source.event("line", &self.name, fields));
+    }
+}
@@ -0,0 +1,58 @@
+//! Every command name keeps its prefix color and `{index}` across the runs
+//! of a session: the first run gives each name a slot, which is remembered
+//! in a state file, and later runs reuse it even if commands were added,
+//! removed or reordered. New names get the lowest slot not taken.
+
+use std::{collections::BTreeMap, path::PathBuf, sync::Mutex};
+
+use crate::logs;
+
+struct Slots {
+    path: PathBuf,
+    names: BTreeMap<String, usize>,
+}
+
+/// Only set for sessions run from the command line, embedded runners count
+/// their jobs from the start.
+static SLOTS: Mutex<Option<Slots>> = Mutex::new(None);
+
+fn path(session: &str) -> PathBuf {
+    let root = logs::root();
+    let name = logs::file_name(session);
+    let dir = root.parent().unwrap_or(&root).join("slots");
+    dir.join(format!("{}.json", name.trim_end_matches(".log")))
+}
+
+/// Uses the slots of earlier runs of the session from now on.
+pub fn load(session: &str) {
+    let path = path(session);
+    let names = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default();
+    *SLOTS.lock().expect("lock") = Some(Slots { path, names });
+}
+
+/// The slot of `name`, counting from 0. A name without one gets the lowest
+/// free slot, which is saved right away.
+pub fn get(name: &str) -> Option<usize> {
+    let mut slots = SLOTS.lock().expect("lock");
+    let slots = slots.as_mut()?;
+    if let Some(&slot) = slots.names.get(name) {
+        return Some(slot);
+    }
+    let slot = (0..)
+        .find(|slot| !slots.names.values().any(|taken| taken == slot))
+        .expect("free slot");
+    slots.names.insert(name.to_string(), slot);
+    let json = serde_json::to_string(&slots.names).expect("serialize slots");
+    let written = slots
+        .path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|()| std::fs::write(&slots.path, format!("{json}\n")));
+    if let Err(err) = written {
+        notice!("cannot write {}: {err}", slots.path.display());
+    }
+    Some(slot)
+}
@@ -0,0 +1,161 @@
+//! `--snapshot-env` and `--replay`: a run is recorded with its command
+//! line, working directory, the whole environment and the commands it
+//! resolved to, together with the versions involved. Replaying the file
+//! starts the same run again in the same surroundings, and points out what
+//! changed since, like a config file that resolves to other commands.
+
+use std::{collections::BTreeMap, path::PathBuf, sync::OnceLock};
+
+use crate::{daemon, Job};
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Snapshot {
+    runall: String,
+    time: String,
+    os: String,
+    arch: String,
+    /// `git rev-parse HEAD` in the working directory, outside of a
+    /// repository nothing.
+    git: Option<String>,
+    cwd: PathBuf,
+    /// The command line, without `--snapshot-env`.
+    argv: Vec<String>,
+    env: BTreeMap<String, String>,
+    commands: Vec<Recorded>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, PartialEq)]
+struct Recorded {
+    name: String,
+    cmd: String,
+    cwd: Option<PathBuf>,
+    env: Vec<(String, String)>,
+}
+
+impl Recorded {
+    fn new(job: &Job) -> Self {
+        Self {
+            name: job.name.clone(),
+            cmd: job.cmd.clone(),
+            cwd: job.cwd.clone(),
+            env: job.env.clone(),
+        }
+    }
+}
+
+/// The commands of the snapshot being replayed.
+static REPLAYED: OnceLock<Vec<Recorded>> = OnceLock::new();
+
+fn head() -> Option<String> {
+    let mut git = std::process::Command::new("git");
+    git.args(["rev-parse", "HEAD"]);
+    let output = crate::reaper::output(git.stderr(std::process::Stdio::null())).ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Writes the snapshot of this run to `path`. It holds the environment as
+/// it is, secrets included, so only the user can read it.
+pub fn write(path: &std::path::Path, jobs: &[Job]) {
+    let mut argv = Vec::new();
+    let mut args = std::env::args_os().map(|arg| arg.to_string_lossy().to_string());
+    while let Some(arg) = args.next() {
+        if arg == "--snapshot-env" {
+            args.next();
+        } else if !arg.starts_with("--snapshot-env=") {
+            argv.push(arg);
+        }
+    }
+    let snapshot = Snapshot {
+        runall: env!("CARGO_PKG_VERSION").to_string(),
+        time: chrono::Local::now().to_rfc3339(),
+        os: std::env::consts::OS.to_string(),
+        arch: std::env::consts::ARCH.to_string(),
+        git: head(),
+        cwd: std::env::current_dir().expect("current directory"),
+        argv,
+        env: std::env::vars_os()
+            .map(|(key, value)| {
+                let key = key.to_string_lossy().to_string();
+                (key, value.to_string_lossy().to_string())
+            })
+            .collect(),
+        commands: jobs.iter().map(Recorded::new).collect(),
+    };
+    let json = serde_json::to_string_pretty(&snapshot).expect("serialize snapshot");
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let written = options
+        .open(path)
+        .and_then(|mut file| std::io::Write::write_all(&mut file, format!("{json}\n").as_bytes()));
+    if let Err(err) = written {
+        panic!("cannot write {}: {err}", path.display());
+    }
+}
+
+/// `--replay`: restores the environment and working directory of the
+/// snapshot in `path` and returns its command line.
+pub fn replay(path: &std::path::Path) -> Vec<std::ffi::OsString> {
+    let text = std::fs::read_to_string(path)
+        .unwrap_or_else(|err| panic!("cannot read {}: {err}", path.display()));
+    let snapshot = serde_json::from_str::<Snapshot>(&text)
+        .unwrap_or_else(|err| panic!("invalid snapshot {}: {err}", path.display()));
+    if snapshot.runall != env!("CARGO_PKG_VERSION") {
+        notice!(
+            "the snapshot was taken with runall {}, this is {}",
+            snapshot.runall,
+            env!("CARGO_PKG_VERSION")
+        );
+    }
+    std::env::set_current_dir(&snapshot.cwd)
+        .unwrap_or_else(|err| panic!("cannot change to {}: {err}", snapshot.cwd.display()));
+    let head = head();
+    if snapshot.git.is_some() && head != snapshot.git {
+        notice!(
+            "the snapshot was taken at commit {}, the checkout is at {}",
+            snapshot.git.as_deref().unwrap_or("-"),
+            head.as_deref().unwrap_or("-")
+        );
+    }
+    // the copy of runall started by --daemon replays the snapshot again
+    let detached = std::env::var_os(daemon::DETACHED);
+    for (key, _) in std::env::vars_os() {
+        std::env::remove_var(key);
+    }
+    for (key, value) in &snapshot.env {
+        std::env::set_var(key, value);
+    }
+    if let Some(detached) = detached {
+        std::env::set_var(daemon::DETACHED, detached);
+    }
+    let _ = REPLAYED.set(snapshot.commands);
+    snapshot.argv.into_iter().map(Into::into).collect()
+}
+
+/// Points out the commands that are not what they were in the snapshot
+/// being replayed.
+pub fn compare(jobs: &[Job]) {
+    let Some(recorded) = REPLAYED.get() else {
+        return;
+    };
+    for job in jobs {
+        match recorded.iter().find(|command| command.name == job.name) {
+            Some(command) if *command == Recorded::new(job) => {}
+            Some(command) => notice!(
+                "{} changed since the snapshot, it ran {:?} then",
+                job.name,
+                command.cmd
+            ),
+            None => notice!("{} was not part of the snapshot", job.name),
+        }
+    }
+    for command in recorded {
+        if !jobs.iter().any(|job| job.name == command.name) {
+            notice!("{} was part of the snapshot but is gone", command.name);
+        }
+    }
+}
@@ -0,0 +1,235 @@
+//! Splitting the output of a process into lines. Besides `\n` this handles
+//! what terminals handle:
+//!
+//! - `\r` returns to the start of the line, so progress bars overwrite
+//!   themselves. Only the latest update is kept, and shown when the line is
+//!   done, when the process pauses or at most once per [`PROGRESS`].
+//! - Output without a line break, like a `Password:` prompt, is shown once
+//!   the process wrote nothing for [`IDLE`].
+
+use std::time::{Duration, Instant};
+
+/// How long the rest of a line may take before the start is shown alone.
+pub const IDLE: Duration = Duration::from_millis(200);
+
+/// How often a progress bar that keeps updating is shown.
+pub const PROGRESS: Duration = Duration::from_secs(1);
+
+/// Longest chunk that is forwarded as one line.
+const MAX_LINE: usize = 64 * 1024;
+
+pub struct Splitter {
+    /// The line read so far.
+    buf: Vec<u8>,
+    /// The latest progress update that was not shown.
+    progress: Option<Vec<u8>>,
+    shown_progress: Instant,
+    /// The start of the line was shown, see [`Splitter::idle`].
+    partial: bool,
+}
+
+impl Default for Splitter {
+    fn default() -> Self {
+        Self {
+            buf: Vec::new(),
+            progress: None,
+            shown_progress: Instant::now(),
+            partial: false,
+        }
+    }
+}
+
+/// Appends the line break of lines that don't end in one.
+fn line(mut bytes: Vec<u8>) -> Vec<u8> {
+    bytes.push(b'\n');
+    bytes
+}
+
+impl Splitter {
+    /// Whether there is output that was not returned yet.
+    pub fn pending(&self) -> bool {
+        !self.buf.is_empty() || self.progress.is_some()
+    }
+
+    /// Adds output of the process and returns the lines it completed, each
+    /// ending with `\n`.
+    pub fn push(&mut self, data: &[u8]) -> Vec<Vec<u8>> {
+        let mut lines = Vec::new();
+        for &byte in data {
+            if self.buf.last() == Some(&b'\r') && byte != b'\n' {
+                self.buf.pop();
+                lines.extend(self.overwrite());
+            }
+            match byte {
+                b'\n' => {
+                    if self.buf.last() == Some(&b'\r') {
+                        self.buf.pop();
+                    }
+                    self.progress = None;
+                    let buf = std::mem::take(&mut self.buf);
+                    // the line break after a prompt that was already shown
+                    if !(buf.is_empty() && self.partial) {
+                        lines.push(line(buf));
+                    }
+                    self.partial = false;
+                }
+                _ => self.buf.push(byte),
+            }
+            if self.buf.len() >= MAX_LINE {
+                lines.push(line(std::mem::take(&mut self.buf)));
+            }
+        }
+        lines
+    }
+
+    /// A `\r`: the line so far is a progress update, replaced by what follows.
+    fn overwrite(&mut self) -> Option<Vec<u8>> {
+        if self.buf.is_empty() {
+            return None;
+        }
+        let update = std::mem::take(&mut self.buf);
+        if self.shown_progress.elapsed() >= PROGRESS {
+            self.shown_progress = Instant::now();
+            self.progress = None;
+            return Some(line(update));
+        }
+        self.progress = Some(update);
+        None
+    }
+
+    /// The process wrote nothing for [`IDLE`]: returns the incomplete line or
+    /// the latest progress update, if there is one.
+    pub fn idle(&mut self) -> Option<Vec<u8>> {
+        let shown = self.unfinished();
+        self.partial |= shown.is_some();
+        shown
+    }
+
+    fn unfinished(&mut self) -> Option<Vec<u8>> {
+        if self.buf.last() == Some(&b'\r') {
+            self.buf.pop();
+            if let Some(update) = self.overwrite() {
+                return Some(update);
+            }
+        }
+        if !self.buf.is_empty() {
+            // a character cut in half is completed by the next read
+            let end = self.buf.len() - incomplete_char(&self.buf);
+            if end > 0 {
+                let rest = self.buf.split_off(end);
+                return Some(line(std::mem::replace(&mut self.buf, rest)));
+            }
+        }
+        self.progress.take().map(|update| {
+            self.shown_progress = Instant::now();
+            line(update)
+        })
+    }
+
+    /// The stream ended: returns what is left of the last line.
+    pub fn finish(&mut self) -> Option<Vec<u8>> {
+        if self.buf.last() == Some(&b'\r') {
+            self.buf.pop();
+        }
+        match std::mem::take(&mut self.buf) {
+            buf if !buf.is_empty() => Some(line(buf)),
+            _ => self.progress.take().map(line),
+        }
+    }
+}
+
+/// The number of bytes at the end of `buf` that start a UTF-8 character
+/// without completing it.
+fn incomplete_char(buf: &[u8]) -> usize {
+    for back in 1..=buf.len().min(3) {
+        let byte = buf[buf.len() - back];
+        if byte & 0xc0 == 0x80 {
+            // a continuation byte, the start is further back
+            continue;
+        }
+        let width = match byte {
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => 1,
+        };
+        return if width > back { back } else { 0 };
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(lines: Vec<Vec<u8>>) -> Vec<String> {
+        lines
+            .into_iter()
+            .map(|line| String::from_utf8(line).expect("utf-8"))
+            .collect()
+    }
+
+    fn text(line: Option<Vec<u8>>) -> Option<String> {
+        line.map(|line| String::from_utf8(line).expect("utf-8"))
+    }
+
+    #[test]
+    fn splits_lines() {
+        let mut splitter = Splitter::default();
+        assert_eq!(lines(splitter.push(b"a\nb\r\nc")), ["a\n", "b\n"]);
+        assert!(splitter.pending());
+        assert_eq!(lines(splitter.push(b"d\n\n")), ["cd\n", "\n"]);
+        assert!(!splitter.pending());
+        assert_eq!(splitter.finish(), None);
+    }
+
+    #[test]
+    fn keeps_the_latest_progress_update() {
+        let mut splitter = Splitter::default();
+        assert_eq!(lines(splitter.push(b"10%\r20%\r30%\n")), ["30%\n"]);
+        assert!(splitter.push(b"40%\r50%\r").is_empty());
+        assert_eq!(text(splitter.idle()), Some("50%\n".to_string()));
+        assert_eq!(splitter.idle(), None);
+        assert!(splitter.push(b"60%\r").is_empty());
+        assert_eq!(text(splitter.finish()), Some("60%\n".to_string()));
+    }
+
+    #[test]
+    fn shows_progress_that_keeps_updating() {
+        let mut splitter = Splitter::default();
+        splitter.shown_progress -= PROGRESS;
+        assert_eq!(lines(splitter.push(b"10%\r20%\r")), ["10%\n"]);
+        assert_eq!(lines(splitter.push(b"30%\n")), ["30%\n"]);
+    }
+
+    #[test]
+    fn shows_partial_lines_when_idle() {
+        let mut splitter = Splitter::default();
+        assert!(splitter.push(b"Password: ").is_empty());
+        assert_eq!(text(splitter.idle()), Some("Password: \n".to_string()));
+        // the line break that ends the prompt is no empty line
+        assert!(splitter.push(b"\n").is_empty());
+        assert_eq!(lines(splitter.push(b"\n")), ["\n"]);
+    }
+
+    #[test]
+    fn completes_characters_cut_in_half() {
+        let mut splitter = Splitter::default();
+        assert!(splitter.push(b"caf\xc3").is_empty());
+        assert_eq!(text(splitter.idle()), Some("caf\n".to_string()));
+        assert_eq!(lines(splitter.push(b"\xa9\n")), ["é\n"]);
+        assert_eq!(incomplete_char(b"a\xe2\x82"), 2);
+        assert_eq!(incomplete_char("a€".as_bytes()), 0);
+        assert_eq!(incomplete_char(b"\xf0"), 1);
+        assert_eq!(incomplete_char(b""), 0);
+    }
+
+    #[test]
+    fn cuts_long_lines() {
+        let mut splitter = Splitter::default();
+        let cut = splitter.push(&vec![b'x'; MAX_LINE + 1]);
+        assert_eq!(cut.len(), 1);
+        assert_eq!(cut[0].len(), MAX_LINE + 1);
+        assert_eq!(text(splitter.finish()), Some("x\n".to_string()));
+    }
+}
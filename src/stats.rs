@@ -0,0 +1,62 @@
+//! `--stats-export`: one CSV row per finished process, appended to a file
+//! that accumulates across sessions.
+
+use std::{fs, path::Path, time::Duration};
+
+use chrono::{DateTime, Local};
+
+const HEADER: [&str; 7] = [
+    "name",
+    "start",
+    "duration_ms",
+    "exit_code",
+    "signal",
+    "restarts",
+    "peak_rss_kb",
+];
+
+pub struct Row<'a> {
+    pub name: &'a str,
+    pub start: DateTime<Local>,
+    pub duration: Duration,
+    pub status: std::process::ExitStatus,
+    /// Restarts of the same job before this run, not counting `--repeat` and
+    /// `--every` runs.
+    pub restarts: u32,
+    pub peak_rss_kb: Option<u64>,
+}
+
+pub fn append(path: &Path, row: &Row) {
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .unwrap_or_else(|err| panic!("open {}: {err}", path.display()));
+    let empty = file.metadata().is_ok_and(|m| m.len() == 0);
+    let mut writer = csv::Writer::from_writer(file);
+
+    #[cfg(unix)]
+    let signal = std::os::unix::process::ExitStatusExt::signal(&row.status);
+    #[cfg(not(unix))]
+    let signal = None::<i32>;
+    let optional = |value: Option<String>| value.unwrap_or_default();
+
+    let result = (|| {
+        if empty {
+            writer.write_record(HEADER)?;
+        }
+        writer.write_record([
+            row.name.to_string(),
+            row.start.to_rfc3339(),
+            row.duration.as_millis().to_string(),
+            optional(row.status.code().map(|c| c.to_string())),
+            optional(signal.map(|s| s.to_string())),
+            row.restarts.to_string(),
+            optional(row.peak_rss_kb.map(|kb| kb.to_string())),
+        ])?;
+        writer.flush().map_err(csv::Error::from)
+    })();
+    if let Err(err) = result {
+        eprintln!("error writing {}: {err}", path.display());
+    }
+}
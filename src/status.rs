@@ -0,0 +1,211 @@
+//! The status table of a session: one row per job with its state, pid,
+//! uptime, CPU time and memory, restarts, last exit code, assigned and
+//! listening ports and last line of output. And the summary at its end
+//! (`--summary`).
+
+use std::{fmt::Write, process::ExitStatus, time::Duration};
+
+use crate::{duration, runner, usage};
+
+pub struct Row<'a> {
+    pub name: &'a str,
+    pub pid: Option<u32>,
+    /// pending, lazy (not started until needed), waiting (for the next
+    /// scheduled run), backoff (waiting to be restarted), starting
+    /// (readiness probes pending), ready, not ready, running, exited or
+    /// skipped.
+    pub state: &'static str,
+    /// Time since the current run started.
+    pub uptime: Option<Duration>,
+    pub restarts: u32,
+    pub last_exit: Option<ExitStatus>,
+    /// The PORT assigned by `--port-base`.
+    pub port: Option<u16>,
+    /// TCP ports the process tree listens on.
+    pub ports: Vec<u16>,
+    /// CPU time and memory of the process tree, where known.
+    pub usage: Option<usage::Usage>,
+    pub last_line: Option<String>,
+}
+
+const HEADER: [&str; 11] = [
+    "NAME",
+    "PID",
+    "STATE",
+    "UPTIME",
+    "CPU",
+    "MEM",
+    "RESTARTS",
+    "LAST EXIT",
+    "PORT",
+    "LISTENING",
+    "LAST OUTPUT",
+];
+
+/// Longer last lines are shortened.
+const LAST_LINE: usize = 40;
+
+/// Renders `rows` as a table with aligned columns.
+pub fn table(rows: &[Row]) -> String {
+    let cells = rows
+        .iter()
+        .map(|row| {
+            [
+                row.name.to_string(),
+                row.pid.map_or("-".to_string(), |pid| pid.to_string()),
+                row.state.to_string(),
+                row.uptime.map_or("-".to_string(), duration::format),
+                row.usage
+                    .map_or("-".to_string(), |usage| duration::format(usage.cpu)),
+                row.usage
+                    .map_or("-".to_string(), |usage| usage::format_size(usage.rss)),
+                row.restarts.to_string(),
+                row.last_exit.map_or("-".to_string(), |status| {
+                    runner::exit_code(status).to_string()
+                }),
+                row.port.map_or("-".to_string(), |port| port.to_string()),
+                if row.ports.is_empty() {
+                    "-".to_string()
+                } else {
+                    row.ports
+                        .iter()
+                        .map(u16::to_string)
+                        .collect::<Vec<_>>()
+                        .join(",")
+                },
+                row.last_line
+                    .as_deref()
+                    .filter(|line| !line.is_empty())
+                    .map_or("-".to_string(), shorten),
+            ]
+        })
+        .collect::<Vec<_>>();
+    align(&HEADER, &cells)
+}
+
+/// `line` without colors and control characters, cut to [`LAST_LINE`]
+/// characters.
+fn shorten(line: &str) -> String {
+    let line = crate::level::strip_colors(line)
+        .replace('\t', " ")
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect::<String>();
+    if line.chars().count() <= LAST_LINE {
+        return line;
+    }
+    let cut = line.chars().take(LAST_LINE - 1).collect::<String>();
+    format!("{cut}…")
+}
+
+/// Renders the cells below the header with aligned columns.
+pub fn align<const N: usize>(header: &[&str; N], cells: &[[String; N]]) -> String {
+    let header = header.map(str::to_string);
+    let mut widths = [0; N];
+    for row in std::iter::once(&header).chain(cells) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut out = String::new();
+    for row in std::iter::once(&header).chain(cells) {
+        let mut line = String::new();
+        for (width, cell) in widths.iter().zip(row) {
+            let _ = write!(line, "{cell:width$}  ");
+        }
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// A row of the summary printed at the end of the session.
+pub struct Summary<'a> {
+    pub name: &'a str,
+    /// The final exit status, `None` if the job never ran to the end.
+    pub status: Option<ExitStatus>,
+    /// The time spent running, over all runs.
+    pub duration: Duration,
+    pub restarts: u32,
+    /// Lines of output, over all runs.
+    pub lines: u64,
+    /// The last run was ended by its `--timeout`.
+    pub timed_out: bool,
+    /// CPU time over all runs, where known.
+    pub cpu: Option<Duration>,
+    /// Peak memory of any run in KiB, where known.
+    pub peak_rss: Option<u64>,
+    /// How much longer than expected the last run took.
+    pub overshoot: Option<Duration>,
+    /// Hex SHA-256 of the stdout of the last run with `--checksum`.
+    pub checksum: Option<&'a str>,
+    /// The last lines of the last failed run, see `--failure-context`.
+    pub tail: &'a [String],
+}
+
+const SUMMARY_HEADER: [&str; 7] = [
+    "NAME", "EXIT", "DURATION", "CPU", "PEAK MEM", "RESTARTS", "LINES",
+];
+
+/// The summary table with a line about the session as a whole, and the
+/// checksums of `--checksum` in the format of sha256sum.
+pub fn summary(rows: &[Summary]) -> String {
+    let cells = rows
+        .iter()
+        .map(|row| {
+            [
+                row.name.to_string(),
+                match row.status {
+                    Some(_) if row.timed_out => "timed out".to_string(),
+                    Some(status) => describe(status),
+                    None => "-".to_string(),
+                },
+                match row.overshoot {
+                    Some(over) => format!(
+                        "{} (+{} over)",
+                        duration::format(row.duration),
+                        duration::format(over)
+                    ),
+                    None => duration::format(row.duration),
+                },
+                row.cpu.map_or("-".to_string(), duration::format),
+                row.peak_rss
+                    .map_or("-".to_string(), |kb| usage::format_size(kb * 1024)),
+                row.restarts.to_string(),
+                row.lines.to_string(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let succeeded = rows
+        .iter()
+        .filter(|row| row.status.is_some_and(|s| s.success()))
+        .count();
+    let failed = rows
+        .iter()
+        .filter(|row| row.status.is_some_and(|s| !s.success()))
+        .count();
+    let mut out = align(&SUMMARY_HEADER, &cells);
+    let _ = write!(out, "{succeeded} succeeded, {failed} failed");
+    if succeeded + failed < rows.len() {
+        let _ = write!(out, ", {} did not finish", rows.len() - succeeded - failed);
+    }
+    out.push('\n');
+    if rows.iter().any(|row| row.checksum.is_some()) {
+        out.push_str("stdout sha256:\n");
+        for row in rows {
+            if let Some(checksum) = row.checksum {
+                let _ = writeln!(out, "{checksum}  {}", row.name);
+            }
+        }
+    }
+    out
+}
+
+/// The exit code, or the signal that killed the process.
+fn describe(status: ExitStatus) -> String {
+    match status.code() {
+        Some(code) => code.to_string(),
+        None => status.to_string().replace(':', ""),
+    }
+}
@@ -0,0 +1,181 @@
+//! `runall tail`: follows existing log files like `tail -F`, one command
+//! per file, so that their lines get the prefixes, colors and filters of
+//! the output of commands. Every file is followed by a copy of runall
+//! (`runall tail --follow FILE`), which keeps following when the file is
+//! truncated, replaced by log rotation or doesn't exist yet.
+
+use std::{
+    collections::VecDeque,
+    fs::{File, Metadata},
+    io::{BufRead, BufReader, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{config::Entry, template::shell_quote};
+
+#[derive(clap::Args)]
+pub struct TailArgs {
+    /// The files to follow.
+    #[clap(required = true)]
+    pub files: Vec<PathBuf>,
+
+    /// Names of the files in the output, separated by commas. Defaults to
+    /// the file names without extension.
+    #[clap(long, value_delimiter = ',')]
+    pub names: Vec<String>,
+
+    /// Start with that many of the last lines of each file.
+    #[clap(short = 'n', long, default_value_t = 10)]
+    pub lines: usize,
+
+    /// Follow only the single file given, as done for every file.
+    #[clap(long, hide = true)]
+    pub follow: bool,
+}
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// One command following each of the files.
+pub fn entries(args: &TailArgs) -> Vec<(String, Entry)> {
+    if !args.names.is_empty() && args.names.len() != args.files.len() {
+        panic!(
+            "expected {} names, got {}",
+            args.files.len(),
+            args.names.len()
+        );
+    }
+    let exe = std::env::current_exe().expect("path of runall");
+    let mut entries = Vec::<(String, Entry)>::new();
+    for (i, file) in args.files.iter().enumerate() {
+        let name = match args.names.get(i) {
+            Some(name) => name.clone(),
+            None => {
+                let stem = file.file_stem().map(|s| s.to_string_lossy().to_string());
+                match stem {
+                    // two app.log in different directories keep their paths
+                    Some(stem) if !entries.iter().any(|(name, _)| *name == stem) => stem,
+                    _ => file.to_string_lossy().to_string(),
+                }
+            }
+        };
+        let cmd = format!(
+            "{} tail --follow --lines {} {}",
+            shell_quote(&exe.to_string_lossy()),
+            args.lines,
+            shell_quote(&file.to_string_lossy())
+        );
+        let entry = Entry {
+            cmd,
+            // following never ends, so it doesn't count as failing
+            kind: Some("service".to_string()),
+            start_banner: Some(false),
+            ..Default::default()
+        };
+        entries.push((name, entry));
+    }
+    entries
+}
+
+/// `--follow`: prints the last lines of `path` and then what is appended
+/// to it, until runall is stopped.
+pub fn follow(args: &TailArgs) -> ! {
+    let [path] = args.files.as_slice() else {
+        panic!("--follow takes one file");
+    };
+    let mut stdout = std::io::stdout().lock();
+    let mut missing = false;
+    let mut last_lines = Some(args.lines);
+    loop {
+        let Ok(file) = File::open(path) else {
+            if !missing {
+                eprintln!("waiting for {} to appear", path.display());
+                missing = true;
+            }
+            std::thread::sleep(POLL_INTERVAL);
+            // a file that shows up later is followed from its start
+            last_lines = None;
+            continue;
+        };
+        missing = false;
+        let mut reader = BufReader::new(file);
+        let start = match last_lines.take() {
+            Some(count) => last_lines_start(&mut reader, count),
+            None => Ok(0),
+        };
+        if let Err(err) = start.and_then(|start| reader.seek(SeekFrom::Start(start))) {
+            panic!("cannot read {}: {err}", path.display());
+        }
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            match reader.read_until(b'\n', &mut buf) {
+                Ok(0) => {}
+                Ok(_) => {
+                    if stdout
+                        .write_all(&buf)
+                        .and_then(|()| stdout.flush())
+                        .is_err()
+                    {
+                        std::process::exit(0);
+                    }
+                    continue;
+                }
+                Err(err) => panic!("cannot read {}: {err}", path.display()),
+            }
+            std::thread::sleep(POLL_INTERVAL);
+            if replaced(path, &mut reader) {
+                eprintln!("{} was replaced, following the new file", path.display());
+                break;
+            }
+        }
+    }
+}
+
+/// The offset of the last `count` lines of the file.
+fn last_lines_start(reader: &mut BufReader<File>, count: usize) -> std::io::Result<u64> {
+    let mut starts = VecDeque::with_capacity(count + 1);
+    let mut offset = 0;
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        let read = reader.read_until(b'\n', &mut buf)?;
+        if read == 0 {
+            break;
+        }
+        starts.push_back(offset);
+        if starts.len() > count {
+            starts.pop_front();
+        }
+        offset += read as u64;
+    }
+    Ok(starts.front().copied().unwrap_or(offset))
+}
+
+/// Whether `path` no longer is the file being read: it was truncated or
+/// another file took its place. While it is gone the old one is read on.
+fn replaced(path: &Path, reader: &mut BufReader<File>) -> bool {
+    let Ok(position) = reader.stream_position() else {
+        return true;
+    };
+    let Ok(current) = std::fs::metadata(path) else {
+        return false;
+    };
+    if current.len() < position {
+        return true;
+    }
+    same_file(reader.get_ref(), &current).is_some_and(|same| !same)
+}
+
+#[cfg(unix)]
+fn same_file(file: &File, current: &Metadata) -> Option<bool> {
+    use std::os::unix::fs::MetadataExt;
+    let open = file.metadata().ok()?;
+    Some(open.ino() == current.ino() && open.dev() == current.dev())
+}
+
+/// Without inodes only truncation is noticed.
+#[cfg(not(unix))]
+fn same_file(_file: &File, _current: &Metadata) -> Option<bool> {
+    None
+}
@@ -0,0 +1,323 @@
+//! Command templates used by the fan-out modes (`--each`, ...).
+
+use std::{ffi::OsStr, path::Path};
+
+/// Quotes `s` for use as a single bash word.
+pub fn shell_quote(s: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c);
+    if !s.is_empty() && s.chars().all(safe) {
+        s.to_string()
+    } else {
+        format!("'{}'", s.replace('\'', r"'\''"))
+    }
+}
+
+/// Substitutes `input` (shell quoted) for the GNU parallel style
+/// placeholders in `template`: `{}` (the input), `{.}` (without extension),
+/// `{/}` (file name), `{//}` (directory) and `{/.}` (file name without
+/// extension). Like xargs, the input is appended if the template has no
+/// placeholder.
+pub fn fill_input(template: &str, input: &str) -> String {
+    let path = Path::new(input);
+    let lossy = |p: Option<&OsStr>| p.map(|p| p.to_string_lossy().to_string());
+    let dir = match lossy(path.parent().map(|p| p.as_os_str())) {
+        Some(dir) if !dir.is_empty() => dir,
+        _ => ".".to_string(),
+    };
+    let placeholders = [
+        ("{}", input.to_string()),
+        ("{.}", path.with_extension("").to_string_lossy().to_string()),
+        ("{/}", lossy(path.file_name()).unwrap_or_default()),
+        ("{//}", dir),
+        ("{/.}", lossy(path.file_stem()).unwrap_or_default()),
+    ];
+    if !placeholders.iter().any(|(p, _)| template.contains(p)) {
+        return format!("{template} {}", shell_quote(input));
+    }
+    placeholders
+        .iter()
+        .fold(template.to_string(), |cmd, (placeholder, value)| {
+            cmd.replace(placeholder, &shell_quote(value))
+        })
+}
+
+/// Substitutes the (shell quoted) pass-through `args` for `{args}` in `cmd`,
+/// or appends them if there is no such placeholder.
+pub fn fill_args(cmd: &str, args: &[String]) -> String {
+    if args.is_empty() {
+        return cmd.to_string();
+    }
+    let quoted = args
+        .iter()
+        .map(|arg| shell_quote(arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+    if cmd.contains("{args}") {
+        cmd.replace("{args}", &quoted)
+    } else {
+        format!("{cmd} {quoted}")
+    }
+}
+
+/// Finds the first unquoted `{a,b,...}` group in `cmd` and returns its byte
+/// range and alternatives, which may contain groups themselves. `${VAR}`,
+/// escaped braces and groups containing whitespace or quotes are not brace
+/// groups.
+fn brace_group(cmd: &str) -> Option<(std::ops::Range<usize>, Vec<&str>)> {
+    let mut quote = None;
+    let mut escaped = false;
+    let mut prev = None;
+    for (i, c) in cmd.char_indices() {
+        match (quote, c) {
+            _ if escaped => escaped = false,
+            // backslashes are literal within single quotes
+            (None | Some('"'), '\\') => escaped = true,
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '{') if prev != Some('$') => {
+                if let Some(group) = group_at(cmd, i) {
+                    return Some(group);
+                }
+            }
+            _ => {}
+        }
+        prev = Some(c);
+    }
+    None
+}
+
+/// The group opened by the `{` at `start`, split at its top level commas.
+fn group_at(cmd: &str, start: usize) -> Option<(std::ops::Range<usize>, Vec<&str>)> {
+    let mut depth = 0;
+    let mut escaped = false;
+    let mut bounds = vec![start];
+    for (i, c) in cmd[start..].char_indices() {
+        let i = start + i;
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '{' => depth += 1,
+            ',' if depth == 1 => bounds.push(i),
+            '}' => {
+                depth -= 1;
+                if depth > 0 {
+                    continue;
+                }
+                if bounds.len() == 1 {
+                    return None;
+                }
+                bounds.push(i);
+                let alternatives = bounds
+                    .windows(2)
+                    .map(|pair| &cmd[pair[0] + 1..pair[1]])
+                    .collect();
+                return Some((start..i + 1, alternatives));
+            }
+            c if c.is_whitespace() || c == '\'' || c == '"' => return None,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Expands shell style brace groups like `cargo test -p {core,api}` into one
+/// command per combination, together with the alternatives chosen for it.
+pub fn expand_braces(cmd: &str) -> Vec<(String, Vec<String>)> {
+    let Some((range, alternatives)) = brace_group(cmd) else {
+        return vec![(cmd.to_string(), Vec::new())];
+    };
+    let (before, after) = (&cmd[..range.start], &cmd[range.end..]);
+    alternatives
+        .into_iter()
+        .flat_map(expand_braces)
+        .flat_map(|(alternative, _)| {
+            expand_braces(after)
+                .into_iter()
+                .map(move |(rest, mut chosen)| {
+                    chosen.insert(0, alternative.clone());
+                    (format!("{before}{alternative}{rest}"), chosen)
+                })
+        })
+        .collect()
+}
+
+/// Fills a `--name-template`: `{i}` is the number of the job (counting from
+/// 1), `{cmd}` the program the command runs and `{input}` the fan-out input.
+pub fn fill_name(template: &str, i: usize, cmd: &str, input: Option<&str>) -> String {
+    let program = cmd
+        .split_whitespace()
+        .find(|word| !word.contains('='))
+        .and_then(|word| word.rsplit('/').next())
+        .unwrap_or("");
+    template
+        .replace("{i}", &i.to_string())
+        .replace("{cmd}", program)
+        .replace("{input}", input.unwrap_or(""))
+}
+
+/// Replaces `{field}` placeholders with the (shell quoted) value returned by
+/// `lookup`. `${VAR}` is left alone so that shell variables keep working.
+pub fn fill_fields(
+    template: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<String, String> {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let (before, after) = rest.split_at(start);
+        out.push_str(before);
+        let field = after[1..].find('}').map(|end| &after[1..end + 1]);
+        match field {
+            Some(field)
+                if !out.ends_with('$')
+                    && !field.is_empty()
+                    && field
+                        .chars()
+                        .all(|c| c.is_alphanumeric() || "_-.".contains(c)) =>
+            {
+                let value =
+                    lookup(field).ok_or_else(|| format!("unknown placeholder {{{field}}}"))?;
+                out.push_str(&shell_quote(&value));
+                rest = &after[field.len() + 2..];
+            }
+            _ => {
+                out.push('{');
+                rest = &after[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expanded(cmd: &str) -> Vec<String> {
+        expand_braces(cmd).into_iter().map(|(cmd, _)| cmd).collect()
+    }
+
+    #[test]
+    fn expands_groups() {
+        assert_eq!(
+            expand_braces("cargo test -p {core,api}"),
+            [
+                ("cargo test -p core".to_string(), vec!["core".to_string()]),
+                ("cargo test -p api".to_string(), vec!["api".to_string()]),
+            ]
+        );
+        assert_eq!(expanded("{a,b} {1,2}"), ["a 1", "a 2", "b 1", "b 2"]);
+        assert_eq!(expanded("make {,clean}"), ["make ", "make clean"]);
+    }
+
+    #[test]
+    fn expands_inside_words() {
+        assert_eq!(
+            expanded("cp f.{txt,bak} /tmp"),
+            ["cp f.txt /tmp", "cp f.bak /tmp"]
+        );
+        assert_eq!(
+            expanded("mkdir -p d/{a,b}/x"),
+            ["mkdir -p d/a/x", "mkdir -p d/b/x"]
+        );
+    }
+
+    #[test]
+    fn expands_nested_groups() {
+        assert_eq!(expanded("x{a,b{1,2}}"), ["xa", "xb1", "xb2"]);
+        let chosen = expand_braces("run {a,{b,c}}")
+            .into_iter()
+            .map(|(_, chosen)| chosen.join("-"))
+            .collect::<Vec<_>>();
+        assert_eq!(chosen, ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn leaves_quoted_braces() {
+        assert_eq!(expanded("echo '{a,b}'"), ["echo '{a,b}'"]);
+        assert_eq!(expanded(r#"echo "{a,b}""#), [r#"echo "{a,b}""#]);
+        assert_eq!(
+            expanded(r#"echo "x" {a,b}"#),
+            [r#"echo "x" a"#, r#"echo "x" b"#]
+        );
+        // backslashes are literal within single quotes
+        assert_eq!(expanded(r"echo '\' {a,b}"), [r"echo '\' a", r"echo '\' b"]);
+    }
+
+    #[test]
+    fn leaves_escaped_braces() {
+        assert_eq!(expanded(r"echo \{a,b}"), [r"echo \{a,b}"]);
+        assert_eq!(expanded(r"echo {a\,b}"), [r"echo {a\,b}"]);
+        // an escaped quote doesn't start a quoted string
+        assert_eq!(
+            expanded(r#"echo \"{a,b}\""#),
+            [r#"echo \"a\""#, r#"echo \"b\""#]
+        );
+        assert_eq!(expanded(r#"echo "\"{a,b}""#), [r#"echo "\"{a,b}""#]);
+    }
+
+    #[test]
+    fn leaves_other_braces() {
+        assert_eq!(expanded("echo ${HOME}"), ["echo ${HOME}"]);
+        assert_eq!(expanded("echo ${A,B}"), ["echo ${A,B}"]);
+        assert_eq!(expanded("echo {a}"), ["echo {a}"]);
+        assert_eq!(expanded("echo {a, b}"), ["echo {a, b}"]);
+        assert_eq!(expanded("echo {a,b"), ["echo {a,b"]);
+        assert_eq!(expanded("f() { a,b; }"), ["f() { a,b; }"]);
+    }
+
+    #[test]
+    fn quotes_words() {
+        assert_eq!(shell_quote("plain-word_1.txt"), "plain-word_1.txt");
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("two words"), "'two words'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+    }
+
+    #[test]
+    fn fills_inputs() {
+        assert_eq!(fill_input("gzip", "a b.txt"), "gzip 'a b.txt'");
+        assert_eq!(fill_input("cp {} {.}.bak", "d/f.txt"), "cp d/f.txt d/f.bak");
+        assert_eq!(
+            fill_input("echo {/} {//} {/.}", "d/f.txt"),
+            "echo f.txt d f"
+        );
+        assert_eq!(fill_input("echo {//}", "f.txt"), "echo .");
+    }
+
+    #[test]
+    fn fills_args() {
+        let args = ["--release".to_string(), "a b".to_string()];
+        assert_eq!(fill_args("cargo build", &[]), "cargo build");
+        assert_eq!(
+            fill_args("cargo build", &args),
+            "cargo build --release 'a b'"
+        );
+        assert_eq!(fill_args("x {args} y", &args), "x --release 'a b' y");
+    }
+
+    #[test]
+    fn fills_names() {
+        assert_eq!(fill_name("cmd-{i}", 2, "echo hi", None), "cmd-2");
+        assert_eq!(
+            fill_name("{cmd}", 1, "FOO=1 /usr/bin/make all", None),
+            "make"
+        );
+        assert_eq!(fill_name("{input}", 1, "gzip", Some("a.txt")), "a.txt");
+    }
+
+    #[test]
+    fn fills_fields() {
+        let lookup = |field: &str| (field == "host").then(|| "a b".to_string());
+        assert_eq!(
+            fill_fields("ssh {host} ${HOME}", lookup),
+            Ok("ssh 'a b' ${HOME}".to_string())
+        );
+        assert_eq!(
+            fill_fields("echo { x }", lookup),
+            Ok("echo { x }".to_string())
+        );
+        assert!(fill_fields("echo {port}", lookup).is_err());
+    }
+}
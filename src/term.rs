@@ -0,0 +1,49 @@
+//! Size of runall's terminal, passed on to the children as `COLUMNS` and
+//! `LINES`, and waiting for a key at the end of the session (`--hold`).
+
+use std::io::Read;
+
+/// Columns and rows of the terminal on stdout, if it is one.
+#[cfg(unix)]
+pub fn size() -> Option<(u16, u16)> {
+    // SAFETY: winsize is plain data, all zeroes is a valid value
+    let mut size = unsafe { std::mem::zeroed::<libc::winsize>() };
+    // SAFETY: TIOCGWINSZ writes a winsize to the valid pointer
+    let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+    (ok && size.ws_col > 0).then_some((size.ws_col, size.ws_row))
+}
+
+#[cfg(not(unix))]
+pub fn size() -> Option<(u16, u16)> {
+    None
+}
+
+/// Waits until a key is pressed on the terminal on stdin. If stdin is no
+/// terminal, the input only arrives with the end of the line.
+#[cfg(unix)]
+pub fn wait_for_key() {
+    // SAFETY: termios is plain data, all zeroes is a valid value
+    let mut mode = unsafe { std::mem::zeroed::<libc::termios>() };
+    // SAFETY: the pointer is valid for the duration of the call
+    let terminal = unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut mode) } == 0;
+    if terminal {
+        let mut raw = mode;
+        // SAFETY: as above, cfmakeraw only changes the flags
+        unsafe {
+            libc::cfmakeraw(&mut raw);
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw);
+        }
+    }
+    let _ = std::io::stdin().read(&mut [0; 16]);
+    if terminal {
+        // SAFETY: the mode was read by tcgetattr
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &mode);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn wait_for_key() {
+    let _ = std::io::stdin().read(&mut [0; 16]);
+}
@@ -0,0 +1,74 @@
+//! `--rate-limit`: caps how many lines per second (or minute) of a process
+//! are printed. Lines over the limit are only counted and still go to the
+//! logs.
+
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimit {
+    lines: u32,
+    per: Duration,
+}
+
+impl RateLimit {
+    /// Parses `200/s` or `1000/m`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (lines, unit) = s
+            .split_once('/')
+            .ok_or_else(|| format!("expected LINES/s or LINES/m, got {s:?}"))?;
+        let lines = lines
+            .parse()
+            .ok()
+            .filter(|&n| n > 0)
+            .ok_or_else(|| format!("invalid line count in {s:?}"))?;
+        let per = match unit {
+            "s" => Duration::from_secs(1),
+            "m" => Duration::from_secs(60),
+            _ => return Err(format!("unknown unit in {s:?}, expected s or m")),
+        };
+        Ok(Self { lines, per })
+    }
+}
+
+/// Counts the lines of one process (shared by its stdout and stderr).
+pub struct Throttle {
+    limit: RateLimit,
+    window_start: Instant,
+    printed: u32,
+    dropped: u64,
+}
+
+impl Throttle {
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            window_start: Instant::now(),
+            printed: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Whether the next line may be printed. If a new window started after
+    /// lines were dropped, also returns how many.
+    pub fn admit(&mut self) -> (bool, Option<u64>) {
+        let now = Instant::now();
+        let mut dropped = None;
+        if now.duration_since(self.window_start) >= self.limit.per {
+            self.window_start = now;
+            self.printed = 0;
+            dropped = self.take_dropped();
+        }
+        if self.printed < self.limit.lines {
+            self.printed += 1;
+            (true, dropped)
+        } else {
+            self.dropped += 1;
+            (false, dropped)
+        }
+    }
+
+    pub fn take_dropped(&mut self) -> Option<u64> {
+        let dropped = std::mem::take(&mut self.dropped);
+        (dropped > 0).then_some(dropped)
+    }
+}
@@ -0,0 +1,73 @@
+//! `--triage`: after a session in a terminal in which something failed, a
+//! menu offers the next steps: read the output of the failed commands, run
+//! them or all commands again, or open the logs.
+
+use std::{
+    io::{BufRead, Write},
+    path::Path,
+    process::Command,
+};
+
+use crate::{browser, pager, rerun};
+
+/// Shows the menu until a choice ends it. Returns the exit code of the
+/// session that was started again, if one was.
+pub fn menu(session: &str, failed_output: &str, log_dir: Option<&Path>) -> Option<i32> {
+    let mut choices = Vec::new();
+    if !failed_output.is_empty() {
+        choices.push("[v]iew the failed output");
+    }
+    choices.extend(["[r]erun the failed", "rerun [a]ll"]);
+    if log_dir.is_some() {
+        choices.push("[o]pen the logs");
+    }
+    choices.push("[q]uit");
+    let exe = std::env::current_exe().expect("path of runall");
+    let mut stdin = std::io::stdin().lock();
+    loop {
+        eprint!("{}: ", choices.join(", "));
+        let _ = std::io::stderr().flush();
+        let mut choice = String::new();
+        // end of input quits
+        if stdin.read_line(&mut choice).map_or(true, |read| read == 0) {
+            eprintln!();
+            return None;
+        }
+        let mut rerun = match choice.trim() {
+            "v" if !failed_output.is_empty() => {
+                if !pager::show(failed_output) {
+                    eprint!("{failed_output}");
+                }
+                continue;
+            }
+            "o" => match log_dir {
+                Some(dir) => {
+                    if let Err(err) = browser::open(&dir.to_string_lossy()) {
+                        eprintln!("cannot open {}: {err}", dir.display());
+                    }
+                    continue;
+                }
+                None => continue,
+            },
+            "r" => {
+                let mut command = Command::new(&exe);
+                command.args(["rerun-failed", "--session", session]);
+                command
+            }
+            "a" => {
+                let mut command = Command::new(&exe);
+                command.args(rerun::argv().into_iter().skip(1));
+                command
+            }
+            "q" | "" => return None,
+            _ => continue,
+        };
+        return Some(match crate::reaper::status(&mut rerun) {
+            Ok(status) => status.code().unwrap_or(1),
+            Err(err) => {
+                eprintln!("cannot start runall again: {err}");
+                1
+            }
+        });
+    }
+}
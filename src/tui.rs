@@ -0,0 +1,328 @@
+//! `--tui`: a full screen view of the session instead of interleaved
+//! output. The overview shows the status table above the latest output of
+//! all processes; a process can be opened to scroll through its output
+//! alone, and started, restarted or stopped with a key.
+//!
+//! The output forwarders and `notice!` write into the buffers kept here, a
+//! render thread redraws the screen when its content changed and a key
+//! thread turns keystrokes into runner events.
+
+use std::{
+    collections::VecDeque,
+    io::{Read, Write},
+    sync::Mutex,
+    time::Duration,
+};
+
+use crate::{level, runner::Event};
+
+/// Lines kept per process.
+const SCROLLBACK: usize = 10_000;
+/// Lines of all processes kept for the overview.
+const RECENT: usize = 1_000;
+const FRAME: Duration = Duration::from_millis(100);
+
+static SCREEN: Mutex<Option<Screen>> = Mutex::new(None);
+
+#[derive(Default)]
+struct Screen {
+    names: Vec<String>,
+    /// Output per process.
+    panes: Vec<VecDeque<String>>,
+    /// Output of all processes and runall's own messages, newest last.
+    recent: VecDeque<String>,
+    /// The status table, header first and a line per process.
+    table: Vec<String>,
+    selected: usize,
+    /// The process whose output fills the screen, otherwise the overview.
+    open: Option<usize>,
+    /// Lines scrolled up from the end of the open pane.
+    scroll: usize,
+}
+
+fn push(lines: &mut VecDeque<String>, max: usize, line: String) {
+    if lines.len() == max {
+        lines.pop_front();
+    }
+    lines.push_back(line);
+}
+
+fn with<T>(f: impl FnOnce(&mut Screen) -> T) -> Option<T> {
+    SCREEN.lock().expect("lock").as_mut().map(f)
+}
+
+/// Whether the session is shown in the terminal UI.
+pub fn active() -> bool {
+    SCREEN.lock().expect("lock").is_some()
+}
+
+/// Switches the terminal to the UI. Keys are sent to the runner as events.
+pub fn start(names: Vec<String>, events: flume::Sender<Event>) {
+    raw_mode();
+    // alternate screen, hidden cursor
+    print!("\x1b[?1049h\x1b[?25l");
+    let _ = std::io::stdout().flush();
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        restore();
+        previous(info);
+    }));
+    *SCREEN.lock().expect("lock") = Some(Screen {
+        panes: names.iter().map(|_| VecDeque::new()).collect(),
+        names,
+        ..Default::default()
+    });
+    std::thread::spawn(render);
+    std::thread::spawn(move || read_keys(events));
+}
+
+/// Restores the terminal. Output from now on is printed as usual.
+pub fn stop() {
+    if SCREEN.lock().expect("lock").take().is_some() {
+        restore();
+    }
+}
+
+fn restore() {
+    restore_mode();
+    print!("\x1b[?25h\x1b[?1049l");
+    let _ = std::io::stdout().flush();
+}
+
+/// A line of output of process `idx`.
+pub fn line(idx: usize, text: &str) {
+    let line = plain(text);
+    with(|screen| {
+        if screen.panes.len() <= idx {
+            screen.panes.resize(idx + 1, VecDeque::new());
+        }
+        let name = screen.names.get(idx).map_or("", String::as_str);
+        push(&mut screen.recent, RECENT, format!("{name} │ {line}"));
+        push(&mut screen.panes[idx], SCROLLBACK, line);
+        if screen.open == Some(idx) && screen.scroll > 0 {
+            // keep the lines in view while scrolled up
+            screen.scroll += 1;
+        }
+    });
+}
+
+/// One of runall's messages.
+pub fn notice(message: &str) {
+    let line = plain(message);
+    with(|screen| push(&mut screen.recent, RECENT, line));
+}
+
+/// The status table, see [`crate::status::table`].
+pub fn update(table: &str) {
+    with(|screen| screen.table = table.lines().map(plain).collect());
+}
+
+/// Without colors and control characters, which would be cut in the middle
+/// when lines are shortened to the width of the screen.
+fn plain(text: &str) -> String {
+    level::strip_colors(text.trim_end_matches(['\r', '\n']))
+        .replace('\t', "    ")
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect()
+}
+
+fn render() {
+    let mut last = String::new();
+    loop {
+        let (columns, rows) = crate::term::size().unwrap_or((80, 24));
+        let Some(lines) = with(|screen| screen.lines(rows as usize)) else {
+            return;
+        };
+        let mut frame = String::new();
+        for (row, (line, highlight)) in lines.iter().enumerate() {
+            let line = line.chars().take(columns as usize).collect::<String>();
+            let (on, off) = if *highlight {
+                ("\x1b[7m", "\x1b[0m")
+            } else {
+                ("", "")
+            };
+            frame.push_str(&format!("\x1b[{};1H{on}{line}\x1b[K{off}", row + 1));
+        }
+        frame.push_str(&format!("\x1b[{};1H\x1b[J", lines.len() + 1));
+        if frame != last {
+            let mut stdout = std::io::stdout().lock();
+            let _ = stdout.write_all(frame.as_bytes());
+            let _ = stdout.flush();
+            last = frame;
+        }
+        std::thread::sleep(FRAME);
+    }
+}
+
+impl Screen {
+    /// The lines on the screen, and whether each is highlighted.
+    fn lines(&self, rows: usize) -> Vec<(String, bool)> {
+        let mut lines = Vec::new();
+        let output = match self.open {
+            Some(idx) => {
+                let state = self.table.get(idx + 1).map_or("", String::as_str);
+                lines.push((state.to_string(), true));
+                lines.push((
+                    "esc back  s start  r restart  x stop  pgup/pgdn scroll".to_string(),
+                    false,
+                ));
+                &self.panes[idx]
+            }
+            None => {
+                lines.push((
+                    "↑/↓ select  enter open  s start  r restart  x stop  q quit".to_string(),
+                    false,
+                ));
+                for (row, line) in self.table.iter().enumerate() {
+                    lines.push((line.clone(), row == self.selected + 1));
+                }
+                lines.push(("─".repeat(200), false));
+                &self.recent
+            }
+        };
+        let height = rows.saturating_sub(lines.len());
+        let end = output.len().saturating_sub(self.scroll);
+        let start = end.saturating_sub(height);
+        lines.extend(output.range(start..end).map(|line| (line.clone(), false)));
+        lines
+    }
+
+    fn jobs(&self) -> usize {
+        self.table.len().saturating_sub(1)
+    }
+
+    /// Handles a key, returns the event for the runner if it has one.
+    fn key(&mut self, key: Key) -> Option<Event> {
+        let page = crate::term::size().map_or(20, |(_, rows)| rows as usize / 2);
+        match (key, self.open) {
+            (Key::Up, None) => self.selected = self.selected.saturating_sub(1),
+            (Key::Down, None) => {
+                self.selected = (self.selected + 1).min(self.jobs().saturating_sub(1));
+            }
+            (Key::Enter, None) if self.selected < self.jobs() => {
+                self.open = Some(self.selected);
+                self.scroll = 0;
+            }
+            (Key::Escape | Key::Enter | Key::Char('q'), Some(_)) => self.open = None,
+            (Key::Up, Some(idx)) => {
+                self.scroll = (self.scroll + 1).min(self.panes[idx].len().saturating_sub(1));
+            }
+            (Key::PageUp, Some(idx)) => {
+                self.scroll = (self.scroll + page).min(self.panes[idx].len().saturating_sub(1));
+            }
+            (Key::Down, Some(_)) => self.scroll = self.scroll.saturating_sub(1),
+            (Key::PageDown, Some(_)) => self.scroll = self.scroll.saturating_sub(page),
+            (Key::Char('s'), _) if self.selected < self.jobs() => {
+                return Some(Event::Start(self.open.unwrap_or(self.selected)));
+            }
+            (Key::Char('r'), _) if self.selected < self.jobs() => {
+                return Some(Event::Restart(self.open.unwrap_or(self.selected)));
+            }
+            (Key::Char('x'), _) if self.selected < self.jobs() => {
+                return Some(Event::Stop(self.open.unwrap_or(self.selected)));
+            }
+            (Key::Char('q'), None) | (Key::Interrupt, _) => return Some(Event::Interrupt),
+            _ => {}
+        }
+        None
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Key {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Enter,
+    Escape,
+    Interrupt,
+    Char(char),
+}
+
+impl Key {
+    /// The keys in what one read from the terminal returned.
+    fn parse(mut input: &[u8]) -> Vec<Key> {
+        let mut keys = Vec::new();
+        while let Some((&byte, rest)) = input.split_first() {
+            let (key, rest) = match (byte, rest) {
+                (0x1b, [b'[', b'A', rest @ ..]) => (Some(Key::Up), rest),
+                (0x1b, [b'[', b'B', rest @ ..]) => (Some(Key::Down), rest),
+                (0x1b, [b'[', b'5', b'~', rest @ ..]) => (Some(Key::PageUp), rest),
+                (0x1b, [b'[', b'6', b'~', rest @ ..]) => (Some(Key::PageDown), rest),
+                // other sequences are skipped up to their final byte
+                (0x1b, [b'[', rest @ ..]) => {
+                    let end = rest.iter().position(|b| (0x40..=0x7e).contains(b));
+                    (None, end.map_or(&[][..], |end| &rest[end + 1..]))
+                }
+                (0x1b, rest) => (Some(Key::Escape), rest),
+                (b'\r' | b'\n', rest) => (Some(Key::Enter), rest),
+                // ctrl-c, raw mode turns off the signal
+                (0x03, rest) => (Some(Key::Interrupt), rest),
+                (b'k', rest) => (Some(Key::Up), rest),
+                (b'j', rest) => (Some(Key::Down), rest),
+                (byte, rest) => (Some(Key::Char(byte as char)), rest),
+            };
+            keys.extend(key);
+            input = rest;
+        }
+        keys
+    }
+}
+
+fn read_keys(events: flume::Sender<Event>) {
+    let mut stdin = std::io::stdin();
+    let mut buf = [0; 64];
+    loop {
+        let n = match stdin.read(&mut buf) {
+            Ok(0) | Err(_) => return,
+            Ok(n) => n,
+        };
+        for key in Key::parse(&buf[..n]) {
+            let Some(event) = with(|screen| screen.key(key)) else {
+                return;
+            };
+            if let Some(event) = event {
+                let _ = events.send(event);
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+static ORIGINAL_MODE: Mutex<Option<libc::termios>> = Mutex::new(None);
+
+#[cfg(unix)]
+fn raw_mode() {
+    // SAFETY: termios is plain data, all zeroes is a valid value
+    let mut mode = unsafe { std::mem::zeroed::<libc::termios>() };
+    // SAFETY: the pointer is valid for the duration of the call
+    if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut mode) } != 0 {
+        panic!("--tui needs a terminal");
+    }
+    *ORIGINAL_MODE.lock().expect("lock") = Some(mode);
+    // SAFETY: as above, cfmakeraw only changes the flags
+    unsafe {
+        libc::cfmakeraw(&mut mode);
+        libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &mode);
+    }
+}
+
+#[cfg(unix)]
+fn restore_mode() {
+    if let Some(mode) = ORIGINAL_MODE.lock().expect("lock").take() {
+        // SAFETY: the mode was read by tcgetattr
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &mode);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn raw_mode() {
+    panic!("--tui is only supported on unix");
+}
+
+#[cfg(not(unix))]
+fn restore_mode() {}
@@ -0,0 +1,146 @@
+//! CPU time and memory of the processes, polled from /proc and therefore
+//! only known on Linux. Used by the budget (`--session-mem-limit`), the
+//! status table and `--stats`.
+
+use std::time::{Duration, Instant};
+
+/// How often the usage is polled.
+pub const INTERVAL: Duration = Duration::from_secs(1);
+
+/// Resident memory and CPU time of a process and its descendants.
+#[derive(Clone, Copy, Default)]
+pub struct Usage {
+    pub rss: u64,
+    pub cpu: Duration,
+}
+
+/// The CPU usage of the jobs between two polls.
+#[derive(Default)]
+pub struct Meter {
+    /// CPU time per job at the last poll.
+    cpu_time: Vec<Option<(Instant, Duration)>>,
+}
+
+impl Meter {
+    /// The CPU usage of job `idx` in percent of a core since the last poll,
+    /// given its total CPU time.
+    pub fn cpu(&mut self, idx: usize, total: Duration) -> f64 {
+        if self.cpu_time.len() <= idx {
+            self.cpu_time.resize(idx + 1, None);
+        }
+        let now = Instant::now();
+        let previous = self.cpu_time[idx].replace((now, total));
+        match previous {
+            Some((at, before)) if now > at => {
+                // processes that exited take their CPU time with them
+                let used = total.saturating_sub(before);
+                used.as_secs_f64() / now.duration_since(at).as_secs_f64() * 100.0
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Forgets the CPU time of job `idx`, which stopped running.
+    pub fn forget(&mut self, idx: usize) {
+        if let Some(time) = self.cpu_time.get_mut(idx) {
+            *time = None;
+        }
+    }
+}
+
+/// A number of bytes like `1.5G`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["K", "M", "G", "T"];
+    let mut size = bytes as f64;
+    let mut unit = "";
+    for next in UNITS {
+        if size < 1024.0 {
+            break;
+        }
+        size /= 1024.0;
+        unit = next;
+    }
+    if unit.is_empty() {
+        format!("{bytes}B")
+    } else {
+        format!("{size:.1}{unit}")
+    }
+}
+
+/// The usage of `pid` and its descendants.
+#[cfg(target_os = "linux")]
+pub fn of(pid: u32) -> Option<Usage> {
+    // SAFETY: sysconf has no preconditions
+    let (ticks, page) = unsafe {
+        (
+            libc::sysconf(libc::_SC_CLK_TCK).max(1) as f64,
+            libc::sysconf(libc::_SC_PAGESIZE).max(1) as u64,
+        )
+    };
+    let mut usage = Usage::default();
+    for pid in crate::ports::descendants(pid) {
+        let Ok(stat) = std::fs::read_to_string(format!("/proc/{pid}/stat")) else {
+            continue;
+        };
+        // the command name in parentheses may contain spaces, the fields
+        // after it start with the state (field 3)
+        let Some((_, rest)) = stat.rsplit_once(") ") else {
+            continue;
+        };
+        let fields = rest.split_whitespace().collect::<Vec<_>>();
+        let field = |n: usize| {
+            fields
+                .get(n - 3)
+                .and_then(|field| field.parse::<u64>().ok())
+                .unwrap_or(0)
+        };
+        // utime, stime and the times of the children it waited for
+        let cpu = field(14) + field(15) + field(16) + field(17);
+        usage.cpu += Duration::from_secs_f64(cpu as f64 / ticks);
+        usage.rss += field(24) * page;
+    }
+    Some(usage)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn of(_pid: u32) -> Option<Usage> {
+    None
+}
+
+/// `--stats`: the usage of the running jobs at the last poll and the peak
+/// memory of their current runs.
+#[derive(Default)]
+pub struct Sampler {
+    /// When the usage is polled next.
+    pub next: Option<Instant>,
+    meter: Meter,
+    /// CPU in percent of a core and resident memory per running job.
+    pub current: Vec<Option<(f64, u64)>>,
+    /// Peak resident memory of the current run per job.
+    peak: Vec<u64>,
+}
+
+impl Sampler {
+    pub fn record(&mut self, idx: usize, usage: Usage) {
+        if self.current.len() <= idx {
+            self.current.resize(idx + 1, None);
+            self.peak.resize(idx + 1, 0);
+        }
+        self.current[idx] = Some((self.meter.cpu(idx, usage.cpu), usage.rss));
+        self.peak[idx] = self.peak[idx].max(usage.rss);
+    }
+
+    /// Job `idx` isn't running.
+    pub fn idle(&mut self, idx: usize) {
+        self.meter.forget(idx);
+        if let Some(current) = self.current.get_mut(idx) {
+            *current = None;
+        }
+    }
+
+    /// The peak memory of the run of job `idx` that ended, in bytes.
+    pub fn finish(&mut self, idx: usize) -> u64 {
+        self.idle(idx);
+        self.peak.get_mut(idx).map_or(0, std::mem::take)
+    }
+}
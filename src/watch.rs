@@ -0,0 +1,87 @@
+//! `--watch`: restarts a command when files it depends on change. The paths
+//! are polled, which needs no support from the OS and works the same on
+//! network drives and in containers.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::runner::Event;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Wait for that long without further changes before restarting, so that a
+/// checkout or a save of several files restarts the command once.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Directories below the watched paths that are not looked into: build
+/// output, dependencies and hidden directories like `.git`.
+const IGNORED: [&str; 2] = ["target", "node_modules"];
+
+/// Modification time and size of every file below the watched paths.
+type Snapshot = BTreeMap<PathBuf, (SystemTime, u64)>;
+
+/// Polls `paths` and sends [`Event::Changed`] for job `idx` whenever one of
+/// them changed. Stops once the session is over.
+pub fn watch(idx: usize, paths: Vec<PathBuf>, events: flume::Sender<Event>) {
+    std::thread::spawn(move || {
+        let mut last = snapshot(&paths);
+        loop {
+            std::thread::sleep(POLL_INTERVAL);
+            let mut current = snapshot(&paths);
+            if current == last {
+                continue;
+            }
+            loop {
+                std::thread::sleep(DEBOUNCE);
+                let settled = snapshot(&paths);
+                if settled == current {
+                    break;
+                }
+                current = settled;
+            }
+            last = current;
+            if events.send(Event::Changed(idx)).is_err() {
+                return;
+            }
+        }
+    });
+}
+
+fn snapshot(paths: &[PathBuf]) -> Snapshot {
+    let mut snapshot = Snapshot::new();
+    for path in paths {
+        scan(path, &mut snapshot);
+    }
+    snapshot
+}
+
+fn scan(path: &Path, snapshot: &mut Snapshot) {
+    // paths that don't exist (yet) have no entries, creating them is a change
+    let Ok(metadata) = path.metadata() else {
+        return;
+    };
+    if !metadata.is_dir() {
+        let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+        snapshot.insert(path.to_path_buf(), (modified, metadata.len()));
+        return;
+    }
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return;
+    };
+    for entry in entries.filter_map(Result::ok) {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        let ignored = name.starts_with('.') || IGNORED.contains(&name.as_ref());
+        // links to directories are not followed, they could form a cycle
+        if (file_type.is_dir() && ignored) || (file_type.is_symlink() && entry.path().is_dir()) {
+            continue;
+        }
+        scan(&entry.path(), snapshot);
+    }
+}
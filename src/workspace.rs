@@ -0,0 +1,321 @@
+//! Detection of npm / yarn / pnpm workspaces for `--workspaces`.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::Job;
+
+struct Package {
+    name: String,
+    dir: PathBuf,
+    scripts: HashSet<String>,
+    deps: Vec<String>,
+}
+
+/// Reads the workspace package globs, either from the `workspaces` field of
+/// `package.json` (npm, yarn) or from `pnpm-workspace.yaml`.
+fn workspace_patterns(root: &Path) -> Option<Vec<String>> {
+    if let Ok(yaml) = fs::read_to_string(root.join("pnpm-workspace.yaml")) {
+        return Some(pnpm_patterns(&yaml));
+    }
+
+    let manifest = read_manifest(&root.join("package.json"))?;
+    let workspaces = manifest.get("workspaces")?;
+    let list = workspaces.get("packages").unwrap_or(workspaces);
+    Some(
+        list.as_array()?
+            .iter()
+            .filter_map(|p| p.as_str().map(|s| s.to_string()))
+            .collect(),
+    )
+}
+
+/// pnpm-workspace.yaml is only read as far as the `packages:` list goes.
+fn pnpm_patterns(yaml: &str) -> Vec<String> {
+    let mut patterns = Vec::new();
+    let mut in_packages = false;
+    for line in yaml.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if !line.starts_with(' ') && !line.starts_with('-') {
+            in_packages = trimmed == "packages:";
+            continue;
+        }
+        if let (true, Some(item)) = (in_packages, trimmed.strip_prefix('-')) {
+            let item = item.trim().trim_matches(|c| c == '\'' || c == '"');
+            patterns.push(item.to_string());
+        }
+    }
+    patterns
+}
+
+pub fn read_manifest(path: &Path) -> Option<serde_json::Value> {
+    let content = fs::read_to_string(path).ok()?;
+    match serde_json::from_str(&content) {
+        Ok(manifest) => Some(manifest),
+        Err(err) => {
+            eprintln!("cannot parse {}: {err}", path.display());
+            None
+        }
+    }
+}
+
+fn packages(root: &Path, patterns: &[String]) -> Vec<Package> {
+    let excludes = patterns
+        .iter()
+        .filter_map(|p| p.strip_prefix('!'))
+        .filter_map(|p| glob::Pattern::new(p.trim_start_matches("./")).ok())
+        .collect::<Vec<_>>();
+
+    let mut seen = HashSet::new();
+    let mut packages = Vec::new();
+    for pattern in patterns.iter().filter(|p| !p.starts_with('!')) {
+        let full = root.join(pattern.trim_start_matches("./"));
+        let Ok(paths) = glob::glob(&full.to_string_lossy()) else {
+            eprintln!("invalid workspace pattern {pattern:?}");
+            continue;
+        };
+        for dir in paths.flatten() {
+            let rel = dir.strip_prefix(root).unwrap_or(&dir);
+            if excludes.iter().any(|p| p.matches_path(rel)) || !seen.insert(dir.clone()) {
+                continue;
+            }
+            let Some(manifest) = read_manifest(&dir.join("package.json")) else {
+                continue;
+            };
+            let keys = |field: &str| {
+                manifest
+                    .get(field)
+                    .and_then(|v| v.as_object())
+                    .map(|o| o.keys().cloned().collect::<Vec<_>>())
+                    .unwrap_or_default()
+            };
+            let name = manifest
+                .get("name")
+                .and_then(|n| n.as_str())
+                .map(|n| n.to_string())
+                .unwrap_or_else(|| rel.to_string_lossy().to_string());
+            let deps = [
+                "dependencies",
+                "devDependencies",
+                "peerDependencies",
+                "optionalDependencies",
+            ]
+            .iter()
+            .flat_map(|field| keys(field))
+            .collect();
+            packages.push(Package {
+                name,
+                scripts: keys("scripts").into_iter().collect(),
+                deps,
+                dir,
+            });
+        }
+    }
+    packages
+}
+
+/// Extracts the script name from commands like `npm run build`, `pnpm test`
+/// or `yarn run --silent lint`.
+fn script_name(cmd: &str) -> Option<&str> {
+    let mut words = cmd.split_whitespace();
+    if !matches!(words.next()?, "npm" | "pnpm" | "yarn" | "bun") {
+        return None;
+    }
+    words.find(|w| !w.starts_with('-') && *w != "run" && *w != "run-script")
+}
+
+/// Creates one job per workspace package that defines the script invoked by
+/// `cmd`. Jobs are returned in topological order and each job's `after` lists
+/// the (indices of the) jobs of the workspace packages it depends on.
+pub fn jobs(root: &Path, cmd: &str) -> Vec<Job> {
+    let Some(patterns) = workspace_patterns(root) else {
+        panic!("no npm, yarn or pnpm workspace found in {}", root.display());
+    };
+    let packages = packages(root, &patterns);
+    let script = script_name(cmd);
+    let selected = packages
+        .iter()
+        .filter(|p| script.is_none_or(|s| p.scripts.contains(s)))
+        .collect::<Vec<_>>();
+
+    if selected.is_empty() {
+        eprintln!("no workspace package defines {:?}", script.unwrap_or(cmd));
+    }
+
+    let by_name = packages
+        .iter()
+        .map(|p| (p.name.as_str(), p))
+        .collect::<HashMap<_, _>>();
+    let is_selected = |name: &str| selected.iter().any(|p| p.name == name);
+
+    // Direct dependencies on other selected packages. Packages that don't
+    // define the script are looked through so that their dependencies still
+    // determine the order.
+    let mut deps = HashMap::new();
+    for pkg in &selected {
+        let mut found = Vec::new();
+        let mut visited = HashSet::new();
+        let mut todo = pkg.deps.iter().map(|d| d.as_str()).collect::<Vec<_>>();
+        while let Some(dep) = todo.pop() {
+            if !visited.insert(dep) {
+                continue;
+            }
+            let Some(dep_pkg) = by_name.get(dep) else {
+                continue;
+            };
+            if is_selected(dep) {
+                found.push(dep);
+            } else {
+                todo.extend(dep_pkg.deps.iter().map(|d| d.as_str()));
+            }
+        }
+        deps.insert(pkg.name.as_str(), found);
+    }
+
+    let mut ordered: Vec<&Package> = Vec::new();
+    let mut remaining = selected.clone();
+    remaining.sort_by(|a, b| a.name.cmp(&b.name));
+    while !remaining.is_empty() {
+        let Some(pos) = remaining.iter().position(|p| {
+            deps[p.name.as_str()]
+                .iter()
+                .all(|d| ordered.iter().any(|o| o.name == *d))
+        }) else {
+            let names = remaining
+                .iter()
+                .map(|p| p.name.as_str())
+                .collect::<Vec<_>>();
+            panic!(
+                "dependency cycle between workspace packages {}",
+                names.join(", ")
+            );
+        };
+        ordered.push(remaining.remove(pos));
+    }
+
+    ordered
+        .iter()
+        .map(|pkg| Job {
+            cwd: Some(pkg.dir.clone()),
+            after: deps[pkg.name.as_str()]
+                .iter()
+                .map(|d| {
+                    ordered
+                        .iter()
+                        .position(|o| o.name == *d)
+                        .expect("ordered dep")
+                })
+                .collect(),
+            ..Job::new(&pkg.name, cmd)
+        })
+        .collect()
+}
+
+/// `--changed-since`: the jobs of the packages that contain one of the
+/// `changed` files (absolute paths).
+pub fn changed(jobs: Vec<Job>, changed: &[PathBuf]) -> Vec<Job> {
+    crate::retain_jobs(jobs, |job| {
+        job.cwd
+            .as_ref()
+            .and_then(|dir| dir.canonicalize().ok())
+            .is_some_and(|dir| changed.iter().any(|file| file.starts_with(&dir)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A workspace in a temporary directory: `a`, `b` needing `a`, `c`
+    /// without a build script needing `a` and `d` needing `c`.
+    fn workspace(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("runall-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let write = |path: &str, content: &str| {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().expect("parent")).expect("create dir");
+            fs::write(path, content).expect("write file");
+        };
+        write(
+            "package.json",
+            r#"{"workspaces": ["packages/*", "!packages/ignored"]}"#,
+        );
+        let build = r#""scripts": {"build": "tsc"}"#;
+        write(
+            "packages/a/package.json",
+            &format!(r#"{{"name": "a", {build}}}"#),
+        );
+        write(
+            "packages/b/package.json",
+            &format!(r#"{{"name": "b", {build}, "dependencies": {{"a": "*"}}}}"#),
+        );
+        write(
+            "packages/c/package.json",
+            r#"{"name": "c", "devDependencies": {"a": "*"}}"#,
+        );
+        write(
+            "packages/d/package.json",
+            &format!(r#"{{"name": "d", {build}, "dependencies": {{"c": "*"}}}}"#),
+        );
+        write(
+            "packages/ignored/package.json",
+            &format!(r#"{{"name": "ignored", {build}}}"#),
+        );
+        root
+    }
+
+    fn names(jobs: &[Job]) -> Vec<&str> {
+        jobs.iter().map(|job| job.name.as_str()).collect()
+    }
+
+    #[test]
+    fn orders_packages_by_dependencies() {
+        let root = workspace("workspace-order");
+        let jobs = jobs(&root, "npm run build");
+        assert_eq!(names(&jobs), ["a", "b", "d"]);
+        assert_eq!(jobs[1].after, [0]);
+        // c doesn't build, d still waits for what c depends on
+        assert_eq!(jobs[2].after, [0]);
+        assert_eq!(jobs[1].cwd, Some(root.join("packages/b")));
+        // without a script to look for every package runs
+        assert_eq!(names(&super::jobs(&root, "make")), ["a", "b", "c", "d"]);
+        fs::remove_dir_all(root).expect("remove workspace");
+    }
+
+    #[test]
+    fn keeps_changed_packages() {
+        let root = workspace("workspace-changed");
+        let dir = root.canonicalize().expect("canonical root");
+        let changed = changed(
+            jobs(&root, "npm run build"),
+            &[dir.join("packages/b/src/index.ts"), dir.join("README.md")],
+        );
+        assert_eq!(names(&changed), ["b"]);
+        assert!(changed[0].after.is_empty());
+        let none = super::changed(jobs(&root, "npm run build"), &[]);
+        assert!(none.is_empty());
+        fs::remove_dir_all(root).expect("remove workspace");
+    }
+
+    #[test]
+    fn reads_pnpm_workspaces() {
+        let yaml =
+            "# comment\npackages:\n  - 'packages/*'\n  - \"!**/test/**\"\n\ncatalog:\n  - react\n";
+        assert_eq!(pnpm_patterns(yaml), ["packages/*", "!**/test/**"]);
+    }
+
+    #[test]
+    fn finds_script_names() {
+        assert_eq!(script_name("npm run build"), Some("build"));
+        assert_eq!(script_name("yarn run --silent lint"), Some("lint"));
+        assert_eq!(script_name("pnpm test"), Some("test"));
+        assert_eq!(script_name("npm run-script dev"), Some("dev"));
+        assert_eq!(script_name("make build"), None);
+    }
+}